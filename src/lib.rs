@@ -0,0 +1,8 @@
+#[cfg(test)]
+#[macro_use]
+extern crate approx;
+extern crate csv;
+
+pub mod naivebayes;
+pub mod model;
+pub mod ml;
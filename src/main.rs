@@ -1,65 +1,1340 @@
-#[macro_use]
-extern crate approx;
-extern crate csv;
-
-mod naivebayes;
-mod model;
-mod ml;
-
-use model::gaussian_nb::GaussianNaiveBayes;
-use clap::{App, load_yaml};
-use std::string::String;
-use ml::model::Model;
+use mnist_classifier::model::{ModelKind, gaussian_nb::{GaussianNaiveBayes, GaussianParameters}};
+use mnist_classifier::model::{multinomial_nb::MultinomialNaiveBayes, bernoulli_nb::BernoulliNaiveBayes};
+use mnist_classifier::model::mixed_nb::MixedNaiveBayes;
+use mnist_classifier::ml::{model::Model, error::{DatasetError, ModelError}};
+use mnist_classifier::ml::sparse::{SparseDataset, SparseModel};
+use mnist_classifier::ml::dataset::{ColumnRef, DataFormat, Dataset, DatasetSpec};
+use mnist_classifier::ml::{npy, image::GrayImage, envelope::{self, ModelEnvelope, ModelMetadata}};
+use mnist_classifier::ml::{codegen, diff::{self, ModelDiff}, inspection::ModelSummary};
+use mnist_classifier::ml::explanation::{self, Explanation};
+use mnist_classifier::ml::importance::{self, FeatureImportance};
+use mnist_classifier::ml::selection::{FeatureSelection, SelectedModel, SelectionMethod, SelectionRule};
+use mnist_classifier::ml::{mapped::{self, MappedModel}, onnx, pmml};
+use mnist_classifier::ml::prediction::{self, Prediction};
+use mnist_classifier::ml::abstention::{self, AbstentionRule, ConfidenceMeasure};
+use mnist_classifier::ml::abstention::{CoverageReport, RiskCoveragePoint};
+use mnist_classifier::ml::calibration::{self, CalibratedModel, CalibrationMethod};
+use mnist_classifier::ml::calibration::{CalibrationReport, ReliabilityBin};
+use ndarray::{prelude::*, Array};
+use mnist_classifier::ml::validation::{self, CrossValidationReport, Fold};
+use mnist_classifier::ml::hyperparameters::{Hyperparameters, PriorMode};
+use mnist_classifier::ml::tuning::{self, SearchSpace, TrialResult};
+use clap::{App, ArgMatches, load_yaml};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{string::String, error::Error, fs, path::Path, str::FromStr};
+use std::io::{self, Write};
 
 fn run_app() -> Result<(), String> {
     let yaml = load_yaml!("cli-arguments.yaml");
     let arg_matches = App::from(yaml).get_matches();
 
-    // TODO verbosity, confusion
+    let spec: DatasetSpec = parse_dataset_spec(&arg_matches)?;
+
+    if let Some(cv_matches) = arg_matches.subcommand_matches("cv") {
+        return match parse_model_kind(cv_matches, &spec)? {
+            ModelKind::Gaussian => run_cross_validation::<GaussianNaiveBayes>(cv_matches, &spec),
+            ModelKind::Multinomial => run_cross_validation::<MultinomialNaiveBayes>(cv_matches, &spec),
+            ModelKind::Bernoulli => run_cross_validation::<BernoulliNaiveBayes>(cv_matches, &spec),
+            ModelKind::Mixed => run_cross_validation::<MixedNaiveBayes>(cv_matches, &spec)
+        };
+    }
+
+    if let Some(image_matches) = arg_matches.subcommand_matches("predict-image") {
+        return match parse_model_kind(image_matches, &spec)? {
+            ModelKind::Gaussian => run_image_prediction::<GaussianNaiveBayes>(image_matches),
+            ModelKind::Multinomial => run_image_prediction::<MultinomialNaiveBayes>(image_matches),
+            ModelKind::Bernoulli => run_image_prediction::<BernoulliNaiveBayes>(image_matches),
+            ModelKind::Mixed => run_image_prediction::<MixedNaiveBayes>(image_matches)
+        };
+    }
+
+    if let Some(tune_matches) = arg_matches.subcommand_matches("tune") {
+        return match parse_model_kind(tune_matches, &spec)? {
+            ModelKind::Gaussian => run_tuning::<GaussianNaiveBayes>(tune_matches, &spec),
+            ModelKind::Multinomial => run_tuning::<MultinomialNaiveBayes>(tune_matches, &spec),
+            ModelKind::Bernoulli => run_tuning::<BernoulliNaiveBayes>(tune_matches, &spec),
+            ModelKind::Mixed => run_tuning::<MixedNaiveBayes>(tune_matches, &spec)
+        };
+    }
+
+    if let Some(inspect_matches) = arg_matches.subcommand_matches("inspect") {
+        return match parse_model_kind(inspect_matches, &spec)? {
+            ModelKind::Gaussian => run_inspection::<GaussianNaiveBayes>(inspect_matches),
+            ModelKind::Multinomial => run_inspection::<MultinomialNaiveBayes>(inspect_matches),
+            ModelKind::Bernoulli => run_inspection::<BernoulliNaiveBayes>(inspect_matches),
+            ModelKind::Mixed => run_inspection::<MixedNaiveBayes>(inspect_matches)
+        };
+    }
+
+    if let Some(render_matches) = arg_matches.subcommand_matches("render") {
+        return match parse_model_kind(render_matches, &spec)? {
+            ModelKind::Gaussian => run_render(render_matches),
+            kind => Err(format!("Only Gaussian models can be rendered, not {}.", kind))
+        };
+    }
+
+    if let Some(diff_matches) = arg_matches.subcommand_matches("diff") {
+        return run_diff(diff_matches);
+    }
+
+    if let Some(explain_matches) = arg_matches.subcommand_matches("explain") {
+        return match parse_model_kind(explain_matches, &spec)? {
+            ModelKind::Gaussian => run_explanation::<GaussianNaiveBayes>(explain_matches, &spec),
+            ModelKind::Multinomial => run_explanation::<MultinomialNaiveBayes>(explain_matches, &spec),
+            ModelKind::Bernoulli => run_explanation::<BernoulliNaiveBayes>(explain_matches, &spec),
+            ModelKind::Mixed => run_explanation::<MixedNaiveBayes>(explain_matches, &spec)
+        };
+    }
+
+    if let Some(importance_matches) = arg_matches.subcommand_matches("importance") {
+        return match parse_model_kind(importance_matches, &spec)? {
+            ModelKind::Gaussian => run_importance::<GaussianNaiveBayes>(importance_matches, &spec,
+                |model| model.parameters().map(|parameters| importance::gaussian_divergences(&parameters))),
+            ModelKind::Multinomial => run_importance::<MultinomialNaiveBayes>(importance_matches, &spec, |_| Ok(Vec::new())),
+            ModelKind::Bernoulli => run_importance::<BernoulliNaiveBayes>(importance_matches, &spec, |_| Ok(Vec::new())),
+            ModelKind::Mixed => run_importance::<MixedNaiveBayes>(importance_matches, &spec, |_| Ok(Vec::new()))
+        };
+    }
+
+    if let Some(codegen_matches) = arg_matches.subcommand_matches("codegen") {
+        return match parse_model_kind(codegen_matches, &spec)? {
+            ModelKind::Gaussian => run_codegen(codegen_matches),
+            kind => Err(format!("Only Gaussian models can be generated as code, not {}.", kind))
+        };
+    }
 
-    let model: Box<GaussianNaiveBayes> = 
-            match (arg_matches.value_of("labels"), arg_matches.value_of("load")) {
+    // Memory-mapped models are read in place rather than loaded
+    if let Some(load_path) = arg_matches.value_of("load") {
+        if mapped::detect(load_path).map_err(|e| format!("Could not read {}: {}", load_path, e))? {
+            return run_mapped_model(&arg_matches, &spec);
+        }
+    }
+
+    match parse_model_kind(&arg_matches, &spec)? {
+        ModelKind::Gaussian => run_model::<GaussianNaiveBayes>(&arg_matches, &spec),
+        ModelKind::Multinomial => run_model::<MultinomialNaiveBayes>(&arg_matches, &spec),
+        ModelKind::Bernoulli => run_model::<BernoulliNaiveBayes>(&arg_matches, &spec),
+        ModelKind::Mixed => run_model::<MixedNaiveBayes>(&arg_matches, &spec)
+    }
+}
+
+/// Trains or loads a model of type `M`, then calibrates, saves, tests and
+/// predicts with it as the arguments ask.
+fn run_model<M>(arg_matches: &ArgMatches, spec: &DatasetSpec) -> Result<(), String>
+        where M: SparseModel + Serialize + DeserializeOwned {
+    // TODO verbosity
+
+    let (mut model, metadata): (Box<CalibratedModel<SelectedModel<M>>>, ModelMetadata) =
+            match (arg_matches.value_of("train"), arg_matches.value_of("load")) {
+        (None, None) if arg_matches.is_present("labels") => 
+            return Err(String::from("Label training path provided but no training data given.")),
         (None, None) => return Err(String::from("No model loading method provided.")),
         (Some(_), Some(_)) => return Err(String::from("Redundant model loading methods provided. Choose only one!")),
-        (Some(path), None) => {
-            let label_path: String = String::from(path);
+        (None, Some(_)) if arg_matches.is_present("labels") => 
+            return Err(String::from("Redundant model loading methods provided. Choose only one!")),
+        (Some(train_path), None) => {
+            let data: Data = Data::read(spec, train_path)?;
 
-            // Train model here since label path was provided
-            let mut untrained: GaussianNaiveBayes = 
-                GaussianNaiveBayes::from_labels(&label_path);
+            if let Some(report_path) = arg_matches.value_of("missing-report") {
+                if write_missing_report(report_path, &data.missing_counts(), data.len()).is_err() {
+                    return Err(format!("Unable to write missing value report to {}", report_path));
+                }
+            }
 
-            match arg_matches.value_of("train") {
-                Some(tp) => {
-                    let train_path: String = String::from(tp);
+            // Train model here, taking the classes from the labels file if
+            // one was provided and from the training data otherwise
+            let mut untrained: SelectedModel<M> = labelled_model(arg_matches, data.label_names());
 
-                    match untrained.train::<u8>(&train_path) {
-                        Ok(_) => println!("Model trained."),
-                        Err(_) => return Err(String::from("Model training failed"))
-                    };
-                }
-                None => return Err(String::from("Label training path provided but no training data given."))
+            if let Some(hyperparameter_path) = arg_matches.value_of("hyperparameters") {
+                let data: String = fs::read_to_string(hyperparameter_path)
+                    .map_err(|_| format!("Unable to read {}", hyperparameter_path))?;
+                let hyperparameters: Hyperparameters = serde_json::from_str(&data)
+                    .map_err(|_| String::from("Could not deserialize hyperparameters."))?;
+                untrained.set_hyperparameters(hyperparameters);
+            }
+
+            if let Some(var_smoothing) = arg_matches.value_of("var-smoothing") {
+                let mut hyperparameters: Hyperparameters = *untrained.get_hyperparameters();
+                hyperparameters.var_smoothing = var_smoothing.parse::<f64>()
+                    .map_err(|_| String::from("Variance smoothing must be a number."))?;
+                untrained.set_hyperparameters(hyperparameters);
+            }
+
+            if let Some((method, rule)) = parse_selection(arg_matches)? {
+                let dataset: &Dataset = match &data {
+                    Data::Dense(dataset) => dataset,
+                    Data::Sparse(_) => return Err(String::from("Features can only be selected from dense data."))
+                };
+                untrained.set_selection(fit_selection(dataset, method, rule)
+                    .map_err(|e| format!("Could not select features of {}: {}", train_path, e))?);
             }
-            Box::new(untrained)
+
+            let mut untrained: CalibratedModel<SelectedModel<M>> = CalibratedModel::new(untrained);
+            match data.fit(&mut untrained) {
+                Ok(_) => eprintln!("Model trained."),
+                Err(e) => return Err(format!("Model training failed: {}", e))
+            };
+
+            // The checksum covers every file the training data came from
+            let mut source_paths: Vec<String> = spec.source_paths(train_path);
+            source_paths.extend(arg_matches.value_of("labels").map(String::from));
+            let metadata: ModelMetadata = ModelMetadata::new(M::kind(), *untrained.get_hyperparameters())
+                .with_training(data.len(), &source_paths)
+                .map_err(|e| format!("Could not checksum the training data: {}", e))?;
+            let zero_based: Option<bool> = match spec.format {
+                DataFormat::Libsvm => Some(spec.zero_based),
+                _ => None
+            };
+            (Box::new(untrained), ModelMetadata { zero_based, ..metadata })
         },
         (None, Some(path)) => {
-            let model_path: String = String::from(path);
-            Box::new(GaussianNaiveBayes::from_json(&model_path))
+            let envelope: ModelEnvelope<CalibratedModel<SelectedModel<M>>> = load_model(path)?;
+            eprintln!("{}", describe_metadata(&envelope.metadata));
+            (Box::new(envelope.model), envelope.metadata)
         }
     };
+    let spec: &DatasetSpec = &model_spec(spec, &metadata, model.as_ref())?;
+
+    if let Some(calibration_path) = arg_matches.value_of("calibrate") {
+        let method: CalibrationMethod = match arg_matches.value_of("calibration") {
+            Some(name) => name.parse::<CalibrationMethod>()?,
+            None => CalibrationMethod::Platt
+        };
+        let num_bins: usize = match arg_matches.value_of("bins") {
+            Some(bins) => bins.parse::<usize>().ok().filter(|&n| n > 0)
+                .ok_or_else(|| String::from("Number of bins must be a positive integer."))?,
+            None => 10
+        };
+
+        // Recalibrate from the model's own posteriors
+        model.clear_calibration();
+        let held_out: Data = Data::read(spec, calibration_path)?;
+        let raw: Vec<Prediction> = match held_out.predict(model.as_ref()) {
+            Ok(raw) => raw,
+            Err(_) => return Err(String::from("Model calibration failed"))
+        };
+        let report: CalibrationReport = model.calibrate_predictions(&raw, method, num_bins);
+
+        print_reliability("Before calibration", &report.before);
+        print_reliability("After calibration", &report.after);
+    }
 
     if let Some(save_path) = arg_matches.value_of("save") {
-        model.to_json(&String::from(save_path));
+        // Calibration may follow loading, so record the current settings
+        let metadata: ModelMetadata = ModelMetadata {
+            hyperparameters: *model.get_hyperparameters(),
+            ..metadata
+        };
+        let saved: Result<(), Box<dyn Error>> = if mapped::is_mapped_path(save_path) {
+            model.to_mapped(save_path)
+        } else if onnx::is_onnx_path(save_path) {
+            model.to_onnx(save_path)
+        } else if pmml::is_pmml_path(save_path) {
+            model.to_pmml().and_then(|document| pmml::write_pmml(&document, save_path))
+        } else {
+            envelope::save(model.as_ref(), &metadata, save_path)
+        };
+        saved.map_err(|e| format!("Could not save model to {}: {}", save_path, e))?;
     }
 
+    let (measure, rule): (ConfidenceMeasure, Option<AbstentionRule>) = parse_abstention(arg_matches)?;
+
     if let Some(test_path) = arg_matches.value_of("test") {
-        let path: String = String::from(test_path);
+        let data: Data = Data::read(spec, test_path)?;
 
-        match model.test::<u8>(&path, true) {
-            Ok(accuracy) => println!("Model accuracy: {}", accuracy),
+        let predictions: Vec<Prediction> = match data.predict(model.as_ref()) {
+            Ok(predictions) => predictions,
             Err(_) => return Err(String::from("Model testing failed"))
         };
+
+        let confusion_matrix: Array<usize, Ix2> = 
+            prediction::confusion_matrix(&predictions, model.num_labels());
+
+        println!("Confusion Matrix:");
+        println!("{:?}\n", confusion_matrix);
+        println!("Model accuracy: {}", 
+            CalibratedModel::<SelectedModel<M>>::calculate_accuracy(&confusion_matrix));
+
+        if let Some(confusion_path) = arg_matches.value_of("confusion") {
+            if write_confusion_matrix(confusion_path, &confusion_matrix).is_err() {
+                return Err(format!("Unable to write confusion matrix to {}", confusion_path));
+            }
+        }
+
+        if let Some(rule) = rule.as_ref() {
+            let report: CoverageReport = abstention::coverage_report(&predictions, rule);
+
+            println!("Coverage: {} ({} of {} samples classified)", 
+                report.coverage(), report.covered, report.total);
+            println!("Selective accuracy: {}", report.accuracy());
+        }
+
+        if let Some(curve_path) = arg_matches.value_of("risk-coverage") {
+            let curve: Vec<RiskCoveragePoint> = 
+                abstention::risk_coverage_curve(&predictions, measure);
+
+            if write_risk_coverage_curve(curve_path, &curve).is_err() {
+                return Err(format!("Unable to write risk-coverage curve to {}", curve_path));
+            }
+        }
+    }
+
+    if let Some(predict_path) = arg_matches.value_of("predict") {
+        let posteriors: Vec<Vec<f64>> = read_posteriors(spec, predict_path, model.as_ref())?;
+
+        write_prediction_outputs(arg_matches, &model.label_names(), &posteriors, rule.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Tests and predicts with a memory-mapped Gaussian model. It is read in
+/// place rather than loaded, so it cannot be retrained, calibrated or saved.
+fn run_mapped_model(arg_matches: &ArgMatches, spec: &DatasetSpec) -> Result<(), String> {
+    for name in ["train", "labels", "calibrate", "save"].iter() {
+        if arg_matches.is_present(name) {
+            return Err(format!("A memory-mapped model cannot be used with --{}.", name));
+        }
+    }
+
+    let load_path: &str = arg_matches.value_of("load").unwrap();
+    eprint!("Mapping model from {}...", load_path);
+    let model: MappedModel = MappedModel::open(load_path)
+        .map_err(|e| format!("Could not load model from {}: {}", load_path, e))?;
+    eprintln!("done.");
+
+    // LIBSVM rows expand to the model's width
+    let spec: &DatasetSpec = &DatasetSpec {
+        num_features: spec.num_features.or(Some(model.num_features())),
+        ..spec.clone()
+    };
+    let (_, rule): (ConfidenceMeasure, Option<AbstentionRule>) = parse_abstention(arg_matches)?;
+
+    if let Some(test_path) = arg_matches.value_of("test") {
+        let dataset: Dataset = read_dataset(spec, test_path)?;
+        let mapping: Vec<usize> = dataset.label_mapping(&model.label_names())
+            .map_err(|_| String::from("Model testing failed"))?;
+
+        eprintln!("Testing model.");
+        let predictions: Vec<Prediction> = dataset.iter()
+            .map(|(actual, sample)| Ok(Prediction::new(mapping[actual], model.posteriors(sample)?)))
+            .collect::<Result<Vec<Prediction>, ModelError>>()
+            .map_err(|e| format!("Model testing failed: {}", e))?;
+
+        let confusion_matrix: Array<usize, Ix2> =
+            prediction::confusion_matrix(&predictions, model.num_labels());
+
+        println!("Confusion Matrix:");
+        println!("{:?}\n", confusion_matrix);
+        println!("Model accuracy: {}", GaussianNaiveBayes::calculate_accuracy(&confusion_matrix));
+
+        if let Some(confusion_path) = arg_matches.value_of("confusion") {
+            if write_confusion_matrix(confusion_path, &confusion_matrix).is_err() {
+                return Err(format!("Unable to write confusion matrix to {}", confusion_path));
+            }
+        }
+    }
+
+    if let Some(predict_path) = arg_matches.value_of("predict") {
+        let posteriors: Vec<Vec<f64>> = spec.read_samples::<f64>(predict_path)
+            .map_err(|e| format!("Could not read {}: {}", predict_path, e))?.iter()
+            .map(|sample| model.posteriors(sample))
+            .collect::<Result<Vec<Vec<f64>>, ModelError>>()
+            .map_err(|e| format!("Model prediction failed: {}", e))?;
+
+        write_prediction_outputs(arg_matches, &model.label_names(), &posteriors, rule.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// The confidence measure and, when a threshold is given, the rule used to
+/// abstain from low-confidence predictions.
+fn parse_abstention(arg_matches: &ArgMatches)
+        -> Result<(ConfidenceMeasure, Option<AbstentionRule>), String> {
+    let measure: ConfidenceMeasure = match arg_matches.value_of("abstain") {
+        Some(name) => name.parse::<ConfidenceMeasure>()?,
+        None => ConfidenceMeasure::MaxPosterior
+    };
+    let rule: Option<AbstentionRule> = match arg_matches.value_of("threshold") {
+        Some(threshold) => Some(AbstentionRule::new(measure, threshold.parse::<f64>()
+            .map_err(|_| String::from("Abstention threshold must be a number."))?)),
+        None => None
+    };
+
+    Ok((measure, rule))
+}
+
+/// Saves the posteriors when asked and writes one prediction per row.
+fn write_prediction_outputs(arg_matches: &ArgMatches, label_names: &[String],
+        posteriors: &[Vec<f64>], rule: Option<&AbstentionRule>) -> Result<(), String> {
+    if let Some(probabilities_path) = arg_matches.value_of("probabilities") {
+        let flattened: Vec<f64> = posteriors.iter().flatten().copied().collect::<Vec<f64>>();
+        let probabilities: Array<f64, Ix2> =
+            Array::from_shape_vec((posteriors.len(), label_names.len()), flattened)
+                .map_err(|_| String::from("Posteriors do not match the model's classes."))?;
+
+        if npy::write_npy(probabilities_path, &probabilities).is_err() {
+            return Err(format!("Unable to write probabilities to {}", probabilities_path));
+        }
+    }
+
+    let output: Option<&str> = arg_matches.value_of("output");
+    if write_predictions(output, label_names, posteriors, rule).is_err() {
+        return Err(String::from("Model prediction failed"));
+    }
+
+    Ok(())
+}
+
+/// Classifies each image with a saved model, after bringing it to the size
+/// and layout of the training images.
+fn run_image_prediction<M>(arg_matches: &ArgMatches) -> Result<(), String>
+        where M: Model + Serialize + DeserializeOwned {
+    let model: CalibratedModel<SelectedModel<M>> = load_model(arg_matches.value_of("load").unwrap())?.model;
+
+    let (width, height): (usize, usize) = parse_size(arg_matches)?;
+
+    for image_path in arg_matches.values_of("images").unwrap() {
+        let image: GrayImage = read_image(arg_matches, image_path, width, height)?;
+
+        let posteriors: Vec<f64> = model.posteriors(&image.to_features())
+            .map_err(|e| format!("Could not classify {}: {}", image_path, e))?;
+        let label = model.label(prediction::argmax(&posteriors))
+            .map_err(|e| format!("Could not classify {}: {}", image_path, e))?;
+
+        println!("{}: {}", image_path, label.get_name());
+        for (name, probability) in model.label_names().iter().zip(posteriors.iter()) {
+            println!("\t{}\t{:.4}", name, probability);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads an image and prepares it like the training digits, unless told
+/// not to center it.
+fn read_image(arg_matches: &ArgMatches, image_path: &str, width: usize, height: usize)
+        -> Result<GrayImage, String> {
+    let mut image: GrayImage = GrayImage::from_file(image_path)
+        .map_err(|e| format!("Could not read {}: {}", image_path, e))?;
+
+    if arg_matches.is_present("invert") {
+        image = image.invert();
+    }
+
+    Ok(if arg_matches.is_present("no-center") {
+        image.resize(width, height)
+    } else {
+        image.fit_to(width, height)
+    })
+}
+
+/// The WIDTHxHEIGHT of images, by default that of MNIST digits.
+fn parse_size(arg_matches: &ArgMatches) -> Result<(usize, usize), String> {
+    match arg_matches.value_of("size") {
+        Some(size) => size.split_once('x')
+            .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)))
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .ok_or_else(|| format!("Invalid image size '{}', expected WIDTHxHEIGHT.", size)),
+        None => Ok((28, 28))
+    }
+}
+
+fn parse_dataset_spec(arg_matches: &ArgMatches) -> Result<DatasetSpec, String> {
+    let mut spec: DatasetSpec = DatasetSpec {
+        has_header: !arg_matches.is_present("no-header"),
+        quoting: !arg_matches.is_present("no-quoting"),
+        zero_based: arg_matches.is_present("zero-based"),
+        ..DatasetSpec::default()
+    };
+
+    if let Some(num_features) = arg_matches.value_of("num-features") {
+        spec.num_features = Some(num_features.parse::<usize>()
+            .map_err(|_| String::from("Number of features must be a non-negative integer."))?);
+    }
+
+    if let Some(format) = arg_matches.value_of("format") {
+        spec.format = format.parse::<DataFormat>()?;
+    }
+
+    if let Some(column) = arg_matches.value_of("label-column") {
+        spec.label = match column {
+            "none" => None,
+            column => Some(column.parse::<ColumnRef>()?)
+        };
+    } else if spec.format == DataFormat::Arff {
+        // ARFF files default to their last attribute
+        spec.label = None;
+    }
+
+    if let Some(delimiter) = arg_matches.value_of("delimiter") {
+        spec.delimiter = match delimiter {
+            "tab" | "\\t" => b'\t',
+            d if d.len() == 1 => d.as_bytes()[0],
+            _ => return Err(String::from("Delimiter must be a single character or 'tab'."))
+        };
+    }
+
+    if let Some(comment) = arg_matches.value_of("comment") {
+        if comment.len() != 1 {
+            return Err(String::from("Comment marker must be a single character."));
+        }
+        spec.comment = Some(comment.as_bytes()[0]);
+    }
+
+    if let Some(columns) = parse_list::<ColumnRef>(arg_matches, "ignore-columns")? {
+        spec.ignore = columns;
+    }
+
+    if let Some(tokens) = parse_list::<String>(arg_matches, "missing")? {
+        spec.missing = tokens;
+    }
+
+    Ok(spec)
+}
+
+/// The model named on the command line. ARFF data declares its feature
+/// types, so it defaults to the mixed model; everything else to Gaussian.
+fn parse_model_kind(arg_matches: &ArgMatches, spec: &DatasetSpec) -> Result<ModelKind, String> {
+    // Saved models record the kind they hold, except bare legacy JSON ones
+    let saved: Option<ModelKind> = match arg_matches.value_of("load") {
+        Some(path) => envelope::detect_kind(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))?,
+        None => None
+    };
+
+    match (arg_matches.value_of("model"), saved) {
+        (Some(kind), Some(saved)) if kind.parse::<ModelKind>()? != saved =>
+            Err(format!("The saved model is a {} model, not {}.", saved, kind)),
+        (Some(kind), _) => kind.parse::<ModelKind>(),
+        (None, Some(saved)) => Ok(saved),
+        (None, None) if spec.format == DataFormat::Arff => Ok(ModelKind::Mixed),
+        (None, None) => Ok(ModelKind::Gaussian)
+    }
+}
+
+/// Loads a JSON or binary model with its metadata, migrating models saved
+/// by older versions. PMML models are imported with no training metadata.
+/// Memory-mapped models keep only what classifying needs, so they cannot
+/// be loaded in full.
+fn load_model<M: Model + DeserializeOwned>(path: &str) -> Result<ModelEnvelope<M>, String> {
+    let message = |e: Box<dyn Error>| format!("Could not load model from {}: {}", path, e);
+
+    if mapped::detect(path).map_err(message)? {
+        return Err(format!("{} is a memory-mapped model, which can only be tested and predicted with.", path));
+    }
+
+    if pmml::is_pmml_path(path) {
+        let model: M = pmml::read_pmml(path).and_then(|document| M::from_pmml(&document))
+            .map_err(message)?;
+        let metadata: ModelMetadata = ModelMetadata {
+            created: None,
+            ..ModelMetadata::new(M::kind(), *model.get_hyperparameters())
+        };
+        return Ok(ModelEnvelope { metadata, model });
+    }
+
+    envelope::load::<M>(path, M::kind()).map_err(message)
+}
+
+/// The feature selection method and rule the arguments ask for, if any.
+fn parse_selection(arg_matches: &ArgMatches) -> Result<Option<(SelectionMethod, SelectionRule)>, String> {
+    match arg_matches.value_of("select") {
+        Some(method) => Ok(Some((method.parse::<SelectionMethod>()?, parse_selection_rule(arg_matches)?))),
+        None => Ok(None)
+    }
+}
+
+/// Selects the features of the training rows, which must keep at least one.
+fn fit_selection(dataset: &Dataset, method: SelectionMethod, rule: SelectionRule)
+        -> Result<FeatureSelection, String> {
+    let selection: FeatureSelection = FeatureSelection::fit(dataset, method, rule)
+        .map_err(|e| e.to_string())?;
+
+    if selection.features.is_empty() {
+        return Err(String::from("No features were selected."));
+    }
+    eprintln!("Selected {} of {} features by {}.", selection.features.len(),
+        selection.num_features, selection.method);
+    Ok(selection)
+}
+
+/// Wraps a fresh model for one fold, restricted to the features selected
+/// from the fold's training rows when selection was asked for.
+fn fold_model<M>(model: M, selection: Option<(SelectionMethod, SelectionRule)>, train: &Dataset)
+        -> Result<SelectedModel<M>, Box<dyn Error>>
+        where M: Model + Serialize + DeserializeOwned {
+    let mut model: SelectedModel<M> = SelectedModel::new(model);
+    if let Some((method, rule)) = selection {
+        model.set_selection(fit_selection(train, method, rule)?);
+    }
+
+    Ok(model)
+}
+
+fn parse_selection_rule(arg_matches: &ArgMatches) -> Result<SelectionRule, String> {
+    match (arg_matches.value_of("select-top"), arg_matches.value_of("select-threshold")) {
+        (Some(k), _) => k.parse::<usize>().ok().filter(|&k| k > 0).map(SelectionRule::Top)
+            .ok_or_else(|| String::from("Number of selected features must be a positive integer.")),
+        (None, Some(threshold)) => threshold.parse::<f64>().map(SelectionRule::Threshold)
+            .map_err(|_| String::from("Selection threshold must be a number.")),
+        (None, None) => Err(String::from("Feature selection needs --select-top or --select-threshold."))
+    }
+}
+
+/// Where a loaded model came from, as far as it is known.
+fn describe_metadata(metadata: &ModelMetadata) -> String {
+    let unknown: String = String::from("unknown");
+    format!("{} model (schema {}), created {}, trained on {} rows, source {}",
+        metadata.kind, metadata.schema_version,
+        metadata.created.as_ref().unwrap_or(&unknown),
+        metadata.training_rows.map_or(unknown.clone(), |rows| rows.to_string()),
+        metadata.source_checksum.as_ref().unwrap_or(&unknown))
+}
+
+/// The spec for data a model scores, which numbers LIBSVM features the way
+/// the model's training data did and, unless `--num-features` was given,
+/// expands LIBSVM rows to the model's width.
+fn model_spec<M: Model>(spec: &DatasetSpec, metadata: &ModelMetadata, model: &M) -> Result<DatasetSpec, String> {
+    let mut spec: DatasetSpec = match metadata.zero_based {
+        Some(false) if spec.zero_based =>
+            return Err(String::from("The model was trained on LIBSVM data with features numbered from 1.")),
+        Some(zero_based) => DatasetSpec { zero_based, ..spec.clone() },
+        None => spec.clone()
+    };
+
+    if spec.format == DataFormat::Libsvm && spec.num_features.is_none() {
+        let summary: ModelSummary = model.summary().map_err(|e| format!("Could not read the model: {}", e))?;
+        spec.num_features = Some(summary.num_features);
+    }
+
+    Ok(spec)
+}
+
+/// An untrained model for the classes in the labels file, or for the
+/// given classes found in the data when no labels file is given.
+fn labelled_model<M: Model>(arg_matches: &ArgMatches, label_names: &[String]) -> M {
+    match arg_matches.value_of("labels") {
+        Some(label_path) => M::from_labels(label_path),
+        None => M::from_label_names(label_names)
+    }
+}
+
+fn read_dataset(spec: &DatasetSpec, file_path: &str) -> Result<Dataset, String> {
+    spec.read::<f64>(file_path).map_err(|e| format!("Could not read {}: {}", file_path, e))
+}
+
+/// Labelled data for training or testing, kept sparse when the data format
+/// is sparse so rows are never expanded.
+enum Data {
+    Dense(Dataset),
+    Sparse(SparseDataset)
+}
+
+impl Data {
+    fn read(spec: &DatasetSpec, file_path: &str) -> Result<Data, String> {
+        match spec.format {
+            DataFormat::Libsvm => spec.read_sparse(file_path)
+                .map(Data::Sparse)
+                .map_err(|e| format!("Could not read {}: {}", file_path, e)),
+            _ => read_dataset(spec, file_path).map(Data::Dense)
+        }
+    }
+
+    fn label_names(&self) -> &[String] {
+        match self {
+            Data::Dense(dataset) => dataset.get_label_names(),
+            Data::Sparse(dataset) => dataset.get_label_names()
+        }
+    }
+
+    fn fit<M: SparseModel>(&self, model: &mut M) -> Result<(), Box<dyn Error>> {
+        match self {
+            Data::Dense(dataset) => model.fit(dataset),
+            Data::Sparse(dataset) => model.fit_sparse(dataset)
+        }
+    }
+
+    fn predict<M: SparseModel>(&self, model: &M) -> Result<Vec<Prediction>, ModelError> {
+        match self {
+            Data::Dense(dataset) => model.predict(dataset, true),
+            Data::Sparse(dataset) => model.predict_sparse(dataset)
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Data::Dense(dataset) => dataset.len(),
+            Data::Sparse(dataset) => dataset.len()
+        }
+    }
+
+    /// The number of missing (NaN) values of each feature. Sparse rows can
+    /// only be missing the values they store.
+    fn missing_counts(&self) -> Vec<usize> {
+        match self {
+            Data::Dense(dataset) => dataset.missing_counts(),
+            Data::Sparse(dataset) => {
+                let mut counts: Vec<usize> = vec![0; dataset.num_features()];
+                for row in dataset.get_rows() {
+                    for (idx, value) in row.iter() {
+                        if value.is_nan() {
+                            counts[idx] += 1;
+                        }
+                    }
+                }
+                counts
+            }
+        }
+    }
+}
+
+/// Writes the missing count and rate of every feature as CSV and prints
+/// how much of the data is missing overall.
+fn write_missing_report(path: &str, counts: &[usize], num_rows: usize) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    wtr.write_record(["feature", "missing", "rate"])?;
+
+    for (feature, &count) in counts.iter().enumerate() {
+        let rate: f64 = if num_rows == 0 { 0.0 } else { count as f64 / num_rows as f64 };
+        wtr.write_record(&[feature.to_string(), count.to_string(), rate.to_string()])?;
+    }
+    wtr.flush()?;
+
+    let total: usize = counts.iter().sum();
+    println!("{} of {} features have missing values ({} of {} values missing).",
+        counts.iter().filter(|&&count| count > 0).count(), counts.len(),
+        total, counts.len() * num_rows);
+    Ok(())
+}
+
+/// Posteriors for every sample in an unlabelled data file.
+fn read_posteriors<M: SparseModel>(spec: &DatasetSpec, file_path: &str, model: &M)
+        -> Result<Vec<Vec<f64>>, String> {
+    let message = |e: &dyn std::fmt::Display| format!("Could not read {}: {}", file_path, e);
+
+    let posteriors: Result<Vec<Vec<f64>>, ModelError> = match spec.format {
+        DataFormat::Libsvm => {
+            let dataset: SparseDataset = SparseDataset::from_libsvm(file_path, spec.zero_based)
+                .map_err(|e| message(&e))?;
+            model.sparse_posteriors(dataset.get_rows())
+        },
+        _ => spec.read_samples::<f64>(file_path).map_err(|e| message(&e))?.iter()
+            .map(|sample| model.posteriors(sample))
+            .collect::<Result<Vec<Vec<f64>>, ModelError>>()
+    };
+
+    posteriors.map_err(|e| format!("Model prediction failed: {}", e))
+}
+
+fn write_predictions(output: Option<&str>, label_names: &[String], posteriors: &[Vec<f64>],
+        rule: Option<&AbstentionRule>) -> Result<(), Box<dyn Error>> {
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(io::stdout())
+    };
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(["row", "prediction", "confidence"])?;
+
+    for (row, posteriors) in posteriors.iter().enumerate() {
+        let best: usize = prediction::argmax(posteriors);
+
+        let predicted: String = match rule {
+            Some(rule) if rule.abstains(posteriors) => String::from("abstain"),
+            _ => label_names.get(best).ok_or(ModelError::LabelNotFound)?.clone()
+        };
+
+        wtr.write_record(&[row.to_string(), predicted, posteriors[best].to_string()])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes the confusion matrix as a NumPy array for `.npy` paths and as
+/// CSV rows otherwise.
+fn write_confusion_matrix(path: &str, confusion_matrix: &Array<usize, Ix2>)
+        -> Result<(), Box<dyn Error>> {
+    if path.ends_with(".npy") {
+        return npy::write_npy(path, confusion_matrix);
+    }
+
+    let mut wtr = csv::Writer::from_path(path)?;
+    for row in confusion_matrix.outer_iter() {
+        wtr.write_record(row.iter().map(|count| count.to_string()))?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+fn parse_seed(arg_matches: &ArgMatches) -> Result<Option<u64>, String> {
+    match arg_matches.value_of("seed") {
+        Some(seed) => seed.parse::<u64>().map(Some)
+            .map_err(|_| String::from("Seed must be a non-negative integer.")),
+        None => Ok(None)
+    }
+}
+
+/// How many of the highest ranked entries to list, if `--top` was given.
+fn parse_top(arg_matches: &ArgMatches) -> Result<Option<usize>, String> {
+    match arg_matches.value_of("top") {
+        Some(n) => n.parse::<usize>().map(Some)
+            .map_err(|_| String::from("Top must be a non-negative integer.")),
+        None => Ok(None)
+    }
+}
+
+/// Writes `value` as pretty-printed JSON, naming it as `description` in
+/// any error.
+fn write_json<T: Serialize>(json_path: &str, value: &T, description: &str) -> Result<(), String> {
+    let data: String = serde_json::to_string_pretty(value)
+        .map_err(|_| format!("Could not serialize the {}.", description))?;
+    fs::write(json_path, data)
+        .map_err(|_| format!("Unable to write {} to {}", description, json_path))
+}
+
+fn build_folds(arg_matches: &ArgMatches, dataset: &Dataset) -> Result<Vec<Fold>, String> {
+    let k: usize = match arg_matches.value_of("folds") {
+        Some(folds) => folds.parse::<usize>()
+            .map_err(|_| String::from("Number of folds must be a positive integer."))?,
+        None => 5
+    };
+    let seed: Option<u64> = parse_seed(arg_matches)?;
+
+    if k < 2 || k > dataset.len() {
+        return Err(format!("Number of folds must be between 2 and {}.", dataset.len()));
+    }
+
+    Ok(if arg_matches.is_present("stratified") {
+        validation::stratified_k_fold(dataset.get_labels(), k, seed)
+    } else {
+        validation::k_fold(dataset.len(), k, seed)
+    })
+}
+
+/// Parses a comma-separated option into its values, if it was given.
+fn parse_list<T: FromStr>(arg_matches: &ArgMatches, name: &str) 
+        -> Result<Option<Vec<T>>, String> {
+    match arg_matches.value_of(name) {
+        Some(list) => list.split(',')
+            .map(|item| item.trim().parse::<T>()
+                .map_err(|_| format!("Invalid value '{}' for --{}.", item, name)))
+            .collect::<Result<Vec<T>, String>>()
+            .map(Some),
+        None => Ok(None)
+    }
+}
+
+fn run_cross_validation<M>(arg_matches: &ArgMatches, spec: &DatasetSpec) -> Result<(), String>
+        where M: Model + Serialize + DeserializeOwned + Clone {
+    let data_path: &str = arg_matches.value_of("data").unwrap();
+
+    let dataset: Dataset = read_dataset(spec, data_path)?;
+    let folds: Vec<Fold> = build_folds(arg_matches, &dataset)?;
+
+    let selection: Option<(SelectionMethod, SelectionRule)> = parse_selection(arg_matches)?;
+
+    let template: M = labelled_model(arg_matches, dataset.get_label_names());
+    let report: CrossValidationReport = 
+        match validation::cross_validate(&dataset, &folds, |train| fold_model(template.clone(), selection, train)) {
+            Ok(report) => report,
+            Err(e) => return Err(format!("Cross-validation failed: {}", e))
+        };
+
+    for (idx, fold) in report.folds.iter().enumerate() {
+        println!("Fold {} accuracy: {}", idx + 1, fold.accuracy);
+    }
+
+    let (mean, std): (f64, f64) = report.accuracy();
+    println!("Accuracy: {:.4} +/- {:.4}\n", mean, std);
+
+    println!("class\tprecision\t\trecall\t\t\tf1");
+    for (class, summary) in template.label_names().iter().zip(report.class_summary().iter()) {
+        println!("{}\t{:.4} +/- {:.4}\t{:.4} +/- {:.4}\t{:.4} +/- {:.4}", class, 
+            summary[0].0, summary[0].1, summary[1].0, summary[1].1, 
+            summary[2].0, summary[2].1);
+    }
+
+    println!("\nAggregated Confusion Matrix:");
+    println!("{:?}", report.confusion_matrix);
+
+    Ok(())
+}
+
+fn run_tuning<M>(arg_matches: &ArgMatches, spec: &DatasetSpec) -> Result<(), String>
+        where M: Model + Serialize + DeserializeOwned + Clone {
+    let data_path: &str = arg_matches.value_of("data").unwrap();
+
+    let mut space: SearchSpace = SearchSpace::default();
+    if let Some(values) = parse_list::<f64>(arg_matches, "min-std")? {
+        space.min_std = values;
+    }
+    if let Some(values) = parse_list::<f64>(arg_matches, "var-smoothing")? {
+        space.var_smoothing = values;
+    }
+    if let Some(values) = parse_list::<f64>(arg_matches, "alpha")? {
+        space.alpha = values;
+    }
+    if let Some(values) = parse_list::<f64>(arg_matches, "smoothing")? {
+        space.smoothing = values;
+    }
+    if let Some(values) = parse_list::<String>(arg_matches, "binarize")? {
+        space.binarize = values.iter().map(|value| match value.as_str() {
+            "none" => Ok(None),
+            threshold => threshold.parse::<f64>().map(Some)
+                .map_err(|_| format!("Invalid value '{}' for --binarize.", threshold))
+        }).collect::<Result<Vec<Option<f64>>, String>>()?;
+    }
+    if let Some(values) = parse_list::<PriorMode>(arg_matches, "prior")? {
+        space.prior = values;
+    }
+
+    let candidates: Vec<Hyperparameters> = match arg_matches.value_of("search") {
+        Some("random") => {
+            let iterations: usize = match arg_matches.value_of("iterations") {
+                Some(n) => n.parse::<usize>()
+                    .map_err(|_| String::from("Iterations must be a positive integer."))?,
+                None => 10
+            };
+            space.sample(iterations, parse_seed(arg_matches)?)
+        },
+        _ => space.grid()
+    };
+
+    let dataset: Dataset = read_dataset(spec, data_path)?;
+    let folds: Vec<Fold> = build_folds(arg_matches, &dataset)?;
+
+    let selection: Option<(SelectionMethod, SelectionRule)> = parse_selection(arg_matches)?;
+
+    let template: M = labelled_model(arg_matches, dataset.get_label_names());
+    let results: Vec<TrialResult> = 
+        match tuning::search(&dataset, &folds, &candidates, |hyperparameters, train| {
+            let mut model: M = template.clone();
+            model.set_hyperparameters(*hyperparameters);
+            fold_model(model, selection, train)
+        }) {
+            Ok(results) => results,
+            Err(e) => return Err(format!("Hyperparameter search failed: {}", e))
+        };
+
+    println!("min_std\tvar_smoothing\talpha\tsmoothing\tbinarize\tprior\t\taccuracy");
+    for result in results.iter() {
+        let h: &Hyperparameters = &result.hyperparameters;
+        println!("{:e}\t{:e}\t\t{}\t{}\t\t{}\t\t{}\t{:.4} +/- {:.4}", h.min_std, h.var_smoothing,
+            h.alpha, h.smoothing, h.binarize.map_or(String::from("none"), |t| t.to_string()), h.prior, 
+            result.mean_accuracy, result.std_accuracy);
+    }
+
+    if let Some(results_path) = arg_matches.value_of("results") {
+        if write_tuning_results(results_path, &results).is_err() {
+            return Err(format!("Unable to write tuning results to {}", results_path));
+        }
+    }
+
+    if let (Some(best_path), Some(best)) = (arg_matches.value_of("best"), results.first()) {
+        write_json(best_path, &best.hyperparameters, "best configuration")?;
+    }
+
+    Ok(())
+}
+
+fn write_tuning_results(file_path: &str, results: &[TrialResult]) 
+        -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(file_path)?;
+    wtr.write_record(["min_std", "var_smoothing", "alpha", "smoothing", "binarize", "prior", 
+        "mean_accuracy", "std_accuracy"])?;
+
+    for result in results.iter() {
+        let h: &Hyperparameters = &result.hyperparameters;
+        wtr.write_record(&[
+            h.min_std.to_string(),
+            h.var_smoothing.to_string(),
+            h.alpha.to_string(),
+            h.smoothing.to_string(),
+            h.binarize.map_or(String::from("none"), |t| t.to_string()),
+            h.prior.to_string(),
+            result.mean_accuracy.to_string(),
+            result.std_accuracy.to_string()
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Prints what a saved model learned, optionally saving it as JSON too.
+fn run_inspection<M>(arg_matches: &ArgMatches) -> Result<(), String>
+        where M: Model + Serialize + DeserializeOwned {
+    let load_path: &str = arg_matches.value_of("load").unwrap();
+    let envelope: ModelEnvelope<CalibratedModel<SelectedModel<M>>> = load_model(load_path)?;
+    let mut summary: ModelSummary = envelope.model.summary()
+        .map_err(|e| format!("Could not inspect {}: {}", load_path, e))?;
+    let top: Option<usize> = parse_top(arg_matches)?;
+
+    println!("{}", describe_metadata(&envelope.metadata));
+    println!("{} features{}, {}calibrated\n", summary.num_features,
+        summary.selected_features.map_or(String::new(), |count| format!(" ({} selected)", count)),
+        if summary.calibrated { "" } else { "not " });
+
+    println!("class\tsamples\tprior\tzero-variance features");
+    for class in summary.classes.iter() {
+        println!("{}\t{}\t{:.4}\t{}", class.label, class.sample_size, class.prior,
+            class.zero_variance_features.map_or(String::from("-"), |count| count.to_string()));
+    }
+
+    println!("\nfeature\tscore\t\tmeasure");
+    for feature in summary.top_features(top.unwrap_or(10)).iter() {
+        println!("{}\t{:.4}\t{}", feature.feature, feature.score, feature.measure);
+    }
+
+    if let Some(json_path) = arg_matches.value_of("json") {
+        if let Some(top) = top {
+            summary.features = summary.top_features(top);
+        }
+
+        #[derive(Serialize)]
+        struct Inspection<'a> {
+            metadata: &'a ModelMetadata,
+            #[serde(flatten)]
+            summary: &'a ModelSummary
+        }
+
+        let inspection: Inspection = Inspection { metadata: &envelope.metadata, summary: &summary };
+        write_json(json_path, &inspection, "model description")?;
+    }
+
+    Ok(())
+}
+
+/// Draws every class's feature means and standard deviations as images,
+/// each kind stretched over its range across all the classes so classes
+/// can be compared, and tiles them into one sheet per kind.
+fn run_render(arg_matches: &ArgMatches) -> Result<(), String> {
+    let load_path: &str = arg_matches.value_of("load").unwrap();
+    let output_dir: &Path = Path::new(arg_matches.value_of("output").unwrap());
+    let model: CalibratedModel<SelectedModel<GaussianNaiveBayes>> = load_model(load_path)?.model;
+    if model.get_model().get_selection().is_some() {
+        return Err(String::from("Models trained on selected features cannot be rendered."));
+    }
+    let parameters: GaussianParameters = model.get_model().get_model().parameters()
+        .map_err(|e| format!("Could not render {}: {}", load_path, e))?;
+
+    let (width, height): (usize, usize) = parse_size(arg_matches)?;
+    let num_features: usize = parameters.means.first().map_or(0, |class| class.len());
+    if num_features != width * height {
+        return Err(format!("The model has {} features, not {}x{}.", num_features, width, height));
+    }
+
+    let extension: &str = arg_matches.value_of("image-format").unwrap_or("png");
+    let columns: usize = match arg_matches.value_of("columns") {
+        Some(n) => n.parse::<usize>().ok().filter(|&n| n > 0)
+            .ok_or_else(|| String::from("Columns must be a positive integer."))?,
+        None => (parameters.labels.len() as f64).sqrt().ceil().max(1.0) as usize
+    };
+
+    fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Could not create {}: {}", output_dir.display(), e))?;
+
+    for (name, values) in [("mean", &parameters.means), ("std", &parameters.stds)].iter() {
+        let low: f64 = values.iter().flatten().cloned().fold(f64::INFINITY, f64::min);
+        let high: f64 = values.iter().flatten().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+        let images: Vec<GrayImage> = values.iter()
+            .map(|class| GrayImage::new(width, height, class.clone()).map(|image| image.stretch(low, high)))
+            .collect::<Result<Vec<GrayImage>, DatasetError>>()
+            .map_err(|e| format!("Could not render {}: {}", load_path, e))?;
+
+        for (label, image) in parameters.labels.iter().zip(images.iter()) {
+            // Class names may hold characters that paths cannot
+            let label: String = label.chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                .collect::<String>();
+            write_image(image, &output_dir.join(format!("{}-{}.{}", name, label, extension)))?;
+        }
+
+        let sheet: GrayImage = GrayImage::tile(&images, columns)
+            .map_err(|e| format!("Could not tile the {} images: {}", name, e))?;
+        write_image(&sheet, &output_dir.join(format!("{}s.{}", name, extension)))?;
+        println!("{}s range from {} (black) to {} (white)", name, low, high);
+    }
+
+    Ok(())
+}
+
+fn write_image(image: &GrayImage, path: &Path) -> Result<(), String> {
+    let path: String = path.to_string_lossy().to_string();
+    image.to_file(&path).map_err(|e| format!("Unable to write image to {}: {}", path, e))
+}
+
+/// Reports how a Gaussian model changed between two saves, optionally
+/// saving the comparison as JSON too.
+fn run_diff(arg_matches: &ArgMatches) -> Result<(), String> {
+    let load = |name: &str| -> Result<GaussianParameters, String> {
+        let path: &str = arg_matches.value_of(name).unwrap();
+        match envelope::detect_kind(path).map_err(|e| format!("Could not read {}: {}", path, e))? {
+            Some(kind) if kind != ModelKind::Gaussian =>
+                return Err(format!("Only Gaussian models can be compared, not {}.", kind)),
+            _ => {}
+        }
+
+        let model: CalibratedModel<SelectedModel<GaussianNaiveBayes>> = load_model(path)?.model;
+        if model.get_model().get_selection().is_some() {
+            return Err(String::from("Models trained on selected features cannot be compared."));
+        }
+        model.get_model().get_model().parameters().map_err(|e| format!("Could not compare {}: {}", path, e))
+    };
+    let (old, new): (GaussianParameters, GaussianParameters) = (load("old")?, load("new")?);
+    let top: Option<usize> = parse_top(arg_matches)?;
+
+    let mut diff: ModelDiff = diff::compare(&old, &new).map_err(|_| format!(
+        "The models have {} and {} features.", old.means.first().map_or(0, |class| class.len()),
+        new.means.first().map_or(0, |class| class.len())))?;
+
+    println!("\nScore: {:.6} nats", diff.score);
+    if !diff.added_labels.is_empty() {
+        println!("Added classes: {}", diff.added_labels.join(", "));
+    }
+    if !diff.removed_labels.is_empty() {
+        println!("Removed classes: {}", diff.removed_labels.join(", "));
+    }
+
+    println!("\nclass\told prior\tnew prior\tshift");
+    for prior in diff.priors.iter() {
+        println!("{}\t{:.4}\t\t{:.4}\t\t{:+.4}", prior.label, prior.old_prior, prior.new_prior, prior.shift());
+    }
+
+    println!("\nclass\tfeature\told mean\tnew mean\told std\t\tnew std\t\tdivergence");
+    for change in diff.changes.iter().take(top.unwrap_or(10)) {
+        println!("{}\t{}\t{:.4}\t\t{:.4}\t\t{:.4}\t\t{:.4}\t\t{}", change.label, change.feature,
+            change.old_mean, change.new_mean, change.old_std, change.new_std,
+            change.divergence.map_or(String::from("-"), |divergence| format!("{:.4}", divergence)));
+    }
+
+    if let Some(json_path) = arg_matches.value_of("json") {
+        if let Some(top) = top {
+            diff.changes.truncate(top);
+        }
+
+        write_json(json_path, &diff, "comparison")?;
+    }
+
+    Ok(())
+}
+
+/// Explains the prediction for one data row or image, optionally saving the
+/// explanation as JSON and a heatmap too.
+fn run_explanation<M>(arg_matches: &ArgMatches, spec: &DatasetSpec) -> Result<(), String>
+        where M: Model + Serialize + DeserializeOwned {
+    let envelope: ModelEnvelope<CalibratedModel<SelectedModel<M>>> = load_model(arg_matches.value_of("load").unwrap())?;
+    let spec: &DatasetSpec = &model_spec(spec, &envelope.metadata, &envelope.model)?;
+    let model: CalibratedModel<SelectedModel<M>> = envelope.model;
+    let (width, height): (usize, usize) = parse_size(arg_matches)?;
+
+    let sample: Vec<f64> = match (arg_matches.value_of("data"), arg_matches.value_of("image")) {
+        (Some(data_path), None) => {
+            let row: usize = arg_matches.value_of("row").map_or(Ok(0), |row| row.parse::<usize>())
+                .map_err(|_| String::from("Row must be a non-negative integer."))?;
+            let dataset: Dataset = read_dataset(spec, data_path)?;
+            let sample: Option<Vec<f64>> = dataset.iter().nth(row).map(|(_, sample)| sample.to_vec());
+            sample.ok_or_else(|| format!("{} has only {} rows.", data_path, dataset.len()))?
+        },
+        (None, Some(image_path)) => read_image(arg_matches, image_path, width, height)?.to_features(),
+        _ => return Err(String::from("Explain either a --data row or an --image."))
+    };
+
+    let against: Option<usize> = match arg_matches.value_of("against") {
+        Some(name) => Some(model.label_names().iter().position(|label| label == name)
+            .ok_or_else(|| format!("The model has no class named {}.", name))?),
+        None => None
+    };
+    let top: Option<usize> = parse_top(arg_matches)?;
+
+    let mut explanation: Explanation = explanation::explain(&model, &sample, against)
+        .map_err(|e| format!("Could not explain the sample: {}", e))?;
+
+    println!("Predicted {} ({:.4}) over {} ({:.4})", explanation.predicted,
+        explanation.predicted_posterior, explanation.against, explanation.against_posterior);
+    println!("Log10 prior ratio: {:+.4}\n", explanation.prior);
+
+    println!("feature\tvalue\tcontribution");
+    for contribution in explanation.contributions.iter().take(top.unwrap_or(10)) {
+        println!("{}\t{}\t{:+.4}", contribution.feature, contribution.value, contribution.contribution);
+    }
+
+    if let Some(heatmap_path) = arg_matches.value_of("heatmap") {
+        let heatmap: GrayImage = explanation.heatmap(width, height)
+            .map_err(|_| format!("The sample has {} features, not {}x{}.", sample.len(), width, height))?;
+        write_image(&heatmap, Path::new(heatmap_path))?;
+    }
+
+    if let Some(json_path) = arg_matches.value_of("json") {
+        if let Some(top) = top {
+            explanation.contributions.truncate(top);
+        }
+
+        write_json(json_path, &explanation, "explanation")?;
+    }
+
+    Ok(())
+}
+
+/// Scores every feature of a saved model by how well it tells the classes
+/// apart and, given test data, by how much accuracy drops when its values
+/// are shuffled. `divergences` gives the class divergences of models whose
+/// summaries do not include them, and nothing for the rest. Permutation
+/// importance is measured on the uncalibrated scores, since calibration
+/// does not change which features the model relies on.
+fn run_importance<M>(arg_matches: &ArgMatches, spec: &DatasetSpec,
+        divergences: fn(&M) -> Result<Vec<Option<f64>>, ModelError>) -> Result<(), String>
+        where M: Model + Serialize + DeserializeOwned {
+    let load_path: &str = arg_matches.value_of("load").unwrap();
+    let envelope: ModelEnvelope<CalibratedModel<SelectedModel<M>>> = load_model(load_path)?;
+    let spec: &DatasetSpec = &model_spec(spec, &envelope.metadata, &envelope.model)?;
+    let model: CalibratedModel<SelectedModel<M>> = envelope.model;
+    let message = |e: ModelError| format!("Could not score {}: {}", load_path, e);
+
+    let mut features: Vec<FeatureImportance> = importance::separability(&model.summary().map_err(message)?);
+    // The model only scores the features it was trained on
+    let selected: &SelectedModel<M> = model.get_model();
+    let indices: Vec<usize> = selected.get_selection()
+        .map_or_else(|| (0..features.len()).collect::<Vec<usize>>(), |selection| selection.features.clone());
+    for (feature, divergence) in indices.into_iter().zip(divergences(selected.get_model()).map_err(message)?) {
+        features[feature].divergence = divergence;
+    }
+
+    if let Some(test_path) = arg_matches.value_of("test") {
+        let repeats: usize = match arg_matches.value_of("repeats") {
+            Some(n) => n.parse::<usize>().ok().filter(|&n| n > 0)
+                .ok_or_else(|| String::from("Repeats must be a positive integer."))?,
+            None => 5
+        };
+        let dataset: Dataset = read_dataset(spec, test_path)?;
+        if dataset.is_empty() {
+            return Err(format!("{} has no rows.", test_path));
+        }
+
+        let (baseline, drops): (f64, Vec<(f64, f64)>) =
+            importance::permutation_importance(&model, &dataset, repeats, parse_seed(arg_matches)?)
+                .map_err(|e| format!("Could not test against {}: {}", test_path, e))?;
+        let predictions: Vec<Prediction> = model.predict(&dataset, false)
+            .map_err(|e| format!("Could not test against {}: {}", test_path, e))?;
+        let correct: usize = predictions.iter().filter(|prediction| prediction.is_correct()).count();
+        println!("Test accuracy: {:.4}", correct as f64 / predictions.len() as f64);
+        // The drops are measured from the uncalibrated log likelihood terms,
+        // which can rank rows differently where the densities underflow
+        println!("Permutation baseline accuracy: {:.4}", baseline);
+
+        for (feature, (mean, std)) in features.iter_mut().zip(drops) {
+            feature.permutation = Some(mean);
+            feature.permutation_std = Some(std);
+        }
+    }
+
+    let top: usize = parse_top(arg_matches)?.unwrap_or(10);
+    // Rank by permutation importance when it was measured
+    let rank = |feature: &FeatureImportance| -> f64 {
+        feature.permutation.or(feature.f_statistic).or(feature.divergence).unwrap_or(0.0)
+    };
+    let mut ranked: Vec<&FeatureImportance> = features.iter().collect::<Vec<&FeatureImportance>>();
+    ranked.sort_by(|a, b| rank(b).total_cmp(&rank(a)));
+
+    let show = |score: Option<f64>| score.map_or(String::from("-"), |score| format!("{:.4}", score));
+    println!("\nfeature\tf-statistic\tdivergence\tpermutation");
+    for feature in ranked.iter().take(top) {
+        println!("{}\t{}\t\t{}\t\t{}{}", feature.feature, show(feature.f_statistic), show(feature.divergence),
+            show(feature.permutation), feature.permutation_std.map_or(String::new(), |std| format!(" ± {:.4}", std)));
+    }
+
+    if let Some(csv_path) = arg_matches.value_of("csv") {
+        write_importance(csv_path, &features)
+            .map_err(|_| format!("Unable to write feature importance to {}", csv_path))?;
+    }
+
+    Ok(())
+}
+
+/// Writes one row per feature, leaving scores that were not computed empty.
+fn write_importance(file_path: &str, features: &[FeatureImportance]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(file_path)?;
+    wtr.write_record(["feature", "f_statistic", "divergence", "permutation", "permutation_std"])?;
+
+    let cell = |score: Option<f64>| score.map_or(String::new(), |score| score.to_string());
+    for feature in features.iter() {
+        wtr.write_record(&[
+            feature.feature.to_string(),
+            cell(feature.f_statistic),
+            cell(feature.divergence),
+            cell(feature.permutation),
+            cell(feature.permutation_std)
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes a standalone classifier for a saved Gaussian model, as a Rust
+/// module and optionally as a C header and source.
+fn run_codegen(arg_matches: &ArgMatches) -> Result<(), String> {
+    let load_path: &str = arg_matches.value_of("load").unwrap();
+    let model: CalibratedModel<SelectedModel<GaussianNaiveBayes>> = load_model(load_path)?.model;
+    if model.is_calibrated() {
+        return Err(String::from("Calibrated models cannot be generated as code."));
+    }
+    if model.get_model().get_selection().is_some() {
+        return Err(String::from("Models trained on selected features cannot be generated as code."));
+    }
+    let parameters: GaussianParameters = model.get_model().get_model().parameters()
+        .map_err(|e| format!("Could not generate code from {}: {}", load_path, e))?;
+
+    let output_path: &str = arg_matches.value_of("output").unwrap();
+    fs::write(output_path, codegen::rust_module(&parameters))
+        .map_err(|_| format!("Unable to write Rust module to {}", output_path))?;
+
+    if let (Some(header_path), Some(source_path)) =
+            (arg_matches.value_of("c-header"), arg_matches.value_of("c-source")) {
+        let header_name: &str = Path::new(header_path).file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("Invalid C header path {}", header_path))?;
+        let guard: String = header_name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect::<String>();
+
+        fs::write(header_path, codegen::c_header(&parameters, &guard))
+            .map_err(|_| format!("Unable to write C header to {}", header_path))?;
+        fs::write(source_path, codegen::c_source(&parameters, header_name))
+            .map_err(|_| format!("Unable to write C source to {}", source_path))?;
+    }
+
+    Ok(())
+}
+
+fn print_reliability(title: &str, bins: &[ReliabilityBin]) {
+    println!("{} (ECE {:.4}):", title, calibration::expected_calibration_error(bins));
+    println!("  bin\t\tcount\tconfidence\taccuracy");
+
+    for bin in bins.iter().filter(|b| b.count > 0) {
+        println!("  {:.2}-{:.2}\t{}\t{:.4}\t\t{:.4}", 
+            bin.lower, bin.upper, bin.count, bin.mean_confidence, bin.accuracy);
+    }
+}
+
+fn write_risk_coverage_curve(file_path: &str, curve: &[RiskCoveragePoint]) 
+        -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(file_path)?;
+    wtr.write_record(["threshold", "coverage", "risk"])?;
+
+    for point in curve.iter() {
+        wtr.write_record(&[
+            point.threshold.to_string(), 
+            point.coverage.to_string(), 
+            point.risk.to_string()
+        ])?;
     }
 
+    wtr.flush()?;
     Ok(())
 }
 
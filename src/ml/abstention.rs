@@ -0,0 +1,177 @@
+use crate::ml::prediction::Prediction;
+use std::{str::FromStr, string::String, vec::Vec};
+use std::cmp::Ordering;
+
+/// How confident a posterior distribution is in its top class.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConfidenceMeasure {
+    /// The largest posterior probability.
+    MaxPosterior,
+    /// The gap between the two largest posterior probabilities.
+    Margin
+}
+
+impl ConfidenceMeasure {
+    pub fn score(&self, posteriors: &[f64]) -> f64 {
+        let (first, second): (f64, f64) = posteriors.iter()
+            .fold((0.0, 0.0), |(first, second), &p| {
+                if p > first {
+                    (p, first)
+                } else if p > second {
+                    (first, p)
+                } else {
+                    (first, second)
+                }
+            });
+
+        match *self {
+            ConfidenceMeasure::MaxPosterior => first,
+            ConfidenceMeasure::Margin => first - second
+        }
+    }
+}
+
+impl FromStr for ConfidenceMeasure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ConfidenceMeasure, String> {
+        match s {
+            "max-posterior" => Ok(ConfidenceMeasure::MaxPosterior),
+            "margin" => Ok(ConfidenceMeasure::Margin),
+            _ => Err(format!("Unknown abstention rule '{}'.", s))
+        }
+    }
+}
+
+/// Refuses to classify a sample whose confidence falls below the threshold.
+#[derive(Debug, Copy, Clone)]
+pub struct AbstentionRule {
+    measure: ConfidenceMeasure,
+    threshold: f64
+}
+
+impl AbstentionRule {
+    pub fn new(measure: ConfidenceMeasure, threshold: f64) -> AbstentionRule {
+        AbstentionRule {
+            measure,
+            threshold
+        }
+    }
+
+    pub fn abstains(&self, posteriors: &[f64]) -> bool {
+        self.measure.score(posteriors) < self.threshold
+    }
+}
+
+/// Accuracy measured only on the samples the model chose to classify.
+#[derive(Debug, Copy, Clone)]
+pub struct CoverageReport {
+    pub total: usize,
+    pub covered: usize,
+    pub correct: usize
+}
+
+impl CoverageReport {
+    pub fn coverage(&self) -> f64 {
+        self.covered as f64 / self.total as f64
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        if self.covered == 0 { 1.0 } else { self.correct as f64 / self.covered as f64 }
+    }
+
+    pub fn risk(&self) -> f64 {
+        1.0 - self.accuracy()
+    }
+}
+
+/// A single point of a risk-coverage curve.
+#[derive(Debug, Copy, Clone)]
+pub struct RiskCoveragePoint {
+    pub threshold: f64,
+    pub coverage: f64,
+    pub risk: f64
+}
+
+pub fn coverage_report(predictions: &[Prediction], rule: &AbstentionRule)
+        -> CoverageReport {
+    predictions.iter()
+        .filter(|p| !rule.abstains(p.get_posteriors()))
+        .fold(CoverageReport { total: predictions.len(), covered: 0, correct: 0 },
+            |mut report, p| {
+                report.covered += 1;
+                report.correct += p.is_correct() as usize;
+                report
+            })
+}
+
+/// Sweeps the abstention threshold over every observed confidence score.
+/// Each point uses the threshold that keeps exactly the samples scoring at
+/// least as high as it, ordered from lowest to highest coverage.
+pub fn risk_coverage_curve(predictions: &[Prediction], measure: ConfidenceMeasure)
+        -> Vec<RiskCoveragePoint> {
+    let mut scored: Vec<(f64, bool)> = predictions.iter()
+        .map(|p| (measure.score(p.get_posteriors()), p.is_correct()))
+        .collect::<Vec<(f64, bool)>>();
+
+    // Most confident samples first so each prefix is a covered set
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+    let total: f64 = scored.len() as f64;
+    let mut curve: Vec<RiskCoveragePoint> = Vec::new();
+    let mut errors: usize = 0;
+
+    for (idx, &(score, correct)) in scored.iter().enumerate() {
+        errors += !correct as usize;
+
+        // Samples tied on score are accepted or rejected together
+        if idx + 1 < scored.len() && scored[idx + 1].0 == score {
+            continue;
+        }
+
+        let covered: usize = idx + 1;
+        curve.push(RiskCoveragePoint {
+            threshold: score,
+            coverage: covered as f64 / total,
+            risk: errors as f64 / covered as f64
+        });
+    }
+
+    curve
+}
+
+#[cfg(test)]
+mod abstention_tests {
+    use crate::ml::abstention::*;
+    use crate::ml::prediction::Prediction;
+
+    #[test]
+    fn test_confidence_measures() {
+        let posteriors: Vec<f64> = vec![0.2, 0.5, 0.3];
+
+        assert_relative_eq!(ConfidenceMeasure::MaxPosterior.score(&posteriors), 0.5);
+        assert_relative_eq!(ConfidenceMeasure::Margin.score(&posteriors), 0.2);
+    }
+
+    #[test]
+    fn test_risk_coverage_curve() {
+        let predictions: Vec<Prediction> = vec![
+            Prediction::new(0, vec![0.9, 0.1]),
+            Prediction::new(0, vec![0.3, 0.7]),
+            Prediction::new(1, vec![0.4, 0.6]),
+            Prediction::new(1, vec![0.45, 0.55])
+        ];
+
+        let rule: AbstentionRule = AbstentionRule::new(ConfidenceMeasure::MaxPosterior, 0.65);
+        let report: CoverageReport = coverage_report(&predictions, &rule);
+        assert_eq!(report.covered, 2);
+        assert_relative_eq!(report.accuracy(), 0.5);
+
+        let curve = risk_coverage_curve(&predictions, ConfidenceMeasure::MaxPosterior);
+        assert_eq!(curve.len(), 4);
+        assert_relative_eq!(curve[0].risk, 0.0);
+        assert_relative_eq!(curve[1].risk, 0.5);
+        assert_relative_eq!(curve[3].coverage, 1.0);
+        assert_relative_eq!(curve[3].risk, 0.25);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::ml::{error::DatasetError, dataset::{ColumnRef, Dataset, FeatureType}};
+use std::{error::Error, fs, string::String, vec::Vec};
+
+/// Token Weka writes for a missing value.
+static MISSING: &str = "?";
+
+/// The attributes and rows of an ARFF file. Values are stored as numbers:
+/// nominal values by their index in the declared list and missing values
+/// as NaN.
+#[derive(Debug, Clone)]
+pub struct ArffFile {
+    relation: String,
+    names: Vec<String>,
+    types: Vec<FeatureType>,
+    rows: Vec<Vec<f64>>
+}
+
+/// Splits on commas outside single or double quotes, stripping the quotes
+/// and surrounding whitespace from each field.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut current: String = String::new();
+    let mut quote: Option<char> = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, ',') => {
+                fields.push(current.trim().to_string());
+                current.clear();
+            },
+            (None, c) => current.push(c)
+        }
+    }
+
+    fields.push(current.trim().to_string());
+    fields
+}
+
+/// Splits an `@attribute` declaration into its name and type, allowing a
+/// quoted name.
+fn split_declaration(rest: &str) -> Result<(String, String), DatasetError> {
+    let rest: &str = rest.trim();
+
+    let (name, kind): (&str, &str) = match rest.chars().next() {
+        Some(q) if q == '\'' || q == '"' => {
+            let end: usize = rest[1..].find(q).ok_or(DatasetError::InvalidHeader)? + 1;
+            (&rest[1..end], &rest[end + 1..])
+        },
+        _ => rest.split_once(char::is_whitespace).ok_or(DatasetError::InvalidHeader)?
+    };
+
+    Ok((name.to_string(), kind.trim().to_string()))
+}
+
+fn parse_type(kind: &str) -> Result<FeatureType, DatasetError> {
+    if kind.starts_with('{') && kind.ends_with('}') {
+        return Ok(FeatureType::Nominal(split_fields(&kind[1..kind.len() - 1])));
+    }
+
+    match kind.to_lowercase().as_str() {
+        "numeric" | "real" | "integer" => Ok(FeatureType::Numeric),
+        // String and date attributes have no numeric meaning here
+        _ => Err(DatasetError::InvalidHeader)
+    }
+}
+
+fn parse_value(field: &str, kind: &FeatureType) -> Result<f64, DatasetError> {
+    if field == MISSING {
+        return Ok(f64::NAN);
+    }
+
+    match kind {
+        FeatureType::Numeric => field.parse::<f64>().map_err(|_| DatasetError::InvalidValue),
+        FeatureType::Nominal(values) => values.iter().position(|v| v == field)
+            .map(|idx| idx as f64)
+            .ok_or(DatasetError::InvalidValue)
+    }
+}
+
+impl ArffFile {
+    pub fn parse(contents: &str) -> Result<ArffFile, DatasetError> {
+        let mut arff: ArffFile = ArffFile {
+            relation: String::new(),
+            names: Vec::new(),
+            types: Vec::new(),
+            rows: Vec::new()
+        };
+        let mut in_data: bool = false;
+
+        for line in contents.lines() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('%') {
+                continue;
+            }
+
+            if in_data {
+                if line.starts_with('{') {
+                    // Sparse ARFF rows are not supported
+                    return Err(DatasetError::InvalidValue);
+                }
+
+                let fields: Vec<String> = split_fields(line);
+                if fields.len() != arff.types.len() {
+                    return Err(DatasetError::InconsistentRow);
+                }
+
+                arff.rows.push(fields.iter().zip(arff.types.iter())
+                    .map(|(field, kind)| parse_value(field, kind))
+                    .collect::<Result<Vec<f64>, DatasetError>>()?);
+                continue;
+            }
+
+            let (keyword, rest): (&str, &str) = line.split_once(char::is_whitespace)
+                .unwrap_or((line, ""));
+            match keyword.to_lowercase().as_str() {
+                "@relation" => arff.relation = rest.trim().trim_matches('\'').to_string(),
+                "@attribute" => {
+                    let (name, kind): (String, String) = split_declaration(rest)?;
+                    arff.names.push(name);
+                    arff.types.push(parse_type(&kind)?);
+                },
+                "@data" => in_data = true,
+                _ => return Err(DatasetError::InvalidHeader)
+            }
+        }
+
+        if !in_data {
+            return Err(DatasetError::InvalidHeader);
+        }
+
+        Ok(arff)
+    }
+
+    pub fn from_file(file_path: &str) -> Result<ArffFile, Box<dyn Error>> {
+        Ok(ArffFile::parse(&fs::read_to_string(file_path)?)?)
+    }
+
+    pub fn get_relation(&self) -> &str {
+        &self.relation
+    }
+
+    pub fn get_names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The index of the class attribute: the one `class` names or is at if
+    /// given, otherwise the last attribute, as Weka assumes.
+    fn class_index(&self, class: Option<&ColumnRef>) -> Result<usize, DatasetError> {
+        match class {
+            Some(ColumnRef::Index(index)) if *index < self.names.len() => Ok(*index),
+            Some(ColumnRef::Index(_)) => Err(DatasetError::UnknownColumn),
+            Some(ColumnRef::Name(name)) => self.names.iter().position(|n| n == name)
+                .ok_or(DatasetError::UnknownColumn),
+            None => self.names.len().checked_sub(1).ok_or(DatasetError::MissingLabel)
+        }
+    }
+
+    fn feature_row(row: &[f64], class_index: usize) -> Vec<f64> {
+        row.iter().enumerate()
+            .filter(|&(idx, _)| idx != class_index)
+            .map(|(_, &value)| value)
+            .collect::<Vec<f64>>()
+    }
+
+    /// Builds a dataset whose classes are the values of the nominal class
+    /// attribute, in declared order, and whose features keep their types.
+    pub fn to_dataset(&self, class: Option<&ColumnRef>) -> Result<Dataset, DatasetError> {
+        let class_index: usize = self.class_index(class)?;
+        let label_names: Vec<String> = match &self.types[class_index] {
+            FeatureType::Nominal(values) => values.clone(),
+            FeatureType::Numeric => return Err(DatasetError::InvalidLabel)
+        };
+
+        let mut dataset: Dataset = Dataset::with_label_names(label_names, Vec::new(), Vec::new());
+        for row in self.rows.iter() {
+            let label: f64 = row[class_index];
+            if label.is_nan() {
+                return Err(DatasetError::MissingLabel);
+            }
+
+            dataset.push(label as usize, ArffFile::feature_row(row, class_index))?;
+        }
+
+        dataset.set_feature_types(self.types.iter().enumerate()
+            .filter(|&(idx, _)| idx != class_index)
+            .map(|(_, kind)| kind.clone())
+            .collect::<Vec<FeatureType>>());
+
+        Ok(dataset)
+    }
+
+    /// The feature values of every row, ignoring the class attribute.
+    pub fn to_samples(&self, class: Option<&ColumnRef>) -> Result<Vec<Vec<f64>>, DatasetError> {
+        let class_index: usize = self.class_index(class)?;
+
+        Ok(self.rows.iter()
+            .map(|row| ArffFile::feature_row(row, class_index))
+            .collect::<Vec<Vec<f64>>>())
+    }
+}
+
+#[cfg(test)]
+mod arff_tests {
+    use crate::ml::arff::*;
+
+    static WEATHER: &str = "% Weka's weather data\n\
+        @relation weather\n\
+        @attribute outlook {sunny, overcast, rainy}\n\
+        @attribute temperature real\n\
+        @attribute 'wind speed' numeric\n\
+        @attribute play {yes, no}\n\
+        @data\n\
+        sunny,85,?,no\n\
+        'overcast',83,3.5,yes\n\
+        ?,70,1,yes\n";
+
+    #[test]
+    fn test_parse_header_and_rows() {
+        let arff: ArffFile = ArffFile::parse(WEATHER).unwrap();
+        assert_eq!(arff.get_relation(), "weather");
+        assert_eq!(arff.get_names()[2], "wind speed");
+
+        let dataset: Dataset = arff.to_dataset(None).unwrap();
+        assert_eq!(dataset.get_label_names(), &["yes", "no"]);
+        assert_eq!(dataset.get_labels(), &[1, 0, 0]);
+        assert_eq!(dataset.get_sample(1), &[1.0, 83.0, 3.5]);
+        assert!(dataset.get_sample(0)[2].is_nan());
+        assert!(dataset.get_sample(2)[0].is_nan());
+        assert_eq!(dataset.get_feature_types()[1], FeatureType::Numeric);
+
+        let temperature: ColumnRef = ColumnRef::Name(String::from("temperature"));
+        assert_eq!(arff.to_dataset(Some(&temperature)).err(), Some(DatasetError::InvalidLabel));
+
+        // A class given by position is the attribute at that position
+        assert_eq!(arff.to_dataset(Some(&ColumnRef::Index(1))).err(), Some(DatasetError::InvalidLabel));
+        assert_eq!(arff.to_dataset(Some(&ColumnRef::Index(3))).unwrap().get_labels(), &[1, 0, 0]);
+        assert_eq!(arff.to_dataset(Some(&ColumnRef::Index(4))).err(), Some(DatasetError::UnknownColumn));
+    }
+
+    #[test]
+    fn test_rejects_malformed_input() {
+        assert_eq!(ArffFile::parse("@relation r\n@attribute a string\n@data\n").err(),
+            Some(DatasetError::InvalidHeader));
+        assert_eq!(ArffFile::parse("@relation r\n@attribute a {x, y}\n@data\nz\n").err(),
+            Some(DatasetError::InvalidValue));
+    }
+}
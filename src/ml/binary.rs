@@ -0,0 +1,492 @@
+extern crate flate2;
+extern crate zstd;
+
+use crate::ml::{error::ModelFileError, envelope::{self, ModelEnvelope, ModelMetadata}};
+use crate::ml::{hyperparameters::{Hyperparameters, PriorMode}, label::Label, model::Model};
+use crate::model::ModelKind;
+use crate::naivebayes::class_label::ClassLabel;
+use std::{convert::{TryFrom, TryInto}, error::Error, fs, io::{Read, Write}, str::FromStr};
+use std::{string::String, vec::Vec};
+
+/// The first bytes of every binary model file.
+pub static MAGIC: &[u8; 8] = b"NBMODEL\0";
+
+/// The newest layout this build reads and the one it writes.
+pub static VERSION: u16 = 1;
+
+/// File extension of binary models, optionally followed by `.gz` or `.zst`.
+pub static EXTENSION: &str = ".nbm";
+
+// The header is followed by the body: the metadata, then the model's own
+// fields as each model writes them. Counts and sizes are u64s, optional
+// values are a presence byte then the value, and strings are a u64 length
+// then UTF-8 bytes. Per-class statistics are written as blocks of
+// little-endian f64s, one value per feature, so a class's means are
+// contiguous however many features there are.
+static HEADER_LEN: usize = 16;
+
+/// How the body of a binary model file is compressed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd
+}
+
+impl Compression {
+    /// The compression implied by a file name ending in `.gz` or `.zst`.
+    pub fn from_path(file_path: &str) -> Compression {
+        if file_path.ends_with(".gz") {
+            Compression::Gzip
+        } else if file_path.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match *self {
+            Compression::None => 0,
+            Compression::Gzip => 1,
+            Compression::Zstd => 2
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Compression> {
+        match code {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Gzip),
+            2 => Some(Compression::Zstd),
+            _ => None
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Compression, String> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("Unknown compression '{}'.", s))
+        }
+    }
+}
+
+/// The fixed 16 byte header: the magic, the format version (u16), the model
+/// kind and compression (one byte each) and four reserved bytes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BinaryHeader {
+    pub version: u16,
+    pub kind: ModelKind,
+    pub compression: Compression
+}
+
+impl BinaryHeader {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.push(self.kind.code());
+        bytes.push(self.compression.code());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    /// Parses the header, or `None` if the bytes do not start with the magic.
+    pub fn parse(bytes: &[u8]) -> Result<Option<BinaryHeader>, ModelFileError> {
+        if !bytes.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(ModelFileError::InvalidEncoding);
+        }
+
+        let version: u16 = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if version == 0 || version > VERSION {
+            return Err(ModelFileError::UnsupportedVersion);
+        }
+
+        Ok(Some(BinaryHeader {
+            version,
+            kind: ModelKind::from_code(bytes[10]).ok_or(ModelFileError::UnknownKind)?,
+            compression: Compression::from_code(bytes[11]).ok_or(ModelFileError::InvalidEncoding)?
+        }))
+    }
+}
+
+/// Whether a model saved at this path should be written in binary.
+pub fn is_binary_path(file_path: &str) -> bool {
+    [EXTENSION, ".nbm.gz", ".nbm.zst"].iter().any(|ext| file_path.ends_with(ext))
+}
+
+/// The header of the model file at the path, or `None` if it is not a
+/// binary model, such as a JSON one.
+pub fn detect(file_path: &str) -> Result<Option<BinaryHeader>, Box<dyn Error>> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(HEADER_LEN);
+    fs::File::open(file_path)?.take(HEADER_LEN as u64).read_to_end(&mut bytes)?;
+
+    Ok(BinaryHeader::parse(&bytes)?)
+}
+
+/// Appends the fields of a binary model body.
+pub struct BinaryWriter {
+    bytes: Vec<u8>
+}
+
+impl BinaryWriter {
+    fn new() -> BinaryWriter {
+        BinaryWriter { bytes: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        self.write_usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// Writes the values without their count, which the reader must know.
+    pub fn write_floats(&mut self, values: &[f64]) {
+        for &value in values.iter() {
+            self.write_f64(value);
+        }
+    }
+
+    /// Writes the values without their count, which the reader must know.
+    pub fn write_sizes(&mut self, values: &[usize]) {
+        for &value in values.iter() {
+            self.write_usize(value);
+        }
+    }
+
+    pub fn write_option<T, F: FnOnce(&mut BinaryWriter, T)>(&mut self, value: Option<T>, write: F) {
+        self.write_bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    pub fn write_labels(&mut self, labels: &[ClassLabel]) {
+        self.write_usize(labels.len());
+        for label in labels.iter() {
+            self.write_usize(label.get_index());
+            self.write_string(label.get_name());
+        }
+    }
+
+    pub fn write_hyperparameters(&mut self, hyperparameters: &Hyperparameters) {
+        self.write_floats(&[hyperparameters.min_std, hyperparameters.var_smoothing,
+            hyperparameters.alpha, hyperparameters.smoothing]);
+        self.write_option(hyperparameters.binarize, BinaryWriter::write_f64);
+        self.write_u8(match hyperparameters.prior {
+            PriorMode::Empirical => 0,
+            PriorMode::Uniform => 1
+        });
+    }
+
+    fn write_metadata(&mut self, metadata: &ModelMetadata) {
+        self.write_usize(metadata.schema_version as usize);
+        self.write_option(metadata.created.as_deref(), BinaryWriter::write_string);
+        self.write_option(metadata.training_rows, BinaryWriter::write_usize);
+        self.write_option(metadata.source_checksum.as_deref(), BinaryWriter::write_string);
+        self.write_option(metadata.zero_based, BinaryWriter::write_bool);
+        self.write_hyperparameters(&metadata.hyperparameters);
+    }
+}
+
+/// Reads the fields of a binary model body from the front of a byte slice.
+pub struct BinaryReader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ModelFileError> {
+        if self.bytes.len() < len {
+            return Err(ModelFileError::InvalidEncoding);
+        }
+
+        let (taken, rest): (&'a [u8], &'a [u8]) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn word(&mut self) -> Result<[u8; 8], ModelFileError> {
+        let mut word: [u8; 8] = [0; 8];
+        word.copy_from_slice(self.take(8)?);
+        Ok(word)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ModelFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, ModelFileError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ModelFileError::InvalidEncoding)
+        }
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, ModelFileError> {
+        usize::try_from(u64::from_le_bytes(self.word()?)).map_err(|_| ModelFileError::InvalidEncoding)
+    }
+
+    /// A count of items that each take at least a byte, so it can be no
+    /// more than the bytes left. Checked before anything is allocated.
+    pub fn read_count(&mut self) -> Result<usize, ModelFileError> {
+        let count: usize = self.read_usize()?;
+        if count > self.bytes.len() {
+            return Err(ModelFileError::InvalidEncoding);
+        }
+
+        Ok(count)
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, ModelFileError> {
+        Ok(f64::from_le_bytes(self.word()?))
+    }
+
+    pub fn read_string(&mut self) -> Result<String, ModelFileError> {
+        let len: usize = self.read_usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| ModelFileError::InvalidEncoding)
+    }
+
+    pub fn read_floats(&mut self, len: usize) -> Result<Vec<f64>, ModelFileError> {
+        let bytes: &[u8] = self.take(len.checked_mul(8).ok_or(ModelFileError::InvalidEncoding)?)?;
+        Ok(bytes.chunks_exact(8)
+            .map(|word| f64::from_le_bytes(word.try_into().unwrap()))
+            .collect::<Vec<f64>>())
+    }
+
+    pub fn read_sizes(&mut self, len: usize) -> Result<Vec<usize>, ModelFileError> {
+        let bytes: &[u8] = self.take(len.checked_mul(8).ok_or(ModelFileError::InvalidEncoding)?)?;
+        bytes.chunks_exact(8)
+            .map(|word| usize::try_from(u64::from_le_bytes(word.try_into().unwrap()))
+                .map_err(|_| ModelFileError::InvalidEncoding))
+            .collect::<Result<Vec<usize>, ModelFileError>>()
+    }
+
+    pub fn read_option<T, F: FnOnce(&mut BinaryReader<'a>) -> Result<T, ModelFileError>>(&mut self, read: F)
+            -> Result<Option<T>, ModelFileError> {
+        match self.read_bool()? {
+            true => read(self).map(Some),
+            false => Ok(None)
+        }
+    }
+
+    pub fn read_labels(&mut self) -> Result<Vec<ClassLabel>, ModelFileError> {
+        (0..self.read_count()?)
+            .map(|_| Ok(ClassLabel::new(self.read_usize()?, self.read_string()?)))
+            .collect::<Result<Vec<ClassLabel>, ModelFileError>>()
+    }
+
+    pub fn read_hyperparameters(&mut self) -> Result<Hyperparameters, ModelFileError> {
+        let values: Vec<f64> = self.read_floats(4)?;
+
+        Ok(Hyperparameters {
+            min_std: values[0],
+            var_smoothing: values[1],
+            alpha: values[2],
+            smoothing: values[3],
+            binarize: self.read_option(BinaryReader::read_f64)?,
+            prior: match self.read_u8()? {
+                0 => PriorMode::Empirical,
+                1 => PriorMode::Uniform,
+                _ => return Err(ModelFileError::InvalidEncoding)
+            }
+        })
+    }
+
+    fn read_metadata(&mut self, kind: ModelKind) -> Result<ModelMetadata, ModelFileError> {
+        let schema_version: usize = self.read_usize()?;
+        if schema_version == 0 || schema_version > envelope::SCHEMA_VERSION as usize {
+            return Err(ModelFileError::UnsupportedVersion);
+        }
+
+        Ok(ModelMetadata {
+            schema_version: schema_version as u32,
+            kind,
+            created: self.read_option(BinaryReader::read_string)?,
+            training_rows: self.read_option(BinaryReader::read_usize)?,
+            source_checksum: self.read_option(BinaryReader::read_string)?,
+            zero_based: self.read_option(BinaryReader::read_bool)?,
+            hyperparameters: self.read_hyperparameters()?
+        })
+    }
+}
+
+/// Writes a model and its metadata behind a binary header, compressing the
+/// body if asked.
+pub fn to_bytes<M: Model>(model: &M, metadata: &ModelMetadata, compression: Compression)
+        -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut writer: BinaryWriter = BinaryWriter::new();
+    writer.write_metadata(metadata);
+    model.encode_binary(&mut writer);
+    let body: Vec<u8> = writer.bytes;
+
+    let header: BinaryHeader = BinaryHeader { version: VERSION, kind: metadata.kind, compression };
+    let mut bytes: Vec<u8> = header.to_bytes();
+
+    match compression {
+        Compression::None => bytes.extend_from_slice(&body),
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(bytes, flate2::Compression::default());
+            encoder.write_all(&body)?;
+            bytes = encoder.finish()?;
+        },
+        Compression::Zstd => bytes.extend_from_slice(&zstd::encode_all(body.as_slice(), 0)?)
+    }
+
+    Ok(bytes)
+}
+
+/// Reads the model and metadata written by `to_bytes`, checking that they
+/// are of the expected kind.
+pub fn from_bytes<M: Model>(bytes: &[u8], kind: ModelKind)
+        -> Result<ModelEnvelope<M>, Box<dyn Error>> {
+    let header: BinaryHeader = BinaryHeader::parse(bytes)?.ok_or(ModelFileError::InvalidMagic)?;
+    if header.kind != kind {
+        return Err(Box::new(ModelFileError::KindMismatch));
+    }
+
+    let compressed: &[u8] = &bytes[HEADER_LEN..];
+    let body: Vec<u8> = match header.compression {
+        Compression::None => compressed.to_vec(),
+        Compression::Gzip => {
+            let mut body: Vec<u8> = Vec::new();
+            flate2::read::GzDecoder::new(compressed).read_to_end(&mut body)?;
+            body
+        },
+        Compression::Zstd => zstd::decode_all(compressed)?
+    };
+
+    let mut reader: BinaryReader = BinaryReader { bytes: &body };
+    let metadata: ModelMetadata = reader.read_metadata(kind)?;
+    let model: M = M::decode_binary(&mut reader)?;
+    if !reader.bytes.is_empty() {
+        return Err(Box::new(ModelFileError::InvalidEncoding));
+    }
+
+    Ok(ModelEnvelope { metadata, model })
+}
+
+/// Loads a model saved by `write_binary`, panicking like `read_json` if
+/// the file is missing or malformed.
+pub fn read_binary<M: Model>(file_path: &str, kind: ModelKind) -> M {
+    eprint!("Loading model from {}...", file_path);
+
+    let bytes: Vec<u8> = fs::read(file_path).expect("Unable to read model file.");
+    let envelope: ModelEnvelope<M> = match from_bytes(&bytes, kind) {
+        Ok(envelope) => envelope,
+        Err(e) => panic!("Could not decode binary model: {}", e)
+    };
+
+    eprintln!("done.");
+
+    envelope.model
+}
+
+/// Saves the model and its metadata in binary.
+pub fn write_binary<M: Model>(model: &M, metadata: &ModelMetadata, file_path: &str,
+        compression: Compression) -> Result<(), Box<dyn Error>> {
+    eprint!("Saving model to {}...", file_path);
+
+    fs::write(file_path, to_bytes(model, metadata, compression)?)?;
+
+    eprintln!("done.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod binary_tests {
+    use crate::ml::{binary::*, calibration::{CalibratedModel, CalibrationMethod}};
+    use crate::ml::dataset::{Dataset, FeatureType};
+    use crate::ml::selection::{FeatureSelection, SelectedModel, SelectionMethod, SelectionRule};
+    use crate::model::{gaussian_nb::GaussianNaiveBayes, mixed_nb::MixedNaiveBayes};
+
+    fn dataset() -> Dataset {
+        Dataset::with_label_names(vec![String::from("a"), String::from("b")], vec![0, 0, 0, 1, 1], vec![
+            vec![1.0, 0.0, 5.0], vec![2.0, 3.0, 6.0], vec![3.0, 0.0, f64::NAN],
+            vec![1.5, 9.0, 7.0], vec![2.5, 9.0, 8.0]
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        model.fit(&dataset()).unwrap();
+        let metadata: ModelMetadata = ModelMetadata {
+            training_rows: Some(5),
+            zero_based: Some(true),
+            ..ModelMetadata::new(ModelKind::Gaussian, *model.get_hyperparameters())
+        };
+
+        for &compression in [Compression::None, Compression::Gzip, Compression::Zstd].iter() {
+            let bytes: Vec<u8> = to_bytes(&model, &metadata, compression).unwrap();
+            let header: BinaryHeader = BinaryHeader::parse(&bytes).unwrap().unwrap();
+            assert_eq!((header.version, header.compression), (1, compression));
+
+            let envelope: ModelEnvelope<GaussianNaiveBayes> = from_bytes(&bytes, ModelKind::Gaussian).unwrap();
+            assert_eq!(envelope.metadata, metadata);
+            assert_eq!(serde_json::to_value(&envelope.model).unwrap(), serde_json::to_value(&model).unwrap());
+            assert!(from_bytes::<GaussianNaiveBayes>(&bytes, ModelKind::Mixed).is_err());
+        }
+
+        // Each class's means are one block, however many features there are
+        let bytes: Vec<u8> = to_bytes(&model, &metadata, Compression::None).unwrap();
+        let block: Vec<u8> = [2.0f64, 1.0, 5.5].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        assert!(bytes.windows(block.len()).any(|window| window == block.as_slice()));
+
+        assert_eq!(BinaryHeader::parse(b"{\"labels\": []}").unwrap(), None);
+        assert!(from_bytes::<GaussianNaiveBayes>(&bytes[..bytes.len() - 1], ModelKind::Gaussian).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_wrapped_mixed() {
+        let mut dataset: Dataset = dataset();
+        dataset.set_feature_types(vec![FeatureType::Numeric,
+            FeatureType::Nominal((0..10).map(|category| category.to_string()).collect::<Vec<String>>()),
+            FeatureType::Numeric]);
+
+        let mut selected: SelectedModel<MixedNaiveBayes> = SelectedModel::from_label_names(&[]);
+        selected.set_selection(FeatureSelection::fit(&dataset, SelectionMethod::MutualInformation,
+            SelectionRule::Top(2)).unwrap());
+        let mut model: CalibratedModel<SelectedModel<MixedNaiveBayes>> = CalibratedModel::new(selected);
+        model.fit(&dataset).unwrap();
+        model.calibrate(&dataset, CalibrationMethod::Isotonic, 10).unwrap();
+
+        let metadata: ModelMetadata = ModelMetadata::new(ModelKind::Mixed, *model.get_hyperparameters());
+        let bytes: Vec<u8> = to_bytes(&model, &metadata, Compression::None).unwrap();
+        let envelope: ModelEnvelope<CalibratedModel<SelectedModel<MixedNaiveBayes>>> =
+            from_bytes(&bytes, ModelKind::Mixed).unwrap();
+        assert_eq!(serde_json::to_value(&envelope.model).unwrap(), serde_json::to_value(&model).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_oversized_counts() {
+        // A label count larger than the data is rejected before allocating
+        let mut writer: BinaryWriter = BinaryWriter::new();
+        writer.write_usize(u64::MAX as usize);
+        assert_eq!(BinaryReader { bytes: &writer.bytes }.read_labels().unwrap_err(),
+            ModelFileError::InvalidEncoding);
+        assert!(BinaryReader { bytes: &[] }.read_floats(usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,581 @@
+extern crate serde;
+
+use crate::ml::{model::{self, Model}, label::Label, error::{ModelError, ModelFileError, PmmlError}, dataset::Dataset};
+use crate::ml::{inspection::ModelSummary, pmml::PmmlModel};
+use crate::ml::prediction::{self, Prediction};
+use crate::ml::{hyperparameters::Hyperparameters, sparse::{SparseModel, SparseDataset, SparseRow}};
+use crate::ml::{binary::{self, BinaryReader, BinaryWriter, Compression}, envelope::ModelMetadata};
+use crate::model::ModelKind;
+
+use self::serde::{Serialize, Deserialize, de::DeserializeOwned};
+use std::{error::Error, str::FromStr, string::String, vec::Vec};
+use std::cmp::Ordering;
+
+static MAX_NEWTON_ITERATIONS: usize = 100;
+static MIN_PROBABILITY: f64 = 1e-12;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CalibrationMethod {
+    Platt,
+    Isotonic
+}
+
+impl FromStr for CalibrationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CalibrationMethod, String> {
+        match s {
+            "platt" => Ok(CalibrationMethod::Platt),
+            "isotonic" => Ok(CalibrationMethod::Isotonic),
+            _ => Err(format!("Unknown calibration method '{}'.", s))
+        }
+    }
+}
+
+/// A one-vs-rest mapping from a raw posterior to a calibrated probability.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Calibrator {
+    /// `1 / (1 + exp(a * logit(p) + b))`
+    Platt { a: f64, b: f64 },
+    /// Piecewise-linear non-decreasing fit through the pooled block means.
+    Isotonic { thresholds: Vec<f64>, values: Vec<f64> }
+}
+
+fn logit(p: f64) -> f64 {
+    let clipped: f64 = p.max(MIN_PROBABILITY).min(1.0 - MIN_PROBABILITY);
+    (clipped / (1.0 - clipped)).ln()
+}
+
+impl Calibrator {
+    pub fn fit(method: CalibrationMethod, scores: &[f64], targets: &[bool])
+            -> Calibrator {
+        match method {
+            CalibrationMethod::Platt => Calibrator::fit_platt(scores, targets),
+            CalibrationMethod::Isotonic => Calibrator::fit_isotonic(scores, targets)
+        }
+    }
+
+    /// Platt's sigmoid fit using Newton's method with backtracking, as
+    /// described by Lin, Lin & Weng (2007). Targets are smoothed towards
+    /// the class prior to avoid overfitting perfectly separated scores.
+    fn fit_platt(scores: &[f64], targets: &[bool]) -> Calibrator {
+        let positives: f64 = targets.iter().filter(|&&t| t).count() as f64;
+        let negatives: f64 = targets.len() as f64 - positives;
+
+        let hi: f64 = (positives + 1.0) / (positives + 2.0);
+        let lo: f64 = 1.0 / (negatives + 2.0);
+        let t: Vec<f64> = targets.iter()
+            .map(|&target| if target { hi } else { lo })
+            .collect::<Vec<f64>>();
+        let f: Vec<f64> = scores.iter().map(|&p| logit(p)).collect::<Vec<f64>>();
+
+        let objective = |a: f64, b: f64| -> f64 {
+            f.iter().zip(t.iter()).fold(0.0, |total, (&fi, &ti)| {
+                let z: f64 = fi * a + b;
+                // Numerically stable form of the cross entropy
+                if z >= 0.0 {
+                    total + ti * z + (1.0 + (-z).exp()).ln()
+                } else {
+                    total + (ti - 1.0) * z + (1.0 + z.exp()).ln()
+                }
+            })
+        };
+
+        let (mut a, mut b): (f64, f64) =
+            (0.0, ((negatives + 1.0) / (positives + 1.0)).ln());
+        let mut value: f64 = objective(a, b);
+
+        for _ in 0..MAX_NEWTON_ITERATIONS {
+            // Gradient and Hessian (with a small ridge for stability)
+            let (mut h11, mut h22, mut h21) = (1e-12, 1e-12, 0.0);
+            let (mut g1, mut g2) = (0.0, 0.0);
+
+            for (&fi, &ti) in f.iter().zip(t.iter()) {
+                let z: f64 = fi * a + b;
+                let (p, q): (f64, f64) = if z >= 0.0 {
+                    let e: f64 = (-z).exp();
+                    (e / (1.0 + e), 1.0 / (1.0 + e))
+                } else {
+                    let e: f64 = z.exp();
+                    (1.0 / (1.0 + e), e / (1.0 + e))
+                };
+
+                let d2: f64 = p * q;
+                h11 += fi * fi * d2;
+                h22 += d2;
+                h21 += fi * d2;
+
+                let d1: f64 = ti - p;
+                g1 += fi * d1;
+                g2 += d1;
+            }
+
+            if g1.abs() < 1e-5 && g2.abs() < 1e-5 {
+                break;
+            }
+
+            let det: f64 = h11 * h22 - h21 * h21;
+            let da: f64 = -(h22 * g1 - h21 * g2) / det;
+            let db: f64 = -(-h21 * g1 + h11 * g2) / det;
+            let gd: f64 = g1 * da + g2 * db;
+
+            let mut step: f64 = 1.0;
+            while step >= 1e-10 {
+                let candidate: f64 = objective(a + step * da, b + step * db);
+
+                if candidate < value + 1e-4 * step * gd {
+                    a += step * da;
+                    b += step * db;
+                    value = candidate;
+                    break;
+                }
+
+                step /= 2.0;
+            }
+
+            if step < 1e-10 {
+                break;
+            }
+        }
+
+        Calibrator::Platt { a, b }
+    }
+
+    /// Pool-adjacent-violators fit of a non-decreasing step function.
+    fn fit_isotonic(scores: &[f64], targets: &[bool]) -> Calibrator {
+        let mut pairs: Vec<(f64, f64)> = scores.iter().zip(targets.iter())
+            .map(|(&s, &t)| (s, if t { 1.0 } else { 0.0 }))
+            .collect::<Vec<(f64, f64)>>();
+        pairs.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal));
+
+        // Each block keeps (sum of scores, sum of targets, count)
+        let mut blocks: Vec<(f64, f64, f64)> = Vec::new();
+
+        for (score, target) in pairs.into_iter() {
+            blocks.push((score, target, 1.0));
+
+            while blocks.len() > 1 {
+                let last: (f64, f64, f64) = blocks[blocks.len() - 1];
+                let prev: (f64, f64, f64) = blocks[blocks.len() - 2];
+
+                if prev.1 / prev.2 < last.1 / last.2 {
+                    break;
+                }
+
+                blocks.pop();
+                let merged: usize = blocks.len() - 1;
+                blocks[merged] = (prev.0 + last.0, prev.1 + last.1, prev.2 + last.2);
+            }
+        }
+
+        Calibrator::Isotonic {
+            thresholds: blocks.iter().map(|b| b.0 / b.2).collect::<Vec<f64>>(),
+            values: blocks.iter().map(|b| b.1 / b.2).collect::<Vec<f64>>()
+        }
+    }
+
+    pub fn transform(&self, p: f64) -> f64 {
+        match self {
+            Calibrator::Platt { a, b } => 1.0 / (1.0 + (a * logit(p) + b).exp()),
+            Calibrator::Isotonic { thresholds, values } => {
+                if thresholds.is_empty() {
+                    return p;
+                }
+
+                // Clamp outside the fitted range, interpolate within it
+                let idx: usize = thresholds.iter().take_while(|&&x| x <= p).count();
+                if idx == 0 {
+                    values[0]
+                } else if idx == thresholds.len() {
+                    values[values.len() - 1]
+                } else {
+                    let (x0, x1) = (thresholds[idx - 1], thresholds[idx]);
+                    let (y0, y1) = (values[idx - 1], values[idx]);
+                    y0 + (y1 - y0) * (p - x0) / (x1 - x0)
+                }
+            }
+        }
+    }
+}
+
+/// Maps each class posterior through its calibrator and renormalizes.
+pub fn calibrate_posteriors(calibrators: &[Calibrator], posteriors: &[f64]) -> Vec<f64> {
+    let calibrated: Vec<f64> = calibrators.iter().zip(posteriors.iter())
+        .map(|(c, &p)| c.transform(p))
+        .collect::<Vec<f64>>();
+    let total: f64 = calibrated.iter().sum();
+
+    if total > 0.0 {
+        calibrated.iter().map(|p| p / total).collect::<Vec<f64>>()
+    } else {
+        posteriors.to_vec()
+    }
+}
+
+/// Fits one calibrator per class from held-out predictions.
+pub fn fit_calibrators(predictions: &[Prediction], num_labels: usize,
+        method: CalibrationMethod) -> Vec<Calibrator> {
+    (0..num_labels).map(|label| {
+        let scores: Vec<f64> = predictions.iter()
+            .map(|p| p.get_posteriors()[label])
+            .collect::<Vec<f64>>();
+        let targets: Vec<bool> = predictions.iter()
+            .map(|p| p.get_actual() == label)
+            .collect::<Vec<bool>>();
+
+        Calibrator::fit(method, &scores, &targets)
+    }).collect::<Vec<Calibrator>>()
+}
+
+/// One bin of a reliability diagram over top-class confidence.
+#[derive(Debug, Copy, Clone)]
+pub struct ReliabilityBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+    pub mean_confidence: f64,
+    pub accuracy: f64
+}
+
+/// Groups predictions into `num_bins` equal-width bins of confidence. No
+/// bins are returned when `num_bins` is 0.
+pub fn reliability_bins(predictions: &[Prediction], num_bins: usize)
+        -> Vec<ReliabilityBin> {
+    if num_bins == 0 {
+        return Vec::new();
+    }
+
+    let mut bins: Vec<ReliabilityBin> = (0..num_bins).map(|idx| ReliabilityBin {
+        lower: idx as f64 / num_bins as f64,
+        upper: (idx + 1) as f64 / num_bins as f64,
+        count: 0,
+        mean_confidence: 0.0,
+        accuracy: 0.0
+    }).collect::<Vec<ReliabilityBin>>();
+
+    for p in predictions.iter() {
+        let confidence: f64 = p.get_posteriors()[p.get_predicted()];
+        let idx: usize = ((confidence * num_bins as f64) as usize).min(num_bins - 1);
+        let bin: &mut ReliabilityBin = &mut bins[idx];
+
+        // Running means so each bin only needs a single pass
+        bin.count += 1;
+        bin.mean_confidence += (confidence - bin.mean_confidence) / bin.count as f64;
+        let correct: f64 = if p.is_correct() { 1.0 } else { 0.0 };
+        bin.accuracy += (correct - bin.accuracy) / bin.count as f64;
+    }
+
+    bins
+}
+
+/// Sample-weighted mean gap between confidence and accuracy across bins,
+/// or 0 when the bins are empty.
+pub fn expected_calibration_error(bins: &[ReliabilityBin]) -> f64 {
+    let total: usize = bins.iter().map(|b| b.count).sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    bins.iter().fold(0.0, |ece, b| {
+        ece + (b.count as f64 / total as f64) * (b.accuracy - b.mean_confidence).abs()
+    })
+}
+
+/// Calibration quality of the same held-out set before and after fitting.
+#[derive(Debug, Clone)]
+pub struct CalibrationReport {
+    pub before: Vec<ReliabilityBin>,
+    pub after: Vec<ReliabilityBin>
+}
+
+/// Wraps a model and recalibrates its posteriors with per-class mappings
+/// fitted on held-out data. The wrapped model's fields are flattened so an
+/// uncalibrated model file loads with no calibrators.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CalibratedModel<M> {
+    #[serde(flatten)]
+    model: M,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    calibrators: Vec<Calibrator>
+}
+
+impl<M: Model> CalibratedModel<M> {
+    pub fn new(model: M) -> CalibratedModel<M> {
+        CalibratedModel {
+            model,
+            calibrators: Vec::new()
+        }
+    }
+
+    pub fn get_model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn is_calibrated(&self) -> bool {
+        !self.calibrators.is_empty()
+    }
+
+    /// Drops the calibrators, so posteriors are the wrapped model's own.
+    pub fn clear_calibration(&mut self) {
+        self.calibrators.clear();
+    }
+
+    pub fn calibrate(&mut self, dataset: &Dataset, method: CalibrationMethod,
+            num_bins: usize) -> Result<CalibrationReport, ModelError> {
+        let raw: Vec<Prediction> = self.model.predict(dataset, true)?;
+        Ok(self.calibrate_predictions(&raw, method, num_bins))
+    }
+
+    /// Fits the calibrators to uncalibrated predictions on held-out data.
+    pub fn calibrate_predictions(&mut self, raw: &[Prediction], method: CalibrationMethod,
+            num_bins: usize) -> CalibrationReport {
+        self.calibrators = fit_calibrators(raw, self.model.num_labels(), method);
+
+        let calibrated: Vec<Prediction> = raw.iter()
+            .map(|p| Prediction::new(p.get_actual(),
+                calibrate_posteriors(&self.calibrators, p.get_posteriors())))
+            .collect::<Vec<Prediction>>();
+
+        CalibrationReport {
+            before: reliability_bins(raw, num_bins),
+            after: reliability_bins(&calibrated, num_bins)
+        }
+    }
+
+    fn apply(&self, posteriors: Vec<f64>) -> Vec<f64> {
+        if self.is_calibrated() {
+            calibrate_posteriors(&self.calibrators, &posteriors)
+        } else {
+            posteriors
+        }
+    }
+}
+
+impl<M: Model + Serialize + DeserializeOwned> Model for CalibratedModel<M> {
+    fn from_labels(file_path: &str) -> CalibratedModel<M> {
+        CalibratedModel::new(M::from_labels(file_path))
+    }
+
+    fn from_label_names(label_names: &[String]) -> CalibratedModel<M> {
+        CalibratedModel::new(M::from_label_names(label_names))
+    }
+
+    fn from_json(file_path: &str) -> CalibratedModel<M> {
+        model::read_json(file_path, M::kind())
+    }
+
+    fn to_json(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        model::write_json(self, &ModelMetadata::new(M::kind(), *self.get_hyperparameters()), file_path)
+    }
+
+    fn kind() -> ModelKind {
+        M::kind()
+    }
+
+    fn from_binary(file_path: &str) -> CalibratedModel<M> {
+        binary::read_binary(file_path, M::kind())
+    }
+
+    fn to_binary(&self, file_path: &str, compression: Compression) -> Result<(), Box<dyn Error>> {
+        let metadata: ModelMetadata = ModelMetadata::new(M::kind(), *self.get_hyperparameters());
+        binary::write_binary(self, &metadata, file_path, compression)
+    }
+
+    /// The wrapped model's fields, followed by the calibrators.
+    fn encode_binary(&self, writer: &mut BinaryWriter) {
+        self.model.encode_binary(writer);
+        writer.write_usize(self.calibrators.len());
+        for calibrator in self.calibrators.iter() {
+            match calibrator {
+                Calibrator::Platt { a, b } => {
+                    writer.write_u8(0);
+                    writer.write_floats(&[*a, *b]);
+                },
+                Calibrator::Isotonic { thresholds, values } => {
+                    writer.write_u8(1);
+                    writer.write_usize(thresholds.len());
+                    writer.write_floats(thresholds);
+                    writer.write_floats(values);
+                }
+            }
+        }
+    }
+
+    fn decode_binary(reader: &mut BinaryReader) -> Result<CalibratedModel<M>, ModelFileError> {
+        let model: M = M::decode_binary(reader)?;
+        let calibrators: Vec<Calibrator> = (0..reader.read_count()?)
+            .map(|_| match reader.read_u8()? {
+                0 => Ok(Calibrator::Platt { a: reader.read_f64()?, b: reader.read_f64()? }),
+                1 => {
+                    let len: usize = reader.read_count()?;
+                    Ok(Calibrator::Isotonic {
+                        thresholds: reader.read_floats(len)?,
+                        values: reader.read_floats(len)?
+                    })
+                },
+                _ => Err(ModelFileError::InvalidEncoding)
+            })
+            .collect::<Result<Vec<Calibrator>, ModelFileError>>()?;
+
+        Ok(CalibratedModel {
+            model,
+            calibrators
+        })
+    }
+
+    /// The mapped layout has no room for calibrators, so only uncalibrated
+    /// models can be saved in it.
+    fn to_mapped(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        if self.is_calibrated() {
+            return Err(Box::new(ModelFileError::UnsupportedLayout));
+        }
+
+        self.model.to_mapped(file_path)
+    }
+
+    fn to_onnx(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        if self.is_calibrated() {
+            return Err(Box::new(ModelFileError::UnsupportedLayout));
+        }
+
+        self.model.to_onnx(file_path)
+    }
+
+    /// PMML priors and likelihoods cannot express calibration, so only
+    /// uncalibrated models can be exported.
+    fn to_pmml(&self) -> Result<PmmlModel, Box<dyn Error>> {
+        if self.is_calibrated() {
+            return Err(Box::new(PmmlError::UnsupportedModel));
+        }
+
+        self.model.to_pmml()
+    }
+
+    fn from_pmml(pmml: &PmmlModel) -> Result<CalibratedModel<M>, Box<dyn Error>> {
+        Ok(CalibratedModel::new(M::from_pmml(pmml)?))
+    }
+
+    fn num_labels(&self) -> usize {
+        self.model.num_labels()
+    }
+
+    fn label_names(&self) -> Vec<String> {
+        self.model.label_names()
+    }
+
+    /// The terms of the underlying model; calibration only rescales the
+    /// posteriors they add up to.
+    fn log_likelihood_terms(&self, sample_features: &[f64])
+            -> Result<(Vec<f64>, Vec<Vec<f64>>), ModelError> {
+        self.model.log_likelihood_terms(sample_features)
+    }
+
+    fn summary(&self) -> Result<ModelSummary, ModelError> {
+        Ok(ModelSummary { calibrated: self.is_calibrated(), ..self.model.summary()? })
+    }
+
+    fn get_hyperparameters(&self) -> &Hyperparameters {
+        self.model.get_hyperparameters()
+    }
+
+    fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters) {
+        self.model.set_hyperparameters(hyperparameters);
+    }
+
+    fn fit(&mut self, dataset: &Dataset) -> Result<(), Box<dyn Error>> {
+        // Calibrators fitted on the previous model no longer apply
+        self.calibrators.clear();
+        self.model.fit(dataset)
+    }
+
+    fn predict(&self, dataset: &Dataset, multithreaded: bool)
+            -> Result<Vec<Prediction>, ModelError> {
+        let raw: Vec<Prediction> = self.model.predict(dataset, multithreaded)?;
+
+        Ok(raw.into_iter()
+            .map(|p| {
+                let actual: usize = p.get_actual();
+                Prediction::new(actual, self.apply(p.get_posteriors().to_vec()))
+            })
+            .collect::<Vec<Prediction>>())
+    }
+
+    fn classify(&self, sample_features: &[f64]) -> Result<Box<dyn Label>, ModelError> {
+        if !self.is_calibrated() {
+            return self.model.classify(sample_features);
+        }
+
+        // Calibration can reorder classes, so take the calibrated argmax
+        let posteriors: Vec<f64> = self.posteriors(sample_features)?;
+        self.model.label(prediction::argmax(&posteriors))
+    }
+
+    fn posteriors(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        Ok(self.apply(self.model.posteriors(sample_features)?))
+    }
+
+    fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError> {
+        self.model.label(index)
+    }
+}
+
+impl<M: SparseModel + Serialize + DeserializeOwned> SparseModel for CalibratedModel<M> {
+    fn fit_sparse(&mut self, dataset: &SparseDataset) -> Result<(), Box<dyn Error>> {
+        self.calibrators.clear();
+        self.model.fit_sparse(dataset)
+    }
+
+    fn sparse_posteriors(&self, rows: &[SparseRow]) -> Result<Vec<Vec<f64>>, ModelError> {
+        Ok(self.model.sparse_posteriors(rows)?.into_iter()
+            .map(|posteriors| self.apply(posteriors))
+            .collect::<Vec<Vec<f64>>>())
+    }
+}
+
+#[cfg(test)]
+mod calibration_tests {
+    use crate::ml::calibration::*;
+
+    #[test]
+    fn test_isotonic_is_monotonic() {
+        let scores: Vec<f64> = vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8];
+        let targets: Vec<bool> = vec![false, true, false, false, true, false, true, true];
+        let calibrator: Calibrator =
+            Calibrator::fit(CalibrationMethod::Isotonic, &scores, &targets);
+
+        let mapped: Vec<f64> = scores.iter().map(|&s| calibrator.transform(s)).collect();
+        assert!(mapped.windows(2).all(|w| w[0] <= w[1]));
+        assert_relative_eq!(calibrator.transform(0.0), 0.0);
+        assert_relative_eq!(calibrator.transform(1.0), 1.0);
+    }
+
+    #[test]
+    fn test_platt_shrinks_overconfidence() {
+        // Scores are always near-certain but only right 75% of the time
+        let scores: Vec<f64> = (0..40).map(|i| if i % 2 == 0 { 0.999 } else { 0.001 }).collect();
+        let targets: Vec<bool> = (0..40).map(|i| (i % 2 == 0) != (i % 8 == 0 || i % 8 == 1)).collect();
+        let calibrator: Calibrator =
+            Calibrator::fit(CalibrationMethod::Platt, &scores, &targets);
+
+        assert_relative_eq!(calibrator.transform(0.999), 0.75, epsilon = 0.05);
+        assert_relative_eq!(calibrator.transform(0.001), 0.25, epsilon = 0.05);
+    }
+
+    #[test]
+    fn test_expected_calibration_error() {
+        let predictions: Vec<Prediction> = vec![
+            Prediction::new(0, vec![0.9, 0.1]),
+            Prediction::new(1, vec![0.9, 0.1]),
+            Prediction::new(1, vec![0.4, 0.6]),
+            Prediction::new(1, vec![0.4, 0.6])
+        ];
+
+        let bins: Vec<ReliabilityBin> = reliability_bins(&predictions, 10);
+        assert_eq!(bins[9].count, 2);
+        assert_relative_eq!(bins[9].accuracy, 0.5);
+        assert_relative_eq!(expected_calibration_error(&bins), 0.5 * 0.4 + 0.5 * 0.4);
+
+        assert!(reliability_bins(&predictions, 0).is_empty());
+        assert_eq!(expected_calibration_error(&reliability_bins(&[], 10)), 0.0);
+    }
+}
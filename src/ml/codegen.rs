@@ -0,0 +1,332 @@
+use crate::model::gaussian_nb::GaussianParameters;
+use std::{fmt::Write, string::String, vec::Vec};
+
+// The generated `classify` scores each feature in log space from the
+// inverse variances. The trained model takes the log of each density
+// instead, so a density too small for an f64, common with the tiny
+// deviations of blank image pixels, underflows to zero and rules its class
+// out. The generated code rules out the same densities by comparing their
+// logs with that of the smallest density an f64 holds, so it picks the
+// same classes.
+
+/// A Rust literal for the value, including the non-finite ones.
+fn rust_float(value: f64) -> String {
+    if value.is_nan() {
+        String::from("f64::NAN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "f64::INFINITY" } else { "f64::NEG_INFINITY" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// A C literal for the value, using the `<math.h>` macros for the
+/// non-finite ones.
+fn c_float(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NAN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "INFINITY" } else { "-INFINITY" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn c_string(s: &str) -> String {
+    let escaped: String = s.chars().map(|c| match c {
+        '\\' => String::from("\\\\"),
+        '"' => String::from("\\\""),
+        '\n' => String::from("\\n"),
+        c => c.to_string()
+    }).collect::<String>();
+    format!("\"{}\"", escaped)
+}
+
+fn join(values: &[f64], literal: fn(f64) -> String) -> String {
+    values.iter().map(|&value| literal(value)).collect::<Vec<String>>().join(", ")
+}
+
+/// `1 / std^2` of every feature in each class.
+fn inverse_variances(parameters: &GaussianParameters) -> Vec<Vec<f64>> {
+    parameters.stds.iter()
+        .map(|stds| stds.iter().map(|std| 1.0 / std.powi(2)).collect::<Vec<f64>>())
+        .collect::<Vec<Vec<f64>>>()
+}
+
+/// `log10(1 / (std * sqrt(2 pi)))`, the log of the factor in front of
+/// every density.
+fn log_normalizers(parameters: &GaussianParameters) -> Vec<Vec<f64>> {
+    parameters.stds.iter()
+        .map(|stds| stds.iter()
+            .map(|std| -(std * (2.0 * std::f64::consts::PI).sqrt()).log10())
+            .collect::<Vec<f64>>())
+        .collect::<Vec<Vec<f64>>>()
+}
+
+/// `0.5 * log10(e)`, which turns the squared deviation over the variance
+/// into the log10 of the density's exponential.
+fn half_log10_e() -> f64 {
+    0.5 * std::f64::consts::LOG10_E
+}
+
+/// The log10 of half the smallest subnormal f64. Densities below it round
+/// to zero.
+fn min_log10_density() -> f64 {
+    f64::from_bits(1).log10() - 2f64.log10()
+}
+
+fn num_features(parameters: &GaussianParameters) -> usize {
+    parameters.means.first().map_or(0, |means| means.len())
+}
+
+/// A self-contained Rust module whose `classify` returns the same class
+/// index as the model's `classify` for every sample.
+pub fn rust_module(parameters: &GaussianParameters) -> String {
+    let mut code: String = String::new();
+    let matrix = |code: &mut String, name: &str, doc: &str, rows: &[Vec<f64>]| {
+        let _ = writeln!(code, "/// {}", doc);
+        let _ = writeln!(code, "pub const {}: [[f64; NUM_FEATURES]; NUM_CLASSES] = [", name);
+        for row in rows.iter() {
+            let _ = writeln!(code, "    [{}],", join(row, rust_float));
+        }
+        let _ = writeln!(code, "];\n");
+    };
+
+    // Writing to a String cannot fail
+    let _ = writeln!(code, "//! Gaussian naive Bayes classifier generated by {} {}.",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(code, "//!\n//! `classify` returns the same classes as the model it was generated from.\n");
+    let _ = writeln!(code, "pub const NUM_CLASSES: usize = {};", parameters.labels.len());
+    let _ = writeln!(code, "pub const NUM_FEATURES: usize = {};\n", num_features(parameters));
+    let _ = writeln!(code, "/// Class names, indexed as returned by `classify`.");
+    let _ = writeln!(code, "pub const LABELS: [&str; NUM_CLASSES] = [{}];\n",
+        parameters.labels.iter().map(|label| format!("{:?}", label)).collect::<Vec<String>>().join(", "));
+    let _ = writeln!(code, "/// Log10 prior of each class.");
+    let _ = writeln!(code, "pub const LOG_PRIORS: [f64; NUM_CLASSES] = [{}];\n",
+        join(&parameters.log_priors, rust_float));
+    matrix(&mut code, "MEANS", "Mean of every feature in each class.", &parameters.means);
+    matrix(&mut code, "INV_VARIANCES", "`1 / std^2` of every feature in each class.",
+        &inverse_variances(parameters));
+    matrix(&mut code, "LOG_NORMALIZERS", "`log10(1 / (std * sqrt(2 pi)))` of every feature in each class.",
+        &log_normalizers(parameters));
+    let _ = writeln!(code, "/// `0.5 * log10(e)`.");
+    let _ = writeln!(code, "const HALF_LOG10_E: f64 = {};
+", rust_float(half_log10_e()));
+    let _ = writeln!(code, "/// Densities whose log10 is below this are too small for an f64 and");
+    let _ = writeln!(code, "/// rule their class out, as they do in the trained model.");
+    let _ = writeln!(code, "const MIN_LOG10_DENSITY: f64 = {};
+", rust_float(min_log10_density()));
+
+    let _ = writeln!(code, "/// Index into `LABELS` of the most likely class. Missing (NaN) features");
+    let _ = writeln!(code, "/// are skipped.\n///\n/// Panics unless there are `NUM_FEATURES` features.");
+    let _ = writeln!(code, "#[allow(clippy::needless_range_loop)]");
+    let _ = writeln!(code, "pub fn classify(features: &[f64]) -> usize {{");
+    let _ = writeln!(code, "    assert_eq!(features.len(), NUM_FEATURES, \"expected {{}} features\", NUM_FEATURES);\n");
+    let _ = writeln!(code, "    let mut scores: [f64; NUM_CLASSES] = [0.0; NUM_CLASSES];");
+    let _ = writeln!(code, "    for class in 0..NUM_CLASSES {{");
+    let _ = writeln!(code, "        let mut total: f64 = 0.0;");
+    let _ = writeln!(code, "        for feature in 0..NUM_FEATURES {{");
+    let _ = writeln!(code, "            let value: f64 = features[feature];");
+    let _ = writeln!(code, "            if value.is_nan() {{\n                continue;\n            }}");
+    if let Some(threshold) = parameters.binarize {
+        let _ = writeln!(code, "            let value: f64 = if value > {} {{ 1.0 }} else {{ 0.0 }};",
+            rust_float(threshold));
+    }
+    let _ = writeln!(code, "            let deviation: f64 = value - MEANS[class][feature];");
+    let _ = writeln!(code, "            let density: f64 = LOG_NORMALIZERS[class][feature]");
+    let _ = writeln!(code, "                - HALF_LOG10_E * deviation * deviation * INV_VARIANCES[class][feature];");
+    let _ = writeln!(code, "            total += if density < MIN_LOG10_DENSITY {{ f64::NEG_INFINITY }} else {{ density }};");
+    let _ = writeln!(code, "        }}");
+    let _ = writeln!(code, "        scores[class] = total + LOG_PRIORS[class];");
+    let _ = writeln!(code, "    }}\n");
+    let _ = writeln!(code, "    let mut best: usize = 0;");
+    let _ = writeln!(code, "    for class in 1..NUM_CLASSES {{");
+    let _ = writeln!(code, "        if scores[class] > scores[best] {{\n            best = class;\n        }}");
+    let _ = writeln!(code, "    }}");
+    let _ = writeln!(code, "    best");
+    let _ = writeln!(code, "}}");
+
+    code
+}
+
+/// A C header declaring `nb_classify` and the class names, guarded by
+/// `guard`.
+pub fn c_header(parameters: &GaussianParameters, guard: &str) -> String {
+    let mut code: String = String::new();
+
+    let _ = writeln!(code, "/* Gaussian naive Bayes classifier generated by {} {}. */\n",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(code, "#ifndef {}\n#define {}\n", guard, guard);
+    let _ = writeln!(code, "#include <stddef.h>\n");
+    let _ = writeln!(code, "#define NB_NUM_CLASSES {}", parameters.labels.len());
+    let _ = writeln!(code, "#define NB_NUM_FEATURES {}\n", num_features(parameters));
+    let _ = writeln!(code, "/* Class names, indexed as returned by nb_classify. */");
+    let _ = writeln!(code, "extern const char *const NB_LABELS[NB_NUM_CLASSES];\n");
+    let _ = writeln!(code, "/* Index into NB_LABELS of the most likely class of NB_NUM_FEATURES");
+    let _ = writeln!(code, " * features. Missing (NaN) features are skipped. */");
+    let _ = writeln!(code, "size_t nb_classify(const double *features);\n");
+    let _ = writeln!(code, "#endif");
+
+    code
+}
+
+/// The C source defining what `c_header` declares, including the header
+/// by the given name.
+pub fn c_source(parameters: &GaussianParameters, header_name: &str) -> String {
+    let mut code: String = String::new();
+    let matrix = |code: &mut String, name: &str, rows: &[Vec<f64>]| {
+        let _ = writeln!(code, "static const double {}[NB_NUM_CLASSES][NB_NUM_FEATURES] = {{", name);
+        for row in rows.iter() {
+            let _ = writeln!(code, "    {{{}}},", join(row, c_float));
+        }
+        let _ = writeln!(code, "}};\n");
+    };
+
+    let _ = writeln!(code, "/* Gaussian naive Bayes classifier generated by {} {}. */\n",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(code, "#include <math.h>\n#include {}\n", c_string(header_name));
+    let _ = writeln!(code, "const char *const NB_LABELS[NB_NUM_CLASSES] = {{{}}};\n",
+        parameters.labels.iter().map(|label| c_string(label)).collect::<Vec<String>>().join(", "));
+    let _ = writeln!(code, "static const double LOG_PRIORS[NB_NUM_CLASSES] = {{{}}};\n",
+        join(&parameters.log_priors, c_float));
+    matrix(&mut code, "MEANS", &parameters.means);
+    matrix(&mut code, "INV_VARIANCES", &inverse_variances(parameters));
+    matrix(&mut code, "LOG_NORMALIZERS", &log_normalizers(parameters));
+    let _ = writeln!(code, "static const double HALF_LOG10_E = {};", c_float(half_log10_e()));
+    let _ = writeln!(code, "/* Densities whose log10 is below this round to zero in the trained model. */");
+    let _ = writeln!(code, "static const double MIN_LOG10_DENSITY = {};\n", c_float(min_log10_density()));
+
+    let _ = writeln!(code, "size_t nb_classify(const double *features) {{");
+    let _ = writeln!(code, "    double scores[NB_NUM_CLASSES];");
+    let _ = writeln!(code, "    size_t best = 0;\n");
+    let _ = writeln!(code, "    for (size_t class = 0; class < NB_NUM_CLASSES; class++) {{");
+    let _ = writeln!(code, "        double total = 0.0;");
+    let _ = writeln!(code, "        for (size_t feature = 0; feature < NB_NUM_FEATURES; feature++) {{");
+    let _ = writeln!(code, "            double value = features[feature];");
+    let _ = writeln!(code, "            if (isnan(value)) {{\n                continue;\n            }}");
+    if let Some(threshold) = parameters.binarize {
+        let _ = writeln!(code, "            value = value > {} ? 1.0 : 0.0;", c_float(threshold));
+    }
+    let _ = writeln!(code, "            double deviation = value - MEANS[class][feature];");
+    let _ = writeln!(code, "            double density = LOG_NORMALIZERS[class][feature]");
+    let _ = writeln!(code, "                - HALF_LOG10_E * deviation * deviation * INV_VARIANCES[class][feature];");
+    let _ = writeln!(code, "            total += density < MIN_LOG10_DENSITY ? -INFINITY : density;");
+    let _ = writeln!(code, "        }}");
+    let _ = writeln!(code, "        scores[class] = total + LOG_PRIORS[class];");
+    let _ = writeln!(code, "        if (scores[class] > scores[best]) {{\n            best = class;\n        }}");
+    let _ = writeln!(code, "    }}\n");
+    let _ = writeln!(code, "    return best;");
+    let _ = writeln!(code, "}}");
+
+    code
+}
+
+#[cfg(test)]
+mod codegen_tests {
+    use crate::ml::codegen::*;
+    use crate::ml::{dataset::Dataset, hyperparameters::Hyperparameters, model::Model, temp_path};
+    use crate::model::gaussian_nb::GaussianNaiveBayes;
+    use std::{env, fs, path::PathBuf, process::Command};
+
+    fn samples() -> Vec<Vec<f64>> {
+        vec![vec![1.2, 1.8, 0.0], vec![8.5, f64::NAN, 1.0], vec![5.0, 5.0, 0.5], vec![40.0, -3.0, 9.0]]
+    }
+
+    fn model(binarize: Option<f64>) -> GaussianNaiveBayes {
+        let names: Vec<String> = vec![String::from("low"), String::from("mid \"m\""), String::from("high")];
+        let dataset: Dataset = Dataset::with_label_names(names, vec![0, 0, 0, 1, 1, 2, 2, 2], vec![
+            vec![1.0, 2.0, 0.0], vec![2.0, 1.0, 0.0], vec![1.5, f64::NAN, 0.0],
+            vec![4.0, 5.0, 1.0], vec![6.0, 5.0, 0.0],
+            vec![8.0, 9.0, 1.0], vec![9.0, 7.0, 1.0], vec![7.5, 8.0, 1.0]
+        ]);
+
+        let mut model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        model.set_hyperparameters(Hyperparameters { binarize, ..Hyperparameters::default() });
+        model.fit(&dataset).unwrap();
+        model
+    }
+
+    fn expected(model: &GaussianNaiveBayes) -> Vec<String> {
+        samples().iter()
+            .map(|sample| model.classify(sample).unwrap().get_index().to_string())
+            .collect::<Vec<String>>()
+    }
+
+    /// Whether the compiler can be run. Machines without one skip the test
+    /// rather than fail it.
+    fn has_compiler(compiler: &str) -> bool {
+        let found: bool = Command::new(compiler).arg("--version").output().is_ok();
+        if !found {
+            eprintln!("Skipping: {} could not be run.", compiler);
+        }
+        found
+    }
+
+    fn run(command: &mut Command) -> Vec<String> {
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().lines().map(String::from).collect::<Vec<String>>()
+    }
+
+    #[test]
+    fn test_rust_module_matches_classify() {
+        let rustc: String = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+        if !has_compiler(&rustc) {
+            return;
+        }
+
+        for (idx, binarize) in [None, Some(3.0)].iter().enumerate() {
+            let model: GaussianNaiveBayes = model(*binarize);
+            let dir: PathBuf = temp_path(&format!("codegen_rust_test_{}", idx));
+            fs::create_dir_all(&dir).unwrap();
+
+            let rows: String = samples().iter()
+                .map(|sample| format!("&[{}]", join(sample, rust_float)))
+                .collect::<Vec<String>>().join(", ");
+            let main: String = format!("mod classifier;\n\nfn main() {{\n    \
+                let samples: [&[f64]; {}] = [{}];\n    \
+                for sample in samples.iter() {{\n        println!(\"{{}}\", classifier::classify(sample));\n    }}\n}}\n",
+                samples().len(), rows);
+            fs::write(dir.join("classifier.rs"), rust_module(&model.parameters().unwrap())).unwrap();
+            fs::write(dir.join("main.rs"), main).unwrap();
+
+            run(Command::new(&rustc).arg("--edition=2018").arg("-o").arg(dir.join("classifier"))
+                .arg(dir.join("main.rs")));
+            assert_eq!(run(&mut Command::new(dir.join("classifier"))), expected(&model));
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_c_source_matches_classify() {
+        if !has_compiler("cc") {
+            return;
+        }
+
+        for (idx, binarize) in [None, Some(3.0)].iter().enumerate() {
+            let model: GaussianNaiveBayes = model(*binarize);
+            let dir: PathBuf = temp_path(&format!("codegen_c_test_{}", idx));
+            fs::create_dir_all(&dir).unwrap();
+
+            let rows: String = samples().iter()
+                .map(|sample| format!("{{{}}}", join(sample, c_float)))
+                .collect::<Vec<String>>().join(", ");
+            let main: String = format!("#include <math.h>\n#include <stdio.h>\n#include \"classifier.h\"\n\n\
+                int main(void) {{\n    \
+                double samples[{}][NB_NUM_FEATURES] = {{{}}};\n    \
+                for (size_t i = 0; i < {}; i++) {{\n        printf(\"%zu\\n\", nb_classify(samples[i]));\n    }}\n    \
+                return 0;\n}}\n", samples().len(), rows, samples().len());
+            let parameters: GaussianParameters = model.parameters().unwrap();
+            fs::write(dir.join("classifier.h"), c_header(&parameters, "CLASSIFIER_H")).unwrap();
+            fs::write(dir.join("classifier.c"), c_source(&parameters, "classifier.h")).unwrap();
+            fs::write(dir.join("main.c"), main).unwrap();
+
+            run(Command::new("cc").arg("-std=c99").arg("-o").arg(dir.join("classifier"))
+                .arg(dir.join("main.c")).arg(dir.join("classifier.c")).arg("-lm"));
+            assert_eq!(run(&mut Command::new(dir.join("classifier"))), expected(&model));
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
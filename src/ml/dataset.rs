@@ -0,0 +1,519 @@
+extern crate num_traits;
+extern crate csv;
+extern crate serde;
+
+use crate::ml::{error::DatasetError, label, idx, npy, sparse::SparseDataset, arff::ArffFile};
+use self::num_traits::ToPrimitive;
+use self::serde::{Serialize, Deserialize};
+use std::{error::Error, fs, str::FromStr, string::String, vec::Vec};
+
+/// Identifies a column either by its zero-based position or its header name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String)
+}
+
+impl FromStr for ColumnRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColumnRef, String> {
+        match s.parse::<usize>() {
+            Ok(index) => Ok(ColumnRef::Index(index)),
+            Err(_) => Ok(ColumnRef::Name(s.to_string()))
+        }
+    }
+}
+
+impl ColumnRef {
+    fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<usize, DatasetError> {
+        match self {
+            ColumnRef::Index(index) => Ok(*index),
+            ColumnRef::Name(name) => headers
+                .and_then(|h| h.iter().position(|header| header == name))
+                .ok_or(DatasetError::UnknownColumn)
+        }
+    }
+}
+
+/// The file format data is read from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DataFormat {
+    /// Delimited text laid out as described by the rest of the spec.
+    Csv,
+    /// MNIST style IDX files, with images and labels in separate files.
+    Idx,
+    /// LIBSVM / svmlight sparse lines of `label index:value` pairs.
+    Libsvm,
+    /// Weka ARFF files, whose header declares each attribute's type.
+    Arff,
+    /// NumPy `.npy` feature and label arrays, or an `.npz` holding both.
+    Npy
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DataFormat, String> {
+        match s {
+            "csv" => Ok(DataFormat::Csv),
+            "idx" => Ok(DataFormat::Idx),
+            "libsvm" | "svmlight" => Ok(DataFormat::Libsvm),
+            "arff" => Ok(DataFormat::Arff),
+            "npy" | "npz" => Ok(DataFormat::Npy),
+            _ => Err(format!("Unknown data format '{}'.", s))
+        }
+    }
+}
+
+/// Describes how a delimited text file is laid out so that training,
+/// testing and prediction all read rows the same way.
+#[derive(Debug, Clone)]
+pub struct DatasetSpec {
+    pub format: DataFormat,
+    /// Whether the first line holds column names rather than a sample.
+    pub has_header: bool,
+    /// The column holding the class name, or `None` for unlabelled data.
+    /// ARFF data takes the last attribute as the class when this is `None`.
+    pub label: Option<ColumnRef>,
+    pub delimiter: u8,
+    /// Whether fields may be wrapped in double quotes.
+    pub quoting: bool,
+    /// Columns that are neither the label nor a feature, such as row ids.
+    pub ignore: Vec<ColumnRef>,
+    /// Lines starting with this byte are skipped.
+    pub comment: Option<u8>,
+    /// Feature cells holding one of these tokens are missing and read as NaN.
+    pub missing: Vec<String>,
+    /// Whether LIBSVM feature indices start at 0 rather than 1.
+    pub zero_based: bool,
+    /// The number of features LIBSVM rows have, when it is not the largest
+    /// index in the file, such as a test file for a wider model.
+    pub num_features: Option<usize>
+}
+
+/// Cells read as missing unless the spec lists its own tokens.
+pub static DEFAULT_MISSING: [&str; 4] = ["", "?", "NA", "NaN"];
+
+impl Default for DatasetSpec {
+    fn default() -> DatasetSpec {
+        DatasetSpec {
+            format: DataFormat::Csv,
+            has_header: true,
+            label: Some(ColumnRef::Index(0)),
+            delimiter: b',',
+            quoting: true,
+            ignore: Vec::new(),
+            comment: None,
+            missing: DEFAULT_MISSING.iter().map(|token| token.to_string()).collect::<Vec<String>>(),
+            zero_based: false,
+            num_features: None
+        }
+    }
+}
+
+impl DatasetSpec {
+    fn reader(&self, file_path: &str) -> Result<csv::Reader<fs::File>, csv::Error> {
+        csv::ReaderBuilder::new()
+            .has_headers(self.has_header)
+            .delimiter(self.delimiter)
+            .quoting(self.quoting)
+            .comment(self.comment)
+            .trim(csv::Trim::All)
+            .from_path(file_path)
+    }
+
+    /// Returns the label column index and the sorted feature column indices
+    /// for records with `width` columns.
+    fn layout(&self, headers: Option<&csv::StringRecord>, width: usize)
+            -> Result<(Option<usize>, Vec<usize>), DatasetError> {
+        let label: Option<usize> = match &self.label {
+            Some(column) => Some(column.resolve(headers)?),
+            None => None
+        };
+        let ignored: Vec<usize> = self.ignore.iter()
+            .map(|column| column.resolve(headers))
+            .collect::<Result<Vec<usize>, DatasetError>>()?;
+
+        if label.is_some_and(|idx| idx >= width) {
+            return Err(DatasetError::UnknownColumn);
+        }
+
+        let features: Vec<usize> = (0..width)
+            .filter(|idx| Some(*idx) != label && !ignored.contains(idx))
+            .collect::<Vec<usize>>();
+
+        Ok((label, features))
+    }
+
+    fn parse_features<Num: ToPrimitive + Copy + FromStr>
+            (&self, record: &csv::StringRecord, columns: &[usize]) -> Result<Vec<f64>, DatasetError> {
+        columns.iter()
+            .map(|&idx| {
+                let cell: &str = record.get(idx).ok_or(DatasetError::InconsistentRow)?;
+                if self.missing.iter().any(|token| token == cell) {
+                    return Ok(f64::NAN);
+                }
+
+                cell.parse::<Num>().ok()
+                    .and_then(|value| value.to_f64())
+                    .ok_or(DatasetError::InvalidValue)
+            })
+            .collect::<Result<Vec<f64>, DatasetError>>()
+    }
+
+    /// Reads LIBSVM data, widened to the spec's number of features if set.
+    pub fn read_sparse(&self, file_path: &str) -> Result<SparseDataset, Box<dyn Error>> {
+        let mut dataset: SparseDataset = SparseDataset::from_libsvm(file_path, self.zero_based)?;
+        if let Some(num_features) = self.num_features {
+            dataset.widen(num_features)?;
+        }
+
+        Ok(dataset)
+    }
+
+    /// The files the data at `file_path` is read from, including the labels
+    /// file IDX images are paired with.
+    pub fn source_paths(&self, file_path: &str) -> Vec<String> {
+        match (self.format, idx::split_paths(file_path)) {
+            (DataFormat::Idx, Ok((images, labels))) => vec![images, labels],
+            _ => file_path.split(',').map(String::from).collect::<Vec<String>>()
+        }
+    }
+
+    /// Reads every labelled record. Feature cells are parsed as `Num` before
+    /// being widened to `f64`. Class names are discovered from the label
+    /// column and indexed in sorted order.
+    pub fn read<Num: ToPrimitive + Copy + FromStr>(&self, file_path: &str)
+            -> Result<Dataset, Box<dyn Error>> {
+        match self.format {
+            DataFormat::Idx => return idx::read_dataset(file_path),
+            // Expanded for callers that need dense rows; see `SparseModel`
+            DataFormat::Libsvm => return Ok(self.read_sparse(file_path)?.to_dense()?),
+            DataFormat::Arff => 
+                return Ok(ArffFile::from_file(file_path)?.to_dataset(self.label.as_ref())?),
+            DataFormat::Npy => return npy::read_dataset(file_path),
+            DataFormat::Csv => {}
+        }
+        if self.label.is_none() {
+            return Err(Box::new(DatasetError::MissingLabel));
+        }
+
+        let mut rdr = self.reader(file_path)?;
+        let headers: Option<csv::StringRecord> =
+            if self.has_header { Some(rdr.headers()?.clone()) } else { None };
+
+        let mut dataset: Dataset = Dataset::default();
+        let mut layout: Option<(Option<usize>, Vec<usize>)> = None;
+
+        for result in rdr.records() {
+            // iterator yields Result<StringRecord, Error>, so check error here.
+            let record = result?;
+
+            // The column layout is fixed by the first record we see
+            if layout.is_none() {
+                layout = Some(self.layout(headers.as_ref(), record.len())?);
+            }
+            let (label_column, feature_columns) = layout.as_ref().unwrap();
+
+            let label: &str = record.get(label_column.unwrap())
+                .ok_or(DatasetError::MissingLabel)?;
+            if label.is_empty() {
+                return Err(Box::new(DatasetError::InvalidLabel));
+            }
+            let sample: Vec<f64> = self.parse_features::<Num>(&record, feature_columns)?;
+
+            dataset.push_named(label, sample)?;
+        }
+
+        dataset.sort_label_names();
+        Ok(dataset)
+    }
+
+    /// Reads only the feature columns of each record, skipping the label
+    /// column if the spec names one.
+    pub fn read_samples<Num: ToPrimitive + Copy + FromStr>(&self, file_path: &str)
+            -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+        match self.format {
+            DataFormat::Idx => return idx::read_samples(file_path),
+            DataFormat::Libsvm => {
+                let dataset: SparseDataset = self.read_sparse(file_path)?;
+                return Ok(dataset.get_rows().iter()
+                    .map(|row| row.to_dense(dataset.num_features()))
+                    .collect::<Vec<Vec<f64>>>());
+            },
+            DataFormat::Arff => 
+                return Ok(ArffFile::from_file(file_path)?.to_samples(self.label.as_ref())?),
+            DataFormat::Npy => return npy::read_samples(file_path),
+            DataFormat::Csv => {}
+        }
+
+        let mut rdr = self.reader(file_path)?;
+        let headers: Option<csv::StringRecord> =
+            if self.has_header { Some(rdr.headers()?.clone()) } else { None };
+
+        let mut samples: Vec<Vec<f64>> = Vec::new();
+        let mut feature_columns: Option<Vec<usize>> = None;
+
+        for result in rdr.records() {
+            let record = result?;
+
+            if feature_columns.is_none() {
+                feature_columns = Some(self.layout(headers.as_ref(), record.len())?.1);
+            }
+
+            samples.push(self.parse_features::<Num>(
+                &record, feature_columns.as_ref().unwrap())?);
+        }
+
+        Ok(samples)
+    }
+}
+
+/// How the values of a feature are to be modelled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureType {
+    /// Continuous values.
+    Numeric,
+    /// Values are indices into the listed category names.
+    Nominal(Vec<String>)
+}
+
+/// Labelled samples held in memory, one row of feature values per sample.
+/// Each label is an index into the dataset's list of class names. Missing
+/// feature values are stored as NaN.
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    label_names: Vec<String>,
+    /// The type of each feature when the source declares one, else empty.
+    feature_types: Vec<FeatureType>,
+    labels: Vec<usize>,
+    samples: Vec<Vec<f64>>
+}
+
+impl Dataset {
+    /// Creates a dataset whose class names are the label indices themselves.
+    pub fn new(labels: Vec<usize>, samples: Vec<Vec<f64>>) -> Dataset {
+        let num_labels: usize = labels.iter().max().map_or(0, |&max| max + 1);
+
+        Dataset {
+            label_names: (0..num_labels).map(|idx| idx.to_string()).collect::<Vec<String>>(),
+            feature_types: Vec::new(),
+            labels,
+            samples
+        }
+    }
+
+    pub fn with_label_names(label_names: Vec<String>, labels: Vec<usize>, samples: Vec<Vec<f64>>)
+            -> Dataset {
+        Dataset {
+            label_names,
+            feature_types: Vec::new(),
+            labels,
+            samples
+        }
+    }
+
+    /// Declares the type of each feature.
+    pub fn set_feature_types(&mut self, feature_types: Vec<FeatureType>) {
+        self.feature_types = feature_types;
+    }
+
+    /// The declared type of each feature, or empty if none were declared.
+    pub fn get_feature_types(&self) -> &[FeatureType] {
+        &self.feature_types
+    }
+
+    /// Reads a CSV with a header row whose first column is the class name.
+    /// Every other cell is parsed as `Num` before being widened to `f64`.
+    pub fn from_csv<Num: ToPrimitive + Copy + FromStr>(file_path: &str)
+            -> Result<Dataset, Box<dyn Error>> {
+        DatasetSpec::default().read::<Num>(file_path)
+    }
+
+    pub fn push(&mut self, label: usize, sample: Vec<f64>) -> Result<(), DatasetError> {
+        if !self.samples.is_empty() && sample.len() != self.num_features() {
+            return Err(DatasetError::InconsistentRow);
+        }
+
+        self.labels.push(label);
+        self.samples.push(sample);
+        Ok(())
+    }
+
+    /// Adds a sample by class name, registering the name if it is new.
+    pub fn push_named(&mut self, name: &str, sample: Vec<f64>) -> Result<(), DatasetError> {
+        let label: usize = match self.label_names.iter().position(|n| n == name) {
+            Some(label) => label,
+            None => {
+                self.label_names.push(name.to_string());
+                self.label_names.len() - 1
+            }
+        };
+
+        self.push(label, sample)
+    }
+
+    /// Re-indexes the classes so their names are in sorted order.
+    pub fn sort_label_names(&mut self) {
+        let mut sorted: Vec<String> = self.label_names.clone();
+        label::sort_names(&mut sorted);
+
+        let mapping: Vec<usize> = self.label_mapping(&sorted)
+            .expect("sorted names hold the same classes");
+        for label in self.labels.iter_mut() {
+            *label = mapping[*label];
+        }
+        self.label_names = sorted;
+    }
+
+    /// For each of this dataset's classes, the index of the class with the
+    /// same name in `names`.
+    pub fn label_mapping(&self, names: &[String]) -> Result<Vec<usize>, DatasetError> {
+        label::mapping(&self.label_names, names)
+    }
+
+    pub fn get_label_names(&self) -> &[String] {
+        &self.label_names
+    }
+
+    pub fn num_labels(&self) -> usize {
+        self.label_names.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.samples.first().map_or(0, |s| s.len())
+    }
+
+    pub fn get_labels(&self) -> &[usize] {
+        &self.labels
+    }
+
+    pub fn get_label(&self, index: usize) -> usize {
+        self.labels[index]
+    }
+
+    pub fn get_sample(&self, index: usize) -> &[f64] {
+        &self.samples[index]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &[f64])> {
+        self.labels.iter().zip(self.samples.iter())
+            .map(|(&label, sample)| (label, sample.as_slice()))
+    }
+
+    /// The number of missing (NaN) values of each feature.
+    pub fn missing_counts(&self) -> Vec<usize> {
+        let mut counts: Vec<usize> = vec![0; self.num_features()];
+
+        for sample in self.samples.iter() {
+            for (count, value) in counts.iter_mut().zip(sample.iter()) {
+                if value.is_nan() {
+                    *count += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// Copies the rows at the given indices, in the given order.
+    pub fn subset(&self, indices: &[usize]) -> Dataset {
+        Dataset {
+            label_names: self.label_names.clone(),
+            feature_types: self.feature_types.clone(),
+            labels: indices.iter().map(|&i| self.labels[i]).collect::<Vec<usize>>(),
+            samples: indices.iter().map(|&i| self.samples[i].clone()).collect::<Vec<Vec<f64>>>()
+        }
+    }
+
+    /// Copies the columns at the given feature indices, in the given order.
+    pub fn select(&self, features: &[usize]) -> Dataset {
+        Dataset {
+            label_names: self.label_names.clone(),
+            feature_types: if self.feature_types.is_empty() {
+                Vec::new()
+            } else {
+                features.iter().map(|&f| self.feature_types[f].clone()).collect::<Vec<FeatureType>>()
+            },
+            labels: self.labels.clone(),
+            samples: self.samples.iter()
+                .map(|sample| features.iter().map(|&f| sample[f]).collect::<Vec<f64>>())
+                .collect::<Vec<Vec<f64>>>()
+        }
+    }
+}
+
+#[cfg(test)]
+mod dataset_tests {
+    use crate::ml::{dataset::*, temp_path};
+    use std::fs;
+
+    #[test]
+    fn test_spec_reads_custom_layout() {
+        let path = temp_path("dataset_spec_test.tsv");
+        fs::write(&path, "id\tx\ty\tclass\n# skipped\n7\t1.5\t2\tb\n8\t0.5\t4\ta\n").unwrap();
+
+        let spec: DatasetSpec = DatasetSpec {
+            label: Some(ColumnRef::Name(String::from("class"))),
+            delimiter: b'\t',
+            ignore: vec![ColumnRef::Index(0)],
+            comment: Some(b'#'),
+            ..DatasetSpec::default()
+        };
+
+        let dataset: Dataset = spec.read::<f64>(path.to_str().unwrap()).unwrap();
+        assert_eq!(dataset.get_labels(), &[1, 0]);
+        assert_eq!(dataset.get_label_names(), &["a", "b"]);
+        assert_eq!(dataset.label_mapping(&[String::from("b"), String::from("a")]).unwrap(), vec![1, 0]);
+        assert_eq!(dataset.get_sample(0), &[1.5, 2.0]);
+        assert_eq!(dataset.get_sample(1), &[0.5, 4.0]);
+
+        let unlabelled: DatasetSpec = DatasetSpec { has_header: false, label: None, ..spec };
+        fs::write(&path, "1\t2\t3\n4\t5\t6\n").unwrap();
+        let samples: Vec<Vec<f64>> = unlabelled.read_samples::<f64>(path.to_str().unwrap()).unwrap();
+        assert_eq!(samples, vec![vec![2.0, 3.0], vec![5.0, 6.0]]);
+
+        let idx: DatasetSpec = DatasetSpec { format: DataFormat::Idx, ..DatasetSpec::default() };
+        assert_eq!(idx.source_paths("train-images-idx3-ubyte"), vec!["train-images-idx3-ubyte", "train-labels-idx1-ubyte"]);
+        assert_eq!(unlabelled.source_paths("x.npy,y.npy"), vec!["x.npy", "y.npy"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_spec_reads_missing_tokens() {
+        let path = temp_path("dataset_missing_test.csv");
+        fs::write(&path, "label,x,y
+a,1,
+b,-,2
+a,?,3
+").unwrap();
+
+        let default: DatasetSpec = DatasetSpec::default();
+        assert!(default.read::<f64>(path.to_str().unwrap()).is_err());
+
+        let spec: DatasetSpec = DatasetSpec { missing: vec![String::from(""), String::from("-")], ..default };
+        assert!(spec.read::<f64>(path.to_str().unwrap()).is_err());
+
+        fs::write(&path, "label,x,y
+a,1,
+b,-,2
+a,-,3
+").unwrap();
+        let dataset: Dataset = spec.read::<f64>(path.to_str().unwrap()).unwrap();
+        assert!(dataset.get_sample(0)[1].is_nan());
+        assert_eq!(dataset.missing_counts(), vec![2, 1]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
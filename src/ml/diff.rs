@@ -0,0 +1,210 @@
+extern crate serde;
+
+use crate::ml::{error::ModelError, inspection};
+use crate::model::gaussian_nb::GaussianParameters;
+use self::serde::Serialize;
+use std::{string::String, vec::Vec};
+
+/// How much more or less likely a class is in the new model.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PriorShift {
+    pub label: String,
+    pub old_prior: f64,
+    pub new_prior: f64
+}
+
+impl PriorShift {
+    pub fn shift(&self) -> f64 {
+        self.new_prior - self.old_prior
+    }
+}
+
+/// How one feature's distribution within one class changed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FeatureChange {
+    pub label: String,
+    pub feature: usize,
+    pub old_mean: f64,
+    pub new_mean: f64,
+    pub old_std: f64,
+    pub new_std: f64,
+    /// KL divergence of the new distribution from the old one, in nats.
+    /// `None` when the feature was constant within the class in either
+    /// model and the distributions differ, since the divergence would only
+    /// reflect the standard deviation floor; see
+    /// `inspection::variance_floor`.
+    pub divergence: Option<f64>
+}
+
+/// What changed between two Gaussian models over the same features.
+/// Classes are matched by name, so only the classes in both are compared.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ModelDiff {
+    pub added_labels: Vec<String>,
+    pub removed_labels: Vec<String>,
+    /// The shared classes, in the old model's order.
+    pub priors: Vec<PriorShift>,
+    /// Every feature of every shared class, most changed first, with those
+    /// whose divergence is not defined last.
+    pub changes: Vec<FeatureChange>,
+    /// KL divergence, in nats, of the new model's joint distribution of
+    /// classes and features from the old one's, over the shared classes
+    /// with their priors rescaled to sum to 1, leaving out the features
+    /// whose divergence is not defined. 0 when nothing changed.
+    pub score: f64
+}
+
+/// Compares two Gaussian models, which must have the same number of
+/// features.
+pub fn compare(old: &GaussianParameters, new: &GaussianParameters) -> Result<ModelDiff, ModelError> {
+    let num_features: usize = old.means.first().map_or(0, |means| means.len());
+    if new.means.first().map_or(0, |means| means.len()) != num_features {
+        return Err(ModelError::FeatureCountMismatch);
+    }
+
+    let added_labels: Vec<String> = new.labels.iter()
+        .filter(|label| !old.labels.contains(label))
+        .cloned()
+        .collect::<Vec<String>>();
+    let removed_labels: Vec<String> = old.labels.iter()
+        .filter(|label| !new.labels.contains(label))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    // Pairs of class indices into the old and new models
+    let shared: Vec<(usize, usize)> = old.labels.iter().enumerate()
+        .filter_map(|(old_class, label)| new.labels.iter().position(|other| other == label)
+            .map(|new_class| (old_class, new_class)))
+        .collect::<Vec<(usize, usize)>>();
+
+    let (old_priors, new_priors): (Vec<f64>, Vec<f64>) =
+        (inspection::priors(&old.log_priors), inspection::priors(&new.log_priors));
+    let priors: Vec<PriorShift> = shared.iter()
+        .map(|&(o, n)| PriorShift {
+            label: old.labels[o].clone(),
+            old_prior: old_priors[o],
+            new_prior: new_priors[n]
+        })
+        .collect::<Vec<PriorShift>>();
+
+    let (old_floor, new_floor): (f64, f64) =
+        (inspection::variance_floor(&old.stds), inspection::variance_floor(&new.stds));
+    let mut changes: Vec<FeatureChange> = Vec::with_capacity(shared.len() * num_features);
+    for &(o, n) in shared.iter() {
+        for feature in 0..num_features {
+            let (old_mean, old_std): (f64, f64) = (old.means[o][feature], old.stds[o][feature]);
+            let (new_mean, new_std): (f64, f64) = (new.means[n][feature], new.stds[n][feature]);
+            let (old_variance, new_variance): (f64, f64) = (old_std.powi(2), new_std.powi(2));
+
+            let divergence: Option<f64> = if (old_mean, old_std) == (new_mean, new_std) {
+                Some(0.0)
+            } else if old_variance <= old_floor || new_variance <= new_floor {
+                None
+            } else {
+                Some(inspection::gaussian_kl(old_mean, old_variance, new_mean, new_variance))
+            };
+
+            changes.push(FeatureChange {
+                label: old.labels[o].clone(),
+                feature,
+                old_mean,
+                new_mean,
+                old_std,
+                new_std,
+                divergence
+            });
+        }
+    }
+
+    // By the chain rule, the prior divergence plus each class's feature
+    // divergences weighted by its old prior
+    let old_total: f64 = priors.iter().map(|p| p.old_prior).sum();
+    let new_total: f64 = priors.iter().map(|p| p.new_prior).sum();
+    let score: f64 = priors.iter().enumerate()
+        .filter(|(_, p)| p.old_prior > 0.0)
+        .map(|(idx, p)| {
+            let (old_prior, new_prior): (f64, f64) = (p.old_prior / old_total, p.new_prior / new_total);
+            let features: f64 = changes[idx * num_features..(idx + 1) * num_features].iter()
+                .filter_map(|change| change.divergence)
+                .sum::<f64>();
+
+            old_prior * ((old_prior / new_prior).ln() + features)
+        })
+        .sum::<f64>();
+
+    let key = |change: &FeatureChange| change.divergence.unwrap_or(f64::NEG_INFINITY);
+    changes.sort_by(|a, b| key(b).total_cmp(&key(a)));
+
+    Ok(ModelDiff { added_labels, removed_labels, priors, changes, score })
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use crate::ml::diff::*;
+
+    fn parameters(labels: &[&str], priors: &[f64], means: Vec<Vec<f64>>) -> GaussianParameters {
+        GaussianParameters {
+            labels: labels.iter().map(|label| label.to_string()).collect::<Vec<String>>(),
+            log_priors: priors.iter().map(|prior| prior.log10()).collect::<Vec<f64>>(),
+            stds: vec![vec![1.0; means[0].len()]; means.len()],
+            means,
+            binarize: None
+        }
+    }
+
+    #[test]
+    fn test_identical_models() {
+        let model: GaussianParameters = parameters(&["a", "b"], &[0.5, 0.5],
+            vec![vec![0.0, 1.0], vec![2.0, 3.0]]);
+        let diff: ModelDiff = compare(&model, &model).unwrap();
+
+        assert!(diff.added_labels.is_empty() && diff.removed_labels.is_empty());
+        assert_eq!(diff.changes.len(), 4);
+        assert_abs_diff_eq!(diff.score, 0.0);
+        assert!(diff.priors.iter().all(|p| p.shift() == 0.0));
+    }
+
+    #[test]
+    fn test_changes() {
+        let old: GaussianParameters = parameters(&["a", "b", "c"], &[0.5, 0.25, 0.25],
+            vec![vec![0.0, 1.0], vec![2.0, 3.0], vec![4.0, 5.0]]);
+        let new: GaussianParameters = parameters(&["d", "b", "a"], &[0.2, 0.4, 0.4],
+            vec![vec![9.0, 9.0], vec![2.0, 5.0], vec![0.0, 1.0]]);
+        let diff: ModelDiff = compare(&old, &new).unwrap();
+
+        assert_eq!(diff.added_labels, vec![String::from("d")]);
+        assert_eq!(diff.removed_labels, vec![String::from("c")]);
+        assert_eq!(diff.priors.iter().map(|p| p.label.as_str()).collect::<Vec<&str>>(), vec!["a", "b"]);
+        assert_abs_diff_eq!(diff.priors[0].shift(), -0.1, epsilon=1e-12);
+
+        // Only feature 1 of b moved, by two standard deviations
+        assert_eq!((diff.changes[0].label.as_str(), diff.changes[0].feature), ("b", 1));
+        assert_abs_diff_eq!(diff.changes[0].divergence.unwrap(), 2.0, epsilon=1e-12);
+        assert_abs_diff_eq!(diff.changes[1].divergence.unwrap(), 0.0, epsilon=1e-12);
+
+        // Shared priors rescale to 2/3, 1/3 and 1/2, 1/2
+        let prior_kl: f64 = (2.0 / 3.0) * (4.0f64 / 3.0).ln() + (1.0 / 3.0) * (2.0f64 / 3.0).ln();
+        assert_abs_diff_eq!(diff.score, prior_kl + 2.0 / 3.0, epsilon=1e-12);
+
+        let narrow: GaussianParameters = parameters(&["a"], &[1.0], vec![vec![0.0]]);
+        assert!(matches!(compare(&old, &narrow), Err(ModelError::FeatureCountMismatch)));
+    }
+
+    #[test]
+    fn test_floor_std_features() {
+        let mut old: GaussianParameters = parameters(&["a", "b"], &[0.5, 0.5],
+            vec![vec![0.0, 1.0, 0.0], vec![2.0, 3.0, 0.0]]);
+        old.stds[0][0] = 1e-10;
+        old.stds[0][2] = 1e-10;
+        let mut new: GaussianParameters = old.clone();
+        new.means[0][0] = 0.5;
+        new.means[1][1] = 4.0;
+
+        // The floored feature that moved is undefined and left out of the
+        // score, and the one that did not still matches
+        let diff: ModelDiff = compare(&old, &new).unwrap();
+        assert_eq!(diff.changes.last().map(|c| (c.feature, c.divergence)), Some((0, None)));
+        assert!(diff.changes.iter().any(|c| c.feature == 2 && c.label == "a" && c.divergence == Some(0.0)));
+        assert_abs_diff_eq!(diff.score, 0.25, epsilon=1e-12);
+    }
+}
@@ -0,0 +1,218 @@
+extern crate chrono;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+
+use crate::ml::{binary::{self, Compression}, error::ModelFileError, mapped, pmml::{self, BayesInput, PmmlModel}};
+use crate::ml::{hyperparameters::Hyperparameters, model::{self, Model}};
+use crate::model::ModelKind;
+use self::serde::{Serialize, Deserialize, de::DeserializeOwned};
+use self::serde_json::{Map, Value};
+use self::sha2::{Digest, Sha256};
+use std::{error::Error, fs, io, path::Path, string::String, vec::Vec};
+
+/// The newest envelope schema. Bare models saved before the envelope
+/// existed are schema 0.
+pub static SCHEMA_VERSION: u32 = 1;
+
+/// Where a saved model came from and how it was trained.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelMetadata {
+    pub schema_version: u32,
+    pub kind: ModelKind,
+    /// RFC 3339 UTC time the model was trained, if known.
+    pub created: Option<String>,
+    pub training_rows: Option<usize>,
+    /// `sha256:` followed by the hex digest of the training file contents.
+    pub source_checksum: Option<String>,
+    /// Whether the LIBSVM training data numbered features from 0, for
+    /// models trained on LIBSVM data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zero_based: Option<bool>,
+    pub hyperparameters: Hyperparameters
+}
+
+/// A model saved together with its metadata. The metadata fields sit next
+/// to `model` at the top level of the saved file.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModelEnvelope<M> {
+    #[serde(flatten)]
+    pub metadata: ModelMetadata,
+    pub model: M
+}
+
+impl ModelMetadata {
+    /// Metadata stamped with the current time and no training details.
+    pub fn new(kind: ModelKind, hyperparameters: Hyperparameters) -> ModelMetadata {
+        ModelMetadata {
+            schema_version: SCHEMA_VERSION,
+            kind,
+            created: Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            training_rows: None,
+            source_checksum: None,
+            zero_based: None,
+            hyperparameters
+        }
+    }
+
+    /// Records the number of training rows and the checksum of the files
+    /// they were read from.
+    pub fn with_training<P: AsRef<Path>>(self, training_rows: usize, source_paths: &[P])
+            -> io::Result<ModelMetadata> {
+        Ok(ModelMetadata {
+            training_rows: Some(training_rows),
+            source_checksum: Some(checksum(source_paths)?),
+            ..self
+        })
+    }
+}
+
+/// The SHA-256 of the files' contents, read one after another.
+pub fn checksum<P: AsRef<Path>>(file_paths: &[P]) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for file_path in file_paths.iter() {
+        io::copy(&mut fs::File::open(file_path)?, &mut hasher)?;
+    }
+
+    let digest: String = hasher.finalize().iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    Ok(format!("sha256:{}", digest))
+}
+
+/// Brings a saved model up to the current schema. Schema 0 files are the
+/// bare model, which is wrapped with whatever metadata it carries.
+pub fn migrate(value: Value, kind: ModelKind) -> Result<Value, ModelFileError> {
+    let version: u64 = match value.get("schema_version") {
+        Some(version) => version.as_u64().ok_or(ModelFileError::InvalidEncoding)?,
+        None => 0
+    };
+
+    match version {
+        0 => {
+            let hyperparameters: Value = match value.get("hyperparameters") {
+                Some(hyperparameters) => hyperparameters.clone(),
+                None => serde_json::to_value(Hyperparameters::default())
+                    .map_err(|_| ModelFileError::InvalidEncoding)?
+            };
+
+            let mut envelope: Map<String, Value> = Map::new();
+            envelope.insert(String::from("schema_version"), Value::from(SCHEMA_VERSION));
+            envelope.insert(String::from("kind"), Value::from(kind.to_string()));
+            envelope.insert(String::from("created"), Value::Null);
+            envelope.insert(String::from("training_rows"), Value::Null);
+            envelope.insert(String::from("source_checksum"), Value::Null);
+            envelope.insert(String::from("hyperparameters"), hyperparameters);
+            envelope.insert(String::from("model"), value);
+            Ok(Value::Object(envelope))
+        },
+        1 => Ok(value),
+        _ => Err(ModelFileError::UnsupportedVersion)
+    }
+}
+
+/// Migrates a saved value and reads the envelope, checking it holds a
+/// model of the expected kind.
+pub fn from_value<M: DeserializeOwned>(value: Value, kind: ModelKind)
+        -> Result<ModelEnvelope<M>, Box<dyn Error>> {
+    let envelope: ModelEnvelope<M> = serde_json::from_value(migrate(value, kind)?)?;
+    if envelope.metadata.kind != kind {
+        return Err(Box::new(ModelFileError::KindMismatch));
+    }
+
+    Ok(envelope)
+}
+
+/// Only the kind of a saved JSON model, ignoring the rest of the file.
+#[derive(Deserialize)]
+struct KindProbe {
+    #[serde(default)]
+    kind: Option<ModelKind>
+}
+
+/// The kind recorded in a binary header or JSON envelope, or `None` for
+/// bare models saved before the envelope existed. Memory-mapped models are
+/// always Gaussian. PMML documents hold a mixed model when they have
+/// categorical inputs and a Gaussian one otherwise.
+pub fn detect_kind(file_path: &str) -> Result<Option<ModelKind>, Box<dyn Error>> {
+    if let Some(header) = binary::detect(file_path)? {
+        return Ok(Some(header.kind));
+    }
+    if mapped::detect(file_path)? {
+        return Ok(Some(ModelKind::Gaussian));
+    }
+    if pmml::is_pmml_path(file_path) {
+        let model: PmmlModel = PmmlModel::parse(&fs::read_to_string(file_path)?)?;
+        let categorical: bool = model.inputs.iter()
+            .any(|input| matches!(input, BayesInput::Categorical { .. }));
+        return Ok(Some(if categorical { ModelKind::Mixed } else { ModelKind::Gaussian }));
+    }
+
+    let reader = io::BufReader::new(fs::File::open(file_path)?);
+    let probe: KindProbe = serde_json::from_reader(reader)?;
+    Ok(probe.kind)
+}
+
+/// Loads a JSON or binary model, told apart by the binary magic bytes, with
+/// its metadata.
+pub fn load<M: Model + DeserializeOwned>(file_path: &str, kind: ModelKind)
+        -> Result<ModelEnvelope<M>, Box<dyn Error>> {
+    eprint!("Loading model from {}...", file_path);
+
+    let bytes: Vec<u8> = fs::read(file_path)?;
+    let envelope: ModelEnvelope<M> = match binary::BinaryHeader::parse(&bytes)? {
+        Some(_) => binary::from_bytes(&bytes, kind)?,
+        None => from_value(serde_json::from_slice(&bytes)?, kind)?
+    };
+
+    eprintln!("done.");
+
+    Ok(envelope)
+}
+
+/// Saves the model with its metadata, in binary for `.nbm` paths and as
+/// JSON otherwise.
+pub fn save<M: Model + Serialize>(model: &M, metadata: &ModelMetadata, file_path: &str)
+        -> Result<(), Box<dyn Error>> {
+    if binary::is_binary_path(file_path) {
+        binary::write_binary(model, metadata, file_path, Compression::from_path(file_path))
+    } else {
+        model::write_json(model, metadata, file_path)
+    }
+}
+
+#[cfg(test)]
+mod envelope_tests {
+    use crate::ml::{envelope::*, temp_path};
+    use serde_json::json;
+
+    #[test]
+    fn test_migrates_bare_models() {
+        let bare: Value = json!({"labels": [{"index": 0, "ascii": 48}], "features": []});
+
+        let envelope: ModelEnvelope<Value> = from_value(bare.clone(), ModelKind::Gaussian).unwrap();
+        assert_eq!(envelope.metadata.schema_version, SCHEMA_VERSION);
+        assert_eq!(envelope.metadata.created, None);
+        assert_eq!(envelope.metadata.hyperparameters, Hyperparameters::default());
+        assert_eq!(envelope.model, bare);
+
+        let saved: Value = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(saved["kind"], "gaussian");
+        let reloaded: ModelEnvelope<Value> = from_value(saved.clone(), ModelKind::Gaussian).unwrap();
+        assert_eq!(reloaded.metadata, envelope.metadata);
+
+        assert!(from_value::<Value>(saved, ModelKind::Mixed).is_err());
+        assert!(from_value::<Value>(json!({"schema_version": 99}), ModelKind::Gaussian).is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let path = temp_path("envelope_checksum_test.csv");
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!(checksum(&[path.to_str().unwrap()]).unwrap(),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,14 +3,18 @@ use std::{error::Error, fmt};
 #[derive(Debug)]
 pub enum ModelError {
     UntrainedError,
-    FeatureNotFound
+    FeatureNotFound,
+    LabelNotFound,
+    FeatureCountMismatch
 }
 
 impl ModelError {
     fn value(&self) -> &str {
         match *self {
             ModelError::UntrainedError => "The model has not been fully trained.",
-            ModelError::FeatureNotFound => "The feature was not present in the provided training data."
+            ModelError::FeatureNotFound => "The feature was not present in the provided training data.",
+            ModelError::LabelNotFound => "The label index does not belong to the model.",
+            ModelError::FeatureCountMismatch => "The sample has a different number of features than the model was trained on."
         }
     }
 }
@@ -26,3 +30,110 @@ impl fmt::Display for ModelError {
         write!(f, "{}", self.value())
     }
 }
+
+#[derive(Debug, PartialEq)]
+pub enum DatasetError {
+    MissingLabel,
+    InvalidLabel,
+    InvalidValue,
+    InconsistentRow,
+    UnknownColumn,
+    UnknownLabel,
+    DuplicateLabel,
+    InvalidIndex,
+    InvalidHeader,
+    NominalFeature
+}
+
+impl DatasetError {
+    fn value(&self) -> &str {
+        match *self {
+            DatasetError::MissingLabel => "A record did not contain a label.",
+            DatasetError::InvalidLabel => "A record had an empty label.",
+            DatasetError::InvalidValue => "A feature value could not be parsed as a number.",
+            DatasetError::InconsistentRow => "A record had a different number of features than the first record.",
+            DatasetError::UnknownColumn => "A column named in the dataset spec was not found in the data.",
+            DatasetError::UnknownLabel => "A record label is not one of the model's classes.",
+            DatasetError::DuplicateLabel => "The labels file gives two classes the same index.",
+            DatasetError::InvalidIndex => "A feature index is below the first index or beyond the last feature of the data.",
+            DatasetError::InvalidHeader => "The file header does not match the expected format.",
+            DatasetError::NominalFeature => "Nominal features can only be selected by mutual information."
+        }
+    }
+}
+
+impl Error for DatasetError {
+    fn description(&self) -> &str {
+        self.value()
+    }
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ModelFileError {
+    InvalidMagic,
+    UnsupportedVersion,
+    UnknownKind,
+    KindMismatch,
+    InvalidEncoding,
+    UnsupportedLayout
+}
+
+impl ModelFileError {
+    fn value(&self) -> &str {
+        match *self {
+            ModelFileError::InvalidMagic => "The file is not a saved model.",
+            ModelFileError::UnsupportedVersion => "The model file was written by a newer version of the format.",
+            ModelFileError::UnknownKind => "The model file names an unknown kind of model.",
+            ModelFileError::KindMismatch => "The model file holds a different kind of model than requested.",
+            ModelFileError::InvalidEncoding => "The model file is truncated or corrupt.",
+            ModelFileError::UnsupportedLayout => "Only uncalibrated Gaussian models can be saved in this format."
+        }
+    }
+}
+
+impl Error for ModelFileError {
+    fn description(&self) -> &str {
+        self.value()
+    }
+}
+
+impl fmt::Display for ModelFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PmmlError {
+    UnsupportedModel,
+    InvalidDocument,
+    UnknownValue
+}
+
+impl PmmlError {
+    fn value(&self) -> &str {
+        match *self {
+            PmmlError::UnsupportedModel => "Only Gaussian and mixed models without binarization can be exchanged as PMML.",
+            PmmlError::InvalidDocument => "The PMML document does not hold a naive Bayes model in the expected form.",
+            PmmlError::UnknownValue => "The PMML document refers to a class or category its data dictionary does not declare."
+        }
+    }
+}
+
+impl Error for PmmlError {
+    fn description(&self) -> &str {
+        self.value()
+    }
+}
+
+impl fmt::Display for PmmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
@@ -0,0 +1,144 @@
+extern crate serde;
+
+use crate::ml::{error::{DatasetError, ModelError}, image::GrayImage, model::Model, prediction};
+use self::serde::Serialize;
+use std::{string::String, vec::Vec};
+
+/// How much one feature's value favours the predicted class.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Contribution {
+    pub feature: usize,
+    pub value: f64,
+    /// The feature's log10 likelihood ratio of the predicted class over the
+    /// class it is compared against. Positive values favour the prediction.
+    pub contribution: f64
+}
+
+/// Why a model prefers one class over another for a sample: the log10 prior
+/// ratio plus the contributions add up to the log10 posterior ratio.
+///
+/// The posteriors are the model's uncalibrated ones, found from the same
+/// log likelihood terms as the contributions, so they add up even where the
+/// densities themselves underflow. A calibrated model's predictions may
+/// rank the classes differently.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub predicted: String,
+    pub predicted_posterior: f64,
+    pub against: String,
+    pub against_posterior: f64,
+    /// The log10 prior ratio of the predicted class over the other.
+    pub prior: f64,
+    /// Every feature, largest contribution in either direction first.
+    pub contributions: Vec<Contribution>
+}
+
+/// Explains the model's prediction for the sample against the class at
+/// `against`, or the runner-up when none is given.
+pub fn explain<M: Model>(model: &M, sample_features: &[f64], against: Option<usize>)
+        -> Result<Explanation, ModelError> {
+    let (log_priors, terms): (Vec<f64>, Vec<Vec<f64>>) = model.log_likelihood_terms(sample_features)?;
+    let scores: Vec<f64> = log_priors.iter().zip(terms.iter())
+        .map(|(log_prior, class_terms)| log_prior + class_terms.iter().sum::<f64>())
+        .collect::<Vec<f64>>();
+    let posteriors: Vec<f64> = prediction::normalize_log10(&scores);
+
+    let mut ranked: Vec<usize> = (0..scores.len()).collect::<Vec<usize>>();
+    // A stable sort keeps the first of tied classes first, as argmax does
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let predicted: usize = *ranked.first().ok_or(ModelError::LabelNotFound)?;
+    let against: usize = match against {
+        Some(class) if class < posteriors.len() => class,
+        Some(_) => return Err(ModelError::LabelNotFound),
+        None => *ranked.get(1).ok_or(ModelError::LabelNotFound)?
+    };
+
+    let mut contributions: Vec<Contribution> = sample_features.iter().enumerate()
+        .map(|(feature, &value)| Contribution {
+            feature,
+            value,
+            contribution: terms[predicted][feature] - terms[against][feature]
+        })
+        .collect::<Vec<Contribution>>();
+    contributions.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+
+    Ok(Explanation {
+        predicted: model.label(predicted)?.get_name().to_string(),
+        predicted_posterior: posteriors[predicted],
+        against: model.label(against)?.get_name().to_string(),
+        against_posterior: posteriors[against],
+        prior: log_priors[predicted] - log_priors[against],
+        contributions
+    })
+}
+
+impl Explanation {
+    /// The contributions in feature order.
+    pub fn by_feature(&self) -> Vec<f64> {
+        let mut values: Vec<f64> = vec![0.0; self.contributions.len()];
+        for contribution in self.contributions.iter() {
+            values[contribution.feature] = contribution.contribution;
+        }
+
+        values
+    }
+
+    /// The contributions laid out row by row as an image, mid-gray where a
+    /// feature favours neither class and brightening towards white for the
+    /// predicted class and darkening towards black for the other, scaled by
+    /// the largest contribution.
+    pub fn heatmap(&self, width: usize, height: usize) -> Result<GrayImage, DatasetError> {
+        let values: Vec<f64> = self.by_feature();
+        let largest: f64 = values.iter().map(|value| value.abs()).fold(0.0, f64::max);
+
+        GrayImage::new(width, height, values)
+            .map(|image| image.stretch(-largest, largest))
+    }
+}
+
+#[cfg(test)]
+mod explanation_tests {
+    use crate::ml::{dataset::Dataset, explanation::*, fitted_gaussian};
+    use crate::model::gaussian_nb::GaussianNaiveBayes;
+
+    #[test]
+    fn test_contributions_add_up() {
+        let (_, model): (Dataset, GaussianNaiveBayes) = fitted_gaussian(vec![0, 0, 0, 1, 1, 2, 2], vec![
+            vec![1.0, 2.0, 0.0, 5.0], vec![2.0, 1.0, 0.5, 5.5], vec![1.5, 1.5, 1.0, 4.5],
+            vec![8.0, 9.0, 0.0, 5.0], vec![9.0, 7.0, 1.0, 5.5],
+            vec![4.0, 4.0, 9.0, 5.0], vec![5.0, 5.0, 8.0, 4.0]
+        ]);
+
+        let sample: [f64; 4] = [2.0, 2.5, 0.5, f64::NAN];
+        let explanation: Explanation = explain(&model, &sample, None).unwrap();
+        assert_eq!(explanation.predicted, "a");
+
+        // The prior and the contributions make up the log10 posterior ratio
+        let total: f64 = explanation.prior + explanation.contributions.iter()
+            .map(|c| c.contribution)
+            .sum::<f64>();
+        let ratio: f64 = (explanation.predicted_posterior / explanation.against_posterior).log10();
+        assert_relative_eq!(total, ratio, max_relative=1e-9);
+
+        let magnitudes: Vec<f64> = explanation.contributions.iter()
+            .map(|c| c.contribution.abs())
+            .collect::<Vec<f64>>();
+        assert!(magnitudes.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(explanation.by_feature()[3], 0.0);
+
+        // Far from every class the densities underflow, but the ranking
+        // still follows the contributions
+        let far: Explanation = explain(&model, &[1.0, 2.0, 1e3, 5.0], None).unwrap();
+        assert_eq!(model.posteriors(&[1.0, 2.0, 1e3, 5.0]).unwrap(), vec![1.0 / 3.0; 3]);
+        assert_eq!(far.predicted, "c");
+        assert!(far.prior + far.contributions.iter().map(|c| c.contribution).sum::<f64>() > 0.0);
+
+        let chosen: Explanation = explain(&model, &sample, Some(2)).unwrap();
+        assert_eq!(chosen.against, "c");
+        assert!(explain(&model, &sample, Some(3)).is_err());
+
+        let heatmap: GrayImage = explanation.heatmap(2, 2).unwrap();
+        assert_eq!(heatmap.get_pixels()[3], 127.5);
+        assert!(explanation.heatmap(3, 2).is_err());
+    }
+}
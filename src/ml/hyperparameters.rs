@@ -0,0 +1,103 @@
+extern crate serde;
+
+use self::serde::{Serialize, Deserialize};
+use std::{fmt, str::FromStr, string::String};
+
+/// Smallest standard deviation a Gaussian feature may be assigned.
+pub static MIN_STD: f64 = 1e-10;
+
+/// Default fraction of the largest feature variance added to all variances.
+pub static VAR_SMOOTHING: f64 = 1e-9;
+
+/// How class priors are estimated from the training data.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriorMode {
+    /// Class frequencies in the training data, Laplace smoothed by `alpha`.
+    Empirical,
+    /// Every class is equally likely.
+    Uniform
+}
+
+impl FromStr for PriorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PriorMode, String> {
+        match s {
+            "empirical" => Ok(PriorMode::Empirical),
+            "uniform" => Ok(PriorMode::Uniform),
+            _ => Err(format!("Unknown prior mode '{}'.", s))
+        }
+    }
+}
+
+impl fmt::Display for PriorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PriorMode::Empirical => write!(f, "empirical"),
+            PriorMode::Uniform => write!(f, "uniform")
+        }
+    }
+}
+
+/// Settings that shape training but are not learned from the data.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hyperparameters {
+    /// Floor applied to every per-class standard deviation.
+    pub min_std: f64,
+    /// Fraction of the largest feature variance added to every variance.
+    /// Models saved before this setting existed were trained without it.
+    #[serde(default)]
+    pub var_smoothing: f64,
+    /// Pseudo-count added to every class when estimating priors.
+    pub alpha: f64,
+    /// Pseudo-count added to every per-class feature count by the
+    /// multinomial and Bernoulli models.
+    pub smoothing: f64,
+    /// Maps feature values to 0 or 1 around this threshold when set.
+    pub binarize: Option<f64>,
+    pub prior: PriorMode
+}
+
+impl Default for Hyperparameters {
+    fn default() -> Hyperparameters {
+        Hyperparameters {
+            min_std: MIN_STD,
+            var_smoothing: VAR_SMOOTHING,
+            alpha: 0.0,
+            smoothing: 1.0,
+            binarize: None,
+            prior: PriorMode::Empirical
+        }
+    }
+}
+
+impl Hyperparameters {
+    /// Applies any configured preprocessing to a raw feature value. Missing
+    /// (NaN) values are passed through unchanged.
+    pub fn transform(&self, value: f64) -> f64 {
+        match self.binarize {
+            Some(threshold) if !value.is_nan() => if value > threshold { 1.0 } else { 0.0 },
+            Some(_) => value,
+            None => value
+        }
+    }
+
+    /// Log10 prior of each class given the number of training rows in it.
+    pub fn log_priors(&self, class_sizes: &[usize]) -> Vec<f64> {
+        let num_classes: f64 = class_sizes.len() as f64;
+
+        match self.prior {
+            PriorMode::Uniform => vec![-num_classes.log10(); class_sizes.len()],
+            PriorMode::Empirical => {
+                let total: f64 = class_sizes.iter().sum::<usize>() as f64;
+                let denominator: f64 = total + self.alpha * num_classes;
+
+                class_sizes.iter()
+                    .map(|&size| ((size as f64 + self.alpha) / denominator).log10())
+                    .collect()
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::ml::{error::DatasetError, dataset::Dataset};
+use std::{error::Error, fs, path::Path, string::String, vec::Vec};
+
+/// Type code of unsigned byte payloads, the only type MNIST uses.
+static UNSIGNED_BYTE: u8 = 0x08;
+
+/// An array read from an IDX file: its dimensions, outermost first, and
+/// its values in row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdxArray {
+    dims: Vec<usize>,
+    data: Vec<u8>
+}
+
+impl IdxArray {
+    /// Parses the bytes of an IDX file. The header is two zero bytes, the
+    /// type code, the number of dimensions, then each dimension as a
+    /// big-endian `u32`.
+    pub fn parse(bytes: &[u8]) -> Result<IdxArray, DatasetError> {
+        if bytes.len() < 4 || bytes[0] != 0 || bytes[1] != 0 {
+            return Err(DatasetError::InvalidHeader);
+        }
+        if bytes[2] != UNSIGNED_BYTE {
+            return Err(DatasetError::InvalidValue);
+        }
+
+        let num_dims: usize = bytes[3] as usize;
+        let header_len: usize = 4 + 4 * num_dims;
+        if bytes.len() < header_len {
+            return Err(DatasetError::InvalidHeader);
+        }
+
+        let dims: Vec<usize> = bytes[4..header_len].chunks(4)
+            .map(|dim| u32::from_be_bytes([dim[0], dim[1], dim[2], dim[3]]) as usize)
+            .collect::<Vec<usize>>();
+
+        // Both the row length and the total must fit, since a leading 0
+        // lets the total fit when the row length does not
+        let data: &[u8] = &bytes[header_len..];
+        let expected: usize = row_len(&dims)
+            .and_then(|row_len| row_len.checked_mul(dims.first().copied().unwrap_or(1)))
+            .ok_or(DatasetError::InvalidHeader)?;
+        if data.len() != expected {
+            return Err(DatasetError::InconsistentRow);
+        }
+
+        Ok(IdxArray {
+            dims,
+            data: data.to_vec()
+        })
+    }
+
+    pub fn from_file(file_path: &str) -> Result<IdxArray, Box<dyn Error>> {
+        Ok(IdxArray::parse(&fs::read(file_path)?)?)
+    }
+
+    pub fn get_dims(&self) -> &[usize] {
+        &self.dims
+    }
+
+    /// Number of items along the first dimension.
+    pub fn len(&self) -> usize {
+        self.dims.first().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Each item flattened in row-major order, so a 28x28 image becomes a
+    /// row of 784 values like the MNIST CSVs.
+    pub fn rows(&self) -> Vec<Vec<f64>> {
+        // `parse` checked that the row length fits
+        let row_len: usize = row_len(&self.dims).unwrap_or(0);
+        if row_len == 0 {
+            return vec![Vec::new(); self.len()];
+        }
+
+        self.data.chunks(row_len)
+            .map(|row| row.iter().map(|&v| v as f64).collect::<Vec<f64>>())
+            .collect::<Vec<Vec<f64>>>()
+    }
+}
+
+/// The number of values in each item, or `None` if it overflows.
+fn row_len(dims: &[usize]) -> Option<usize> {
+    dims.iter().skip(1).try_fold(1usize, |total, &dim| total.checked_mul(dim))
+}
+
+/// Finds the labels file for an images file. The path may name both files
+/// as `IMAGES,LABELS`; otherwise the MNIST naming is assumed, so
+/// `train-images-idx3-ubyte` pairs with `train-labels-idx1-ubyte` in the
+/// same directory.
+pub fn split_paths(file_path: &str) -> Result<(String, String), DatasetError> {
+    if let Some((images, labels)) = file_path.split_once(',') {
+        return Ok((images.to_string(), labels.to_string()));
+    }
+
+    let path: &Path = Path::new(file_path);
+    let file_name: &str = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.contains("images") => name,
+        _ => return Err(DatasetError::MissingLabel)
+    };
+
+    let labels: String = file_name.replace("images", "labels").replace("idx3", "idx1");
+    Ok((file_path.to_string(), path.with_file_name(labels).to_string_lossy().into_owned()))
+}
+
+/// Reads an images file and its matching labels file into a dataset whose
+/// classes are named by the label values.
+pub fn read_dataset(file_path: &str) -> Result<Dataset, Box<dyn Error>> {
+    let (images_path, labels_path): (String, String) = split_paths(file_path)?;
+    let images: IdxArray = IdxArray::from_file(&images_path)?;
+    let labels: IdxArray = IdxArray::from_file(&labels_path)?;
+
+    if labels.get_dims().len() != 1 || labels.len() != images.len() {
+        return Err(Box::new(DatasetError::InconsistentRow));
+    }
+
+    let mut dataset: Dataset = Dataset::default();
+    for (&label, sample) in labels.data.iter().zip(images.rows()) {
+        dataset.push_named(&label.to_string(), sample)?;
+    }
+
+    dataset.sort_label_names();
+    Ok(dataset)
+}
+
+/// Reads the rows of an images file without labels.
+pub fn read_samples(file_path: &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let images_path: &str = file_path.split(',').next().unwrap_or(file_path);
+    Ok(IdxArray::from_file(images_path)?.rows())
+}
+
+#[cfg(test)]
+mod idx_tests {
+    use crate::ml::idx::*;
+    use crate::ml::temp_path;
+
+    fn idx_bytes(dims: &[u32], data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![0, 0, 0x08, dims.len() as u8];
+        for dim in dims.iter() {
+            bytes.extend_from_slice(&dim.to_be_bytes());
+        }
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_validates_header() {
+        let parsed: IdxArray = IdxArray::parse(&idx_bytes(&[2, 1, 2], &[1, 2, 3, 4])).unwrap();
+        assert_eq!(parsed.get_dims(), &[2, 1, 2]);
+        assert_eq!(parsed.rows(), vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+
+        assert_eq!(IdxArray::parse(&[1, 0, 8, 0]), Err(DatasetError::InvalidHeader));
+        assert_eq!(IdxArray::parse(&idx_bytes(&[3], &[1, 2])), Err(DatasetError::InconsistentRow));
+        assert_eq!(IdxArray::parse(&idx_bytes(&[u32::MAX; 3], &[])), Err(DatasetError::InvalidHeader));
+        assert_eq!(IdxArray::parse(&idx_bytes(&[0, u32::MAX, u32::MAX, u32::MAX], &[])),
+            Err(DatasetError::InvalidHeader));
+
+        let mut floats: Vec<u8> = idx_bytes(&[1], &[0]);
+        floats[2] = 0x0D;
+        assert_eq!(IdxArray::parse(&floats), Err(DatasetError::InvalidValue));
+    }
+
+    #[test]
+    fn test_read_dataset_pairs_files() {
+        let images = temp_path("idx_test-images-idx3-ubyte");
+        let labels = temp_path("idx_test-labels-idx1-ubyte");
+        fs::write(&images, idx_bytes(&[3, 2, 2], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11])).unwrap();
+        fs::write(&labels, idx_bytes(&[3], &[7, 2, 7])).unwrap();
+
+        let dataset: Dataset = read_dataset(images.to_str().unwrap()).unwrap();
+        assert_eq!(dataset.get_label_names(), &["2", "7"]);
+        assert_eq!(dataset.get_labels(), &[1, 0, 1]);
+        assert_eq!(dataset.get_sample(1), &[4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(read_samples(images.to_str().unwrap()).unwrap().len(), 3);
+
+        // Only the file name is renamed
+        assert_eq!(split_paths("images/idx3/t10k-images-idx3-ubyte").unwrap().1, "images/idx3/t10k-labels-idx1-ubyte");
+        assert_eq!(split_paths("images/data.bin"), Err(DatasetError::MissingLabel));
+
+        fs::remove_file(&images).unwrap();
+        fs::remove_file(&labels).unwrap();
+    }
+}
@@ -0,0 +1,420 @@
+extern crate png;
+
+use crate::ml::error::DatasetError;
+use std::{error::Error, fs, string::String, vec::Vec};
+
+/// Intensity of a white pixel; images are scaled to the 0-255 range of the
+/// MNIST training data whatever their bit depth.
+static WHITE: f64 = 255.0;
+
+/// Pixels brighter than this count as ink when cropping a digit.
+static INK_THRESHOLD: f64 = 25.5;
+
+/// MNIST digits are fitted into a 20x20 box inside the 28x28 image.
+static BOX_FRACTION: f64 = 20.0 / 28.0;
+
+static PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// A grayscale image stored row by row, the order in which MNIST rows and
+/// IDX images are flattened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrayImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<f64>
+}
+
+/// Splits the header of a PGM into tokens, skipping `#` comments, and
+/// returns them with the offset just past the last one.
+fn pgm_tokens(bytes: &[u8], count: usize) -> Result<(Vec<String>, usize), DatasetError> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut pos: usize = 0;
+
+    while tokens.len() < count {
+        match bytes.get(pos) {
+            None => return Err(DatasetError::InvalidHeader),
+            Some(b'#') => while pos < bytes.len() && bytes[pos] != b'\n' { pos += 1; },
+            Some(c) if c.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let start: usize = pos;
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() { pos += 1; }
+                tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+            }
+        }
+    }
+
+    Ok((tokens, pos))
+}
+
+/// Resamples one line of `input` to `len` values, averaging the input
+/// pixels each output pixel covers, weighted by how much of them it covers.
+fn resample_line(input: &[f64], len: usize) -> Vec<f64> {
+    let scale: f64 = input.len() as f64 / len as f64;
+
+    (0..len).map(|idx| {
+        let (start, end): (f64, f64) = (idx as f64 * scale, (idx + 1) as f64 * scale);
+        let mut total: f64 = 0.0;
+
+        for (src, &value) in input.iter().enumerate()
+                .take(end.ceil() as usize).skip(start.floor() as usize) {
+            let overlap: f64 = end.min(src as f64 + 1.0) - start.max(src as f64);
+            total += overlap * value;
+        }
+
+        total / scale
+    }).collect::<Vec<f64>>()
+}
+
+impl GrayImage {
+    /// An image of `pixels`, which must fill a non-empty `width` by `height`.
+    pub fn new(width: usize, height: usize, pixels: Vec<f64>) -> Result<GrayImage, DatasetError> {
+        if width == 0 || height == 0 {
+            return Err(DatasetError::InvalidHeader);
+        }
+        if pixels.len() != width * height {
+            return Err(DatasetError::InconsistentRow);
+        }
+
+        Ok(GrayImage { width, height, pixels })
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_pixels(&self) -> &[f64] {
+        &self.pixels
+    }
+
+    fn get(&self, x: usize, y: usize) -> f64 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Parses a plain (`P2`) or raw (`P5`) PGM of any maximum value.
+    pub fn parse_pgm(bytes: &[u8]) -> Result<GrayImage, DatasetError> {
+        let (tokens, end): (Vec<String>, usize) = pgm_tokens(bytes, 4)?;
+        let numbers: Vec<usize> = tokens[1..].iter()
+            .map(|token| token.parse::<usize>().map_err(|_| DatasetError::InvalidHeader))
+            .collect::<Result<Vec<usize>, DatasetError>>()?;
+        let (width, height, max_value): (usize, usize, usize) = (numbers[0], numbers[1], numbers[2]);
+
+        if width == 0 || height == 0 || width.checked_mul(height).is_none() || max_value == 0 || max_value > 65535 {
+            return Err(DatasetError::InvalidHeader);
+        }
+
+        let values: Vec<usize> = match tokens[0].as_str() {
+            "P2" => pgm_tokens(&bytes[end..], width * height)?.0.iter()
+                .map(|token| token.parse::<usize>().map_err(|_| DatasetError::InvalidValue))
+                .collect::<Result<Vec<usize>, DatasetError>>()?,
+            "P5" => {
+                // A single whitespace byte separates the header from the data
+                let data: &[u8] = bytes.get(end + 1..).ok_or(DatasetError::InconsistentRow)?;
+                if max_value < 256 {
+                    data.iter().map(|&b| b as usize).collect::<Vec<usize>>()
+                } else {
+                    data.chunks(2)
+                        .map(|pair| (pair[0] as usize) << 8 | *pair.get(1).unwrap_or(&0) as usize)
+                        .collect::<Vec<usize>>()
+                }
+            },
+            // Color and bitmap variants of the format are not grayscale
+            _ => return Err(DatasetError::InvalidHeader)
+        };
+
+        if values.len() < width * height || values.iter().any(|&v| v > max_value) {
+            return Err(DatasetError::InvalidValue);
+        }
+
+        GrayImage::new(width, height, values.iter().take(width * height)
+            .map(|&v| v as f64 * WHITE / max_value as f64)
+            .collect::<Vec<f64>>())
+    }
+
+    /// Decodes a PNG of any color type, taking the luminance of color
+    /// images and compositing transparency onto white.
+    pub fn parse_png(bytes: &[u8]) -> Result<GrayImage, Box<dyn Error>> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+
+        let mut buffer: Vec<u8> = vec![0; reader.output_buffer_size()];
+        let info: png::OutputInfo = reader.next_frame(&mut buffer)?;
+        let channels: usize = info.color_type.samples();
+
+        let pixels: Vec<f64> = buffer[..info.buffer_size()].chunks(channels).map(|px| {
+            let px: Vec<f64> = px.iter().map(|&v| v as f64).collect::<Vec<f64>>();
+            let (gray, alpha): (f64, f64) = match px.len() {
+                1 => (px[0], WHITE),
+                2 => (px[0], px[1]),
+                3 => (0.299 * px[0] + 0.587 * px[1] + 0.114 * px[2], WHITE),
+                _ => (0.299 * px[0] + 0.587 * px[1] + 0.114 * px[2], px[3])
+            };
+
+            (gray * alpha + WHITE * (WHITE - alpha)) / WHITE
+        }).collect::<Vec<f64>>();
+
+        Ok(GrayImage::new(info.width as usize, info.height as usize, pixels)?)
+    }
+
+    /// Reads a PGM or PNG, recognised by its leading bytes.
+    pub fn from_file(file_path: &str) -> Result<GrayImage, Box<dyn Error>> {
+        let bytes: Vec<u8> = fs::read(file_path)?;
+
+        if bytes.starts_with(PNG_SIGNATURE) {
+            GrayImage::parse_png(&bytes)
+        } else {
+            Ok(GrayImage::parse_pgm(&bytes)?)
+        }
+    }
+
+    /// Swaps dark and light, for dark digits drawn on a light background.
+    pub fn invert(&self) -> GrayImage {
+        GrayImage {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|&v| WHITE - v).collect::<Vec<f64>>()
+        }
+    }
+
+    /// Scales the whole image to `width` x `height`, averaging the pixels
+    /// each output pixel covers.
+    pub fn resize(&self, width: usize, height: usize) -> GrayImage {
+        let rows: Vec<Vec<f64>> = self.pixels.chunks(self.width)
+            .map(|row| resample_line(row, width))
+            .collect::<Vec<Vec<f64>>>();
+
+        let mut pixels: Vec<f64> = vec![0.0; width * height];
+        for x in 0..width {
+            let column: Vec<f64> = rows.iter().map(|row| row[x]).collect::<Vec<f64>>();
+            for (y, value) in resample_line(&column, height).into_iter().enumerate() {
+                pixels[y * width + x] = value;
+            }
+        }
+
+        GrayImage { width, height, pixels }
+    }
+
+    fn crop(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> GrayImage {
+        GrayImage {
+            width: x1 - x0,
+            height: y1 - y0,
+            pixels: (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                .map(|(x, y)| self.get(x, y))
+                .collect::<Vec<f64>>()
+        }
+    }
+
+    /// Prepares an image the way MNIST digits were: the ink is cropped,
+    /// scaled to fit a box 20/28 the size of the output keeping its aspect
+    /// ratio, and placed so its center of mass is at the output's center.
+    /// Light ink on a dark background is expected.
+    pub fn fit_to(&self, width: usize, height: usize) -> GrayImage {
+        let ink: Vec<(usize, usize)> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.get(x, y) > INK_THRESHOLD)
+            .collect::<Vec<(usize, usize)>>();
+
+        let mut fitted: GrayImage = GrayImage { width, height, pixels: vec![0.0; width * height] };
+        if ink.is_empty() {
+            return fitted;
+        }
+
+        let x0: usize = ink.iter().map(|&(x, _)| x).min().unwrap();
+        let x1: usize = ink.iter().map(|&(x, _)| x).max().unwrap() + 1;
+        let y0: usize = ink.iter().map(|&(_, y)| y).min().unwrap();
+        let y1: usize = ink.iter().map(|&(_, y)| y).max().unwrap() + 1;
+        let digit: GrayImage = self.crop(x0, y0, x1, y1);
+
+        let scale: f64 = (width as f64 * BOX_FRACTION / digit.width as f64)
+            .min(height as f64 * BOX_FRACTION / digit.height as f64);
+        let scaled: GrayImage = digit.resize(
+            ((digit.width as f64 * scale).round() as usize).clamp(1, width),
+            ((digit.height as f64 * scale).round() as usize).clamp(1, height));
+
+        let mass: f64 = scaled.pixels.iter().sum::<f64>();
+        let (mut cx, mut cy): (f64, f64) = (0.0, 0.0);
+        for (idx, &value) in scaled.pixels.iter().enumerate() {
+            cx += (idx % scaled.width) as f64 * value;
+            cy += (idx / scaled.width) as f64 * value;
+        }
+        let (cx, cy): (f64, f64) = if mass > 0.0 {
+            (cx / mass + 0.5, cy / mass + 0.5)
+        } else {
+            (scaled.width as f64 / 2.0, scaled.height as f64 / 2.0)
+        };
+
+        let left: usize = (width as f64 / 2.0 - cx).round()
+            .clamp(0.0, (width - scaled.width) as f64) as usize;
+        let top: usize = (height as f64 / 2.0 - cy).round()
+            .clamp(0.0, (height - scaled.height) as f64) as usize;
+
+        for y in 0..scaled.height {
+            for x in 0..scaled.width {
+                fitted.pixels[(top + y) * width + left + x] = scaled.get(x, y);
+            }
+        }
+
+        fitted
+    }
+
+    /// The pixels as a feature vector, row by row.
+    pub fn to_features(&self) -> Vec<f64> {
+        self.pixels.clone()
+    }
+
+    /// Maps `low` to black and `high` to white, clamping values outside
+    /// them, so statistics in any range can be viewed. Every pixel is black
+    /// when the range is empty.
+    pub fn stretch(&self, low: f64, high: f64) -> GrayImage {
+        let range: f64 = high - low;
+
+        GrayImage {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter()
+                .map(|&v| if range > 0.0 { ((v - low) / range).clamp(0.0, 1.0) * WHITE } else { 0.0 })
+                .collect::<Vec<f64>>()
+        }
+    }
+
+    /// Lays the images out left to right in rows of `columns`, separated by
+    /// white lines one pixel wide. Every image must be the same size.
+    pub fn tile(images: &[GrayImage], columns: usize) -> Result<GrayImage, DatasetError> {
+        let first: &GrayImage = images.first().ok_or(DatasetError::InconsistentRow)?;
+        if columns == 0 || images.iter()
+                .any(|image| image.width != first.width || image.height != first.height) {
+            return Err(DatasetError::InconsistentRow);
+        }
+
+        let rows: usize = images.len().div_ceil(columns);
+        let width: usize = columns * (first.width + 1) - 1;
+        let height: usize = rows * (first.height + 1) - 1;
+        let mut sheet: GrayImage = GrayImage { width, height, pixels: vec![WHITE; width * height] };
+
+        for (idx, image) in images.iter().enumerate() {
+            let left: usize = (idx % columns) * (first.width + 1);
+            let top: usize = (idx / columns) * (first.height + 1);
+            for y in 0..image.height {
+                for x in 0..image.width {
+                    sheet.pixels[(top + y) * width + left + x] = image.get(x, y);
+                }
+            }
+        }
+
+        Ok(sheet)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().map(|&v| v.round().clamp(0.0, WHITE) as u8).collect::<Vec<u8>>()
+    }
+
+    /// Encodes the image as a raw (`P5`) PGM with a maximum value of 255.
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.to_bytes());
+        bytes
+    }
+
+    /// Encodes the image as an 8-bit grayscale PNG.
+    pub fn to_png(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&self.to_bytes())?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Writes a PNG for paths ending in `.png` and a PGM otherwise.
+    pub fn to_file(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        let bytes: Vec<u8> = if file_path.to_lowercase().ends_with(".png") {
+            self.to_png()?
+        } else {
+            self.to_pgm()
+        };
+
+        Ok(fs::write(file_path, bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod image_tests {
+    use crate::ml::image::*;
+
+    #[test]
+    fn test_parse_pgm() {
+        let plain: GrayImage = GrayImage::parse_pgm(b"P2\n# a comment\n2 2\n15\n0 15\n 5 10\n").unwrap();
+        assert_eq!(plain.get_pixels(), &[0.0, 255.0, 85.0, 170.0]);
+
+        let raw: GrayImage = GrayImage::parse_pgm(b"P5 3 1 255\n\x00\x80\xff").unwrap();
+        assert_eq!(raw.get_pixels(), &[0.0, 128.0, 255.0]);
+        assert_eq!(raw.invert().get_pixels(), &[255.0, 127.0, 0.0]);
+
+        assert_eq!(GrayImage::parse_pgm(b"P6 1 1 255\n\x00\x00\x00").err(),
+            Some(DatasetError::InvalidHeader));
+        assert_eq!(GrayImage::parse_pgm(b"P5 2 2 255\n\x00").err(),
+            Some(DatasetError::InvalidValue));
+        assert_eq!(GrayImage::parse_pgm(b"P2 0 0 255\n").err(), Some(DatasetError::InvalidHeader));
+        assert_eq!(GrayImage::new(0, 3, Vec::new()).err(), Some(DatasetError::InvalidHeader));
+    }
+
+    #[test]
+    fn test_parse_png() {
+        let mut bytes: Vec<u8> = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 255, 255, 255, 0, 0, 0, 0]).unwrap();
+        }
+
+        // A transparent pixel reads as the white it is composited onto
+        let image: GrayImage = GrayImage::parse_png(&bytes).unwrap();
+        assert_eq!(image.get_pixels(), &[255.0, 255.0]);
+    }
+
+    #[test]
+    fn test_write_and_tile() {
+        let image: GrayImage = GrayImage::new(2, 1, vec![-1.0, 3.0]).unwrap().stretch(-1.0, 1.0);
+        assert_eq!(image.get_pixels(), &[0.0, 255.0]);
+        assert_eq!(GrayImage::parse_pgm(&image.to_pgm()).unwrap(), image);
+        assert_eq!(GrayImage::parse_png(&image.to_png().unwrap()).unwrap(), image);
+
+        let black: GrayImage = GrayImage::new(2, 1, vec![0.0, 0.0]).unwrap();
+        let sheet: GrayImage = GrayImage::tile(&[black.clone(), black.clone(), black], 2).unwrap();
+        assert_eq!((sheet.get_width(), sheet.get_height()), (5, 3));
+        assert_eq!(sheet.get_pixels(), &[
+            0.0, 0.0, 255.0, 0.0, 0.0,
+            255.0, 255.0, 255.0, 255.0, 255.0,
+            0.0, 0.0, 255.0, 255.0, 255.0
+        ]);
+        assert!(GrayImage::tile(&[], 2).is_err());
+    }
+
+    #[test]
+    fn test_fit_to_centers_ink() {
+        // A 2x2 blob in the corner of a 10x10 image
+        let mut pixels: Vec<f64> = vec![0.0; 100];
+        for &idx in [0, 1, 10, 11].iter() {
+            pixels[idx] = 255.0;
+        }
+        let image: GrayImage = GrayImage::new(10, 10, pixels).unwrap();
+
+        let fitted: GrayImage = image.fit_to(28, 28);
+        assert_eq!(fitted.to_features().len(), 784);
+        assert_relative_eq!(fitted.get(4, 4), 255.0, max_relative=1e-9);
+        assert_relative_eq!(fitted.get(23, 23), 255.0, max_relative=1e-9);
+        assert_eq!(fitted.get(3, 3), 0.0);
+        assert_eq!(fitted.get(24, 24), 0.0);
+
+        let half: GrayImage = GrayImage::new(2, 1, vec![0.0, 255.0]).unwrap().resize(1, 1);
+        assert_eq!(half.get_pixels(), &[127.5]);
+    }
+}
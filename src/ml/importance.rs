@@ -0,0 +1,229 @@
+extern crate rand;
+extern crate serde;
+
+use crate::ml::{dataset::Dataset, error::ModelError, metrics, model::Model, prediction, validation};
+use crate::ml::inspection::{self, ModelSummary, ScoreMeasure};
+use crate::model::gaussian_nb::GaussianParameters;
+use self::rand::{rngs::StdRng, seq::SliceRandom};
+use self::serde::Serialize;
+use std::vec::Vec;
+
+/// Most log likelihood terms held in memory at once while permuting, so
+/// wide test sets are scored a block of features at a time.
+const TERM_BUDGET: usize = 1 << 23;
+
+/// How much one feature matters to a trained model. Measures that do not
+/// apply to the feature, or were not computed, are `None`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FeatureImportance {
+    pub feature: usize,
+    /// The ANOVA F statistic of the class means, for Gaussian features.
+    pub f_statistic: Option<f64>,
+    /// The symmetric KL divergence, in nats, between the classes' learned
+    /// distributions of the feature; see `inspection::pairwise_divergence`.
+    /// `None` when the feature was constant within some class; see
+    /// `gaussian_divergences`.
+    pub divergence: Option<f64>,
+    /// The mean drop in test accuracy when the feature's values are
+    /// shuffled across the test rows.
+    pub permutation: Option<f64>,
+    /// The standard deviation of the drop across the shuffles.
+    pub permutation_std: Option<f64>
+}
+
+/// The class-separability scores of a model's summary, in feature order.
+pub fn separability(summary: &ModelSummary) -> Vec<FeatureImportance> {
+    let mut features: Vec<FeatureImportance> = (0..summary.num_features)
+        .map(|feature| FeatureImportance {
+            feature,
+            f_statistic: None,
+            divergence: None,
+            permutation: None,
+            permutation_std: None
+        })
+        .collect::<Vec<FeatureImportance>>();
+
+    for score in summary.features.iter() {
+        let importance: &mut FeatureImportance = &mut features[score.feature];
+        match score.measure {
+            ScoreMeasure::FStatistic => importance.f_statistic = Some(score.score),
+            ScoreMeasure::Divergence => importance.divergence = Some(score.score)
+        }
+    }
+
+    features
+}
+
+/// The symmetric KL divergence between the class Gaussians of each feature,
+/// combined across classes by `inspection::pairwise_divergence`. `None` for
+/// features that were constant within some class but differ between
+/// classes, whose divergence would only reflect the standard deviation
+/// floor; see `inspection::variance_floor`.
+pub fn gaussian_divergences(parameters: &GaussianParameters) -> Vec<Option<f64>> {
+    let priors: Vec<f64> = inspection::priors(&parameters.log_priors);
+    let num_features: usize = parameters.means.first().map_or(0, |means| means.len());
+    let floor: f64 = inspection::variance_floor(&parameters.stds);
+
+    (0..num_features).map(|feature| {
+        let class = |class: usize| (parameters.means[class][feature], parameters.stds[class][feature].powi(2));
+        let distributions: Vec<(f64, f64)> = (0..priors.len()).map(class).collect::<Vec<(f64, f64)>>();
+
+        let identical: bool = distributions.windows(2).all(|pair| pair[0] == pair[1]);
+        if !identical && distributions.iter().any(|&(_, variance)| variance <= floor) {
+            return None;
+        }
+
+        Some(inspection::pairwise_divergence(&priors, |a, b| {
+            let ((mean_a, variance_a), (mean_b, variance_b)): ((f64, f64), (f64, f64)) =
+                (distributions[a], distributions[b]);
+
+            inspection::gaussian_kl(mean_a, variance_a, mean_b, variance_b)
+                + inspection::gaussian_kl(mean_b, variance_b, mean_a, variance_a)
+        }))
+    }).collect::<Vec<Option<f64>>>()
+}
+
+/// The model's accuracy on the dataset, and the mean and standard deviation
+/// of how much it drops over `repeats` shuffles of each feature's values
+/// across the rows, in feature order.
+///
+/// A feature's likelihood terms depend only on its own value, so each
+/// shuffle rescores the rows by swapping that feature's terms rather than
+/// classifying them again. The accuracy is therefore that of the
+/// uncalibrated log likelihood terms, which stay finite where `predict`'s
+/// densities underflow, and can differ from the accuracy of `predict`.
+pub fn permutation_importance<M: Model>(model: &M, dataset: &Dataset, repeats: usize, seed: Option<u64>)
+        -> Result<(f64, Vec<(f64, f64)>), ModelError> {
+    let mapping: Vec<usize> = dataset.label_mapping(&model.label_names())
+        .map_err(|_| ModelError::LabelNotFound)?;
+    let actual: Vec<usize> = dataset.get_labels().iter()
+        .map(|&label| mapping[label])
+        .collect::<Vec<usize>>();
+
+    let (num_rows, num_labels, num_features): (usize, usize, usize) =
+        (dataset.len(), model.num_labels(), dataset.num_features());
+    let block: usize = (TERM_BUDGET / (num_rows * num_labels).max(1)).max(1);
+    let mut rng: StdRng = validation::make_rng(seed);
+
+    // Joint log10 likelihood of each row under each class, found on the
+    // first pass over the rows
+    let mut scores: Vec<Vec<f64>> = Vec::with_capacity(num_rows);
+    let mut baseline: f64 = 0.0;
+    let mut drops: Vec<(f64, f64)> = Vec::with_capacity(num_features);
+
+    for start in (0..num_features.max(1)).step_by(block) {
+        let end: usize = (start + block).min(num_features);
+
+        // The block's terms, indexed `[feature][row * num_labels + class]`
+        let mut terms: Vec<Vec<f64>> = vec![Vec::with_capacity(num_rows * num_labels); end - start];
+        for (_, sample) in dataset.iter() {
+            let (log_priors, row_terms): (Vec<f64>, Vec<Vec<f64>>) = model.log_likelihood_terms(sample)?;
+
+            if scores.len() < num_rows {
+                scores.push(log_priors.iter().zip(row_terms.iter())
+                    .map(|(log_prior, class_terms)| log_prior + class_terms.iter().sum::<f64>())
+                    .collect::<Vec<f64>>());
+            }
+            for (feature, feature_terms) in (start..end).zip(terms.iter_mut()) {
+                feature_terms.extend(row_terms.iter().map(|class_terms| class_terms[feature]));
+            }
+        }
+
+        if start == 0 {
+            baseline = accuracy(&scores, &actual, |_, _| 0.0);
+        }
+
+        for feature_terms in terms.iter() {
+            let shuffled: Vec<f64> = (0..repeats).map(|_| {
+                let mut order: Vec<usize> = (0..num_rows).collect::<Vec<usize>>();
+                order.shuffle(&mut rng);
+
+                baseline - accuracy(&scores, &actual, |row, class| feature_terms[order[row] * num_labels + class]
+                    - feature_terms[row * num_labels + class])
+            }).collect::<Vec<f64>>();
+
+            drops.push(metrics::mean_std(&shuffled));
+        }
+    }
+
+    Ok((baseline, drops))
+}
+
+/// The share of rows whose best class, after adding `change(row, class)` to
+/// each score, is the actual one.
+fn accuracy<C: Fn(usize, usize) -> f64>(scores: &[Vec<f64>], actual: &[usize], change: C) -> f64 {
+    let correct: usize = scores.iter().zip(actual.iter()).enumerate()
+        .filter(|(row, (row_scores, &label))| {
+            let changed: Vec<f64> = row_scores.iter().enumerate()
+                .map(|(class, score)| score + change(*row, class))
+                .collect::<Vec<f64>>();
+            prediction::argmax(&changed) == label
+        })
+        .count();
+
+    correct as f64 / scores.len() as f64
+}
+
+#[cfg(test)]
+mod importance_tests {
+    use crate::ml::{fitted_gaussian, importance::*, prediction::Prediction};
+    use crate::model::gaussian_nb::GaussianNaiveBayes;
+
+    fn fixture() -> (Dataset, GaussianNaiveBayes) {
+        // Feature 0 separates the classes, feature 1 only mostly, and
+        // feature 2 is the same for every row
+        fitted_gaussian(vec![0, 0, 0, 0, 1, 1, 1, 1], vec![
+            vec![0.0, 1.0, 3.0], vec![1.0, 0.0, 3.0], vec![0.5, 5.0, 3.0], vec![0.2, 0.5, 3.0],
+            vec![9.0, 5.0, 3.0], vec![8.0, 6.0, 3.0], vec![9.5, 4.0, 3.0], vec![8.5, 5.5, 3.0]
+        ])
+    }
+
+    #[test]
+    fn test_separability() {
+        let (_, model): (Dataset, GaussianNaiveBayes) = fixture();
+
+        let features: Vec<FeatureImportance> = separability(&model.summary().unwrap());
+        assert_eq!(features.iter().map(|f| f.feature).collect::<Vec<usize>>(), vec![0, 1, 2]);
+        assert!(features[0].f_statistic.unwrap() > features[1].f_statistic.unwrap());
+        assert_eq!(features[2].f_statistic, Some(0.0));
+        assert!(features.iter().all(|f| f.divergence.is_none() && f.permutation.is_none()));
+
+        let parameters: GaussianParameters = GaussianParameters {
+            labels: vec![String::from("a"), String::from("b")],
+            log_priors: vec![0.5f64.log10(), 0.5f64.log10()],
+            means: vec![vec![0.0, 1.0], vec![2.0, 1.0]],
+            stds: vec![vec![1.0, 1.0], vec![1.0, 2.0]],
+            binarize: None
+        };
+        let divergences: Vec<Option<f64>> = gaussian_divergences(&parameters);
+        assert_abs_diff_eq!(divergences[0].unwrap(), 4.0, epsilon=1e-12);
+        assert_abs_diff_eq!(divergences[1].unwrap(), 0.5 * (4.0 + 0.25) - 1.0, epsilon=1e-12);
+
+        // A feature constant within a class has no meaningful divergence
+        // unless every class agrees on it
+        let floored: GaussianParameters = GaussianParameters {
+            means: vec![vec![0.0, 3.0], vec![1.0, 3.0]],
+            stds: vec![vec![1e-10, 1e-10], vec![1.0, 1e-10]],
+            ..parameters
+        };
+        assert_eq!(gaussian_divergences(&floored), vec![None, Some(0.0)]);
+    }
+
+    #[test]
+    fn test_permutation_importance() {
+        let (dataset, model): (Dataset, GaussianNaiveBayes) = fixture();
+
+        let (baseline, drops): (f64, Vec<(f64, f64)>) =
+            permutation_importance(&model, &dataset, 5, Some(7)).unwrap();
+        let predictions: Vec<Prediction> = model.predict(&dataset, false).unwrap();
+        let correct: usize = predictions.iter().filter(|p| p.is_correct()).count();
+        assert_abs_diff_eq!(baseline, correct as f64 / dataset.len() as f64);
+
+        assert_eq!(drops.len(), 3);
+        assert!(drops[0].0 > 0.0);
+        assert_eq!(drops[2], (0.0, 0.0));
+
+        // The same seed shuffles the same way
+        assert_eq!(permutation_importance(&model, &dataset, 5, Some(7)).unwrap().1, drops);
+    }
+}
@@ -0,0 +1,231 @@
+extern crate serde;
+
+use crate::{ml::hyperparameters::MIN_STD, model::ModelKind};
+use self::serde::Serialize;
+use std::{fmt, string::String, vec::Vec};
+
+/// Share of a model's widest class variance below which a class variance
+/// is treated as a floor; see `variance_floor`.
+pub const FLOOR_VARIANCE_SHARE: f64 = 1e-6;
+
+/// What a trained model learned about one class.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClassSummary {
+    pub label: String,
+    pub sample_size: usize,
+    pub prior: f64,
+    /// Gaussian features whose training values in the class were all equal,
+    /// so only smoothing gives them any spread. `None` for models without
+    /// Gaussian features.
+    pub zero_variance_features: Option<usize>
+}
+
+/// How a feature's score measures how well it tells the classes apart.
+/// Scores of different measures are in different units, so features are
+/// only ranked against others scored the same way.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScoreMeasure {
+    /// The ANOVA F statistic of the class means, for Gaussian features.
+    /// Constant features score 0, and features constant within every class
+    /// but not across them score infinity; see `f_statistic`.
+    FStatistic,
+    /// The feature's share of the symmetric KL divergence, in nats, between
+    /// the classes' distributions; see `pairwise_divergence`. Used for count,
+    /// binary and categorical features.
+    Divergence
+}
+
+impl fmt::Display for ScoreMeasure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScoreMeasure::FStatistic => write!(f, "f-statistic"),
+            ScoreMeasure::Divergence => write!(f, "divergence")
+        }
+    }
+}
+
+/// How well one feature tells the classes apart.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FeatureScore {
+    pub feature: usize,
+    pub measure: ScoreMeasure,
+    pub score: f64
+}
+
+/// A description of a trained model, for inspecting it without reading
+/// the saved file.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ModelSummary {
+    pub kind: ModelKind,
+    pub calibrated: bool,
+    pub labels: Vec<String>,
+    pub num_features: usize,
+    /// How many of the features the model was trained on, when features
+    /// were selected.
+    pub selected_features: Option<usize>,
+    pub classes: Vec<ClassSummary>,
+    /// Every feature, grouped by measure and most discriminative first
+    /// within each.
+    pub features: Vec<FeatureScore>
+}
+
+impl ModelSummary {
+    /// Summarizes a model from its class sizes, log10 priors and feature
+    /// scores, in label and feature order.
+    pub fn new(kind: ModelKind, labels: Vec<String>, class_sizes: &[usize], log_priors: &[f64],
+            zero_variance_features: Option<Vec<usize>>, scores: &[(ScoreMeasure, f64)]) -> ModelSummary {
+        let priors: Vec<f64> = priors(log_priors);
+        let classes: Vec<ClassSummary> = labels.iter().enumerate()
+            .map(|(class, label)| ClassSummary {
+                label: label.clone(),
+                sample_size: class_sizes[class],
+                prior: priors[class],
+                zero_variance_features: zero_variance_features.as_ref().map(|counts| counts[class])
+            })
+            .collect::<Vec<ClassSummary>>();
+
+        let mut features: Vec<FeatureScore> = scores.iter().enumerate()
+            .map(|(feature, &(measure, score))| FeatureScore { feature, measure, score })
+            .collect::<Vec<FeatureScore>>();
+        features.sort_by(|a, b| a.measure.cmp(&b.measure).then(b.score.total_cmp(&a.score)));
+
+        ModelSummary {
+            kind,
+            calibrated: false,
+            labels,
+            num_features: scores.len(),
+            selected_features: None,
+            classes,
+            features
+        }
+    }
+
+    /// The `top` most discriminative features of each measure, grouped by
+    /// measure.
+    pub fn top_features(&self, top: usize) -> Vec<FeatureScore> {
+        self.features.chunk_by(|a, b| a.measure == b.measure)
+            .flat_map(|group| group.iter().take(top).cloned())
+            .collect::<Vec<FeatureScore>>()
+    }
+}
+
+/// The one-way ANOVA F statistic of a feature from each class's training
+/// rows, mean and sum of squared differences from that mean. Constant
+/// features score 0, and features constant within every class but not
+/// across them score infinity.
+pub fn f_statistic(class_sizes: &[usize], means: &[f64], square_mean_diffs: &[f64]) -> f64 {
+    let total: usize = class_sizes.iter().sum();
+    let num_classes: usize = class_sizes.iter().filter(|&&size| size > 0).count();
+    if num_classes < 2 || total <= num_classes {
+        return 0.0;
+    }
+
+    let mean: f64 = class_sizes.iter().zip(means.iter())
+        .map(|(&size, &mean)| size as f64 * mean)
+        .sum::<f64>() / total as f64;
+    let between: f64 = class_sizes.iter().zip(means.iter())
+        .map(|(&size, &class_mean)| size as f64 * (class_mean - mean).powi(2))
+        .sum::<f64>() / (num_classes - 1) as f64;
+    let within: f64 = square_mean_diffs.iter().sum::<f64>() / (total - num_classes) as f64;
+
+    if between == 0.0 { 0.0 } else { between / within }
+}
+
+/// The variance at or below which a class's variance is taken to be the
+/// training floor of a feature that was constant within the class, given
+/// every class's standard deviations. Divergences from such a feature only
+/// measure the floor, so they are left out rather than reported as huge.
+pub fn variance_floor(stds: &[Vec<f64>]) -> f64 {
+    let max_variance: f64 = stds.iter().flatten().map(|std| std.powi(2)).fold(0.0, f64::max);
+    (max_variance * FLOOR_VARIANCE_SHARE).max(MIN_STD.powi(2))
+}
+
+/// KL divergence of the normal distribution `q` from `p`, in nats.
+pub fn gaussian_kl(mean_p: f64, variance_p: f64, mean_q: f64, variance_q: f64) -> f64 {
+    0.5 * ((variance_q / variance_p).ln() + (variance_p + (mean_p - mean_q).powi(2)) / variance_q - 1.0)
+}
+
+/// Symmetric KL divergence between two distributions over the same
+/// outcomes, or the share of it from the given outcomes when they are only
+/// part of the distributions.
+pub fn discrete_divergence(p: &[f64], q: &[f64]) -> f64 {
+    p.iter().zip(q.iter())
+        .map(|(&p, &q)| (p - q) * (p.ln() - q.ln()))
+        .sum::<f64>()
+}
+
+/// The divergence between every pair of classes, averaged with each pair
+/// weighted by the product of their priors. A single class diverges from
+/// nothing.
+pub fn pairwise_divergence<D: Fn(usize, usize) -> f64>(priors: &[f64], divergence: D) -> f64 {
+    let mut total: f64 = 0.0;
+    let mut weights: f64 = 0.0;
+
+    for a in 0..priors.len() {
+        for b in a + 1..priors.len() {
+            let weight: f64 = priors[a] * priors[b];
+            total += weight * divergence(a, b);
+            weights += weight;
+        }
+    }
+
+    if weights > 0.0 { total / weights } else { 0.0 }
+}
+
+/// The log10 priors as probabilities.
+pub fn priors(log_priors: &[f64]) -> Vec<f64> {
+    log_priors.iter().map(|&log_prior| 10f64.powf(log_prior)).collect::<Vec<f64>>()
+}
+
+#[cfg(test)]
+mod inspection_tests {
+    use crate::ml::inspection::*;
+
+    #[test]
+    fn test_divergences() {
+        assert_abs_diff_eq!(gaussian_kl(1.0, 2.0, 1.0, 2.0), 0.0);
+        assert_abs_diff_eq!(gaussian_kl(0.0, 1.0, 2.0, 1.0), 2.0);
+        assert_abs_diff_eq!(gaussian_kl(0.0, 1.0, 0.0, 4.0), 0.5 * (4f64.ln() + 0.25 - 1.0));
+
+        assert_abs_diff_eq!(discrete_divergence(&[0.5, 0.5], &[0.5, 0.5]), 0.0);
+        assert_abs_diff_eq!(discrete_divergence(&[0.75, 0.25], &[0.25, 0.75]), 3f64.ln());
+
+        // Pairs weighted 0.5 * 0.25 twice and 0.25 * 0.25 once
+        let divergence: f64 = pairwise_divergence(&[0.5, 0.25, 0.25], |a, b| (a + b) as f64);
+        assert_abs_diff_eq!(divergence, (0.125 * 1.0 + 0.125 * 2.0 + 0.0625 * 3.0) / 0.3125);
+        assert_abs_diff_eq!(pairwise_divergence(&[1.0], |_, _| 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_f_statistic() {
+        // Classes [1, 2, 3] and [5, 6, 7]: between 24 / 1, within 4 / 4
+        assert_abs_diff_eq!(f_statistic(&[3, 3], &[2.0, 6.0], &[2.0, 2.0]), 24.0);
+        assert_abs_diff_eq!(f_statistic(&[3, 3], &[2.0, 2.0], &[2.0, 2.0]), 0.0);
+        assert_abs_diff_eq!(f_statistic(&[3, 3], &[0.0, 0.0], &[0.0, 0.0]), 0.0);
+        assert!(f_statistic(&[3, 3], &[0.0, 1.0], &[0.0, 0.0]).is_infinite());
+        assert_abs_diff_eq!(f_statistic(&[3, 0], &[2.0, 0.0], &[2.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_summary_ranks_features() {
+        let labels: Vec<String> = vec![String::from("a"), String::from("b")];
+        let summary: ModelSummary = ModelSummary::new(ModelKind::Gaussian, labels, &[3, 1],
+            &[0.75f64.log10(), 0.25f64.log10()], Some(vec![2, 0]),
+            &[(ScoreMeasure::Divergence, 0.5), (ScoreMeasure::Divergence, 3.0), (ScoreMeasure::Divergence, 1.0)]);
+
+        assert_eq!(summary.num_features, 3);
+        assert_eq!(summary.features.iter().map(|f| f.feature).collect::<Vec<usize>>(), vec![1, 2, 0]);
+        assert_abs_diff_eq!(summary.classes[0].prior, 0.75, epsilon=1e-12);
+        assert_eq!(summary.classes[0].zero_variance_features, Some(2));
+        assert_eq!(summary.classes[1].sample_size, 1);
+
+        // F statistics and divergences are ranked separately
+        let mixed: ModelSummary = ModelSummary::new(ModelKind::Mixed, vec![String::from("a"), String::from("b")],
+            &[1, 1], &[0.5f64.log10(), 0.5f64.log10()], None,
+            &[(ScoreMeasure::Divergence, 0.5), (ScoreMeasure::FStatistic, 0.1), (ScoreMeasure::FStatistic, 9.0),
+                (ScoreMeasure::Divergence, 2.0)]);
+        assert_eq!(mixed.features.iter().map(|f| f.feature).collect::<Vec<usize>>(), vec![2, 1, 3, 0]);
+        assert_eq!(mixed.top_features(1).iter().map(|f| f.feature).collect::<Vec<usize>>(), vec![2, 3]);
+    }
+}
@@ -1,3 +1,99 @@
+use crate::ml::error::DatasetError;
+use std::{cmp::Ordering, collections::HashMap, string::String, vec::Vec};
+
 pub trait Label {
     fn get_index(&self) -> usize;
-}
\ No newline at end of file
+
+    fn get_name(&self) -> &str;
+}
+
+/// Orders label names numerically when every name is a number, otherwise
+/// lexicographically, so the same set of names always gets the same indices.
+pub fn sort_names(names: &mut [String]) {
+    let numeric: Option<Vec<f64>> = names.iter()
+        .map(|name| name.parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>();
+
+    match numeric {
+        Some(_) => names.sort_by(|a, b| {
+            let (x, y): (f64, f64) = (a.parse::<f64>().unwrap(), b.parse::<f64>().unwrap());
+            x.partial_cmp(&y).unwrap_or(Ordering::Equal)
+        }),
+        None => names.sort()
+    }
+}
+
+/// For each name in `from`, the index of the same name in `to`.
+pub fn mapping(from: &[String], to: &[String]) -> Result<Vec<usize>, DatasetError> {
+    let lookup: HashMap<&str, usize> = to.iter().enumerate()
+        .map(|(idx, name)| (name.as_str(), idx))
+        .collect::<HashMap<&str, usize>>();
+
+    from.iter()
+        .map(|name| lookup.get(name.as_str()).copied().ok_or(DatasetError::UnknownLabel))
+        .collect::<Result<Vec<usize>, DatasetError>>()
+}
+
+/// Parses a labels file with one class per line, returning the names in
+/// index order. A line is either just the label name, or an index followed
+/// by the name. For compatibility with older files, an index followed by a
+/// number from 0 to 255 is read as an ASCII code, so "0 48" names the class
+/// "0". Indices only order the classes, so "1 a\n2 b" names classes 0 and 1,
+/// but no two lines may share one.
+pub fn parse_labels_file(contents: &str) -> Result<Vec<String>, DatasetError> {
+    let mut labels: Vec<(usize, String)> = contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(position, line)| {
+            let mut components = line.splitn(2, ' ');
+            let first: &str = components.next().unwrap_or("");
+            let rest: Option<&str> = components.next().map(|r| r.trim());
+
+            match (first.parse::<usize>(), rest) {
+                (Ok(index), Some(rest)) => match rest.parse::<u8>() {
+                    Ok(ascii) => (index, (ascii as char).to_string()),
+                    Err(_) => (index, rest.to_string())
+                },
+                _ => (position, line.to_string())
+            }
+        })
+        .collect::<Vec<(usize, String)>>();
+    labels.sort_by_key(|(index, _)| *index);
+
+    if labels.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err(DatasetError::DuplicateLabel);
+    }
+    Ok(labels.into_iter().map(|(_, name)| name).collect::<Vec<String>>())
+}
+
+#[cfg(test)]
+mod label_tests {
+    use crate::ml::{error::DatasetError, label::*};
+
+    #[test]
+    fn test_parse_labels_file_formats() {
+        let parsed = parse_labels_file("0 48\n1 49\n\n").unwrap();
+        assert_eq!(parsed, vec![String::from("0"), String::from("1")]);
+
+        let parsed = parse_labels_file("setosa\nversicolor\n2 virginica\n").unwrap();
+        assert_eq!(parsed, vec![String::from("setosa"), String::from("versicolor"), String::from("virginica")]);
+
+        // Gaps and a 1-based start are renumbered in order
+        let parsed = parse_labels_file("3 virginica\n1 setosa\n2 versicolor\n").unwrap();
+        assert_eq!(parsed, vec![String::from("setosa"), String::from("versicolor"), String::from("virginica")]);
+
+        assert_eq!(parse_labels_file("0 a\n0 b\n"), Err(DatasetError::DuplicateLabel));
+    }
+
+    #[test]
+    fn test_sort_names() {
+        let mut numeric: Vec<String> = vec!["10", "9", "0"].into_iter().map(String::from).collect();
+        sort_names(&mut numeric);
+        assert_eq!(numeric, vec!["0", "9", "10"]);
+
+        let mut text: Vec<String> = vec!["spam", "ham"].into_iter().map(String::from).collect();
+        sort_names(&mut text);
+        assert_eq!(text, vec!["ham", "spam"]);
+    }
+}
@@ -0,0 +1,240 @@
+extern crate memmap2;
+
+use crate::ml::{error::{ModelError, ModelFileError}, hyperparameters::Hyperparameters};
+use crate::ml::{label::Label, prediction};
+use crate::naivebayes::{class_label::ClassLabel, gaussian_classification};
+use self::memmap2::Mmap;
+use std::{convert::TryInto, error::Error, fs, io::{Read, Write}, string::String, vec::Vec};
+
+/// The first bytes of every memory-mapped model file.
+pub static MAGIC: &[u8; 8] = b"NBMAPPED";
+
+/// The newest layout this build reads and the one it writes.
+pub static VERSION: u32 = 1;
+
+/// File extension of memory-mapped models.
+pub static EXTENSION: &str = ".nbmap";
+
+// The header is the magic, the version (u32), four reserved bytes, the
+// number of classes and features (u64 each) and the binarize threshold,
+// NaN when unset. The log priors, then the means and standard deviations
+// of every class in turn follow as little-endian f64s, and the class names
+// as length-prefixed strings close the file.
+static HEADER_LEN: usize = 40;
+
+/// A Gaussian model read in place from a memory-mapped file. Only the class
+/// names are decoded when it is opened; the parameters are read straight
+/// from the mapped pages on every classification, so processes mapping the
+/// same file share one copy in the page cache.
+pub struct MappedModel {
+    map: Mmap,
+    num_labels: usize,
+    num_features: usize,
+    hyperparameters: Hyperparameters,
+    labels: Vec<ClassLabel>
+}
+
+/// Whether a model saved at this path should use the memory-mapped layout.
+pub fn is_mapped_path(file_path: &str) -> bool {
+    file_path.ends_with(EXTENSION)
+}
+
+/// Whether the file at the path starts with the memory-mapped magic bytes.
+pub fn detect(file_path: &str) -> Result<bool, Box<dyn Error>> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(MAGIC.len());
+    fs::File::open(file_path)?.take(MAGIC.len() as u64).read_to_end(&mut bytes)?;
+
+    Ok(bytes == MAGIC)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_f64(bytes: &[u8], offset: usize) -> f64 {
+    f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Writes a Gaussian model in the memory-mapped layout. The means and
+/// standard deviations hold every feature of the first class, then every
+/// feature of the second and so on.
+pub fn write_mapped(file_path: &str, label_names: &[String], log_priors: &[f64], means: &[f64],
+        stds: &[f64], binarize: Option<f64>) -> Result<(), Box<dyn Error>> {
+    let num_labels: usize = label_names.len();
+    let num_features: usize = means.len().checked_div(num_labels).unwrap_or(0);
+    if log_priors.len() != num_labels || means.len() != num_labels * num_features
+            || stds.len() != means.len() {
+        return Err(Box::new(ModelError::FeatureCountMismatch));
+    }
+
+    eprint!("Saving model to {}...", file_path);
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(HEADER_LEN + 8 * (num_labels + 2 * means.len()));
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(num_labels as u64).to_le_bytes());
+    bytes.extend_from_slice(&(num_features as u64).to_le_bytes());
+    bytes.extend_from_slice(&binarize.unwrap_or(f64::NAN).to_le_bytes());
+
+    for value in log_priors.iter().chain(means.iter()).chain(stds.iter()) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for name in label_names.iter() {
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    fs::File::create(file_path)?.write_all(&bytes)?;
+
+    eprintln!("done.");
+    Ok(())
+}
+
+impl MappedModel {
+    /// Maps the model file, checking its header and size without reading
+    /// the parameters.
+    pub fn open(file_path: &str) -> Result<MappedModel, Box<dyn Error>> {
+        let file: fs::File = fs::File::open(file_path)?;
+        // The file is only ever read, and saving writes a new file rather
+        // than changing a mapped one in place
+        let map: Mmap = unsafe { Mmap::map(&file)? };
+
+        if !map.starts_with(MAGIC) {
+            return Err(Box::new(ModelFileError::InvalidMagic));
+        }
+        if map.len() < HEADER_LEN {
+            return Err(Box::new(ModelFileError::InvalidEncoding));
+        }
+        let version: u32 = u32::from_le_bytes(map[8..12].try_into().unwrap());
+        if version == 0 || version > VERSION {
+            return Err(Box::new(ModelFileError::UnsupportedVersion));
+        }
+
+        let num_labels: usize = read_u64(&map, 16) as usize;
+        let num_features: usize = read_u64(&map, 24) as usize;
+        let binarize: f64 = read_f64(&map, 32);
+
+        let num_values: usize = num_features.checked_mul(2 * num_labels)
+            .and_then(|values| values.checked_add(num_labels))
+            .ok_or(ModelFileError::InvalidEncoding)?;
+        let mut offset: usize = num_values.checked_mul(8)
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .filter(|&end| end <= map.len())
+            .ok_or(ModelFileError::InvalidEncoding)?;
+
+        let mut names: Vec<String> = Vec::with_capacity(num_labels);
+        for _ in 0..num_labels {
+            let len: usize = map.get(offset..offset + 4)
+                .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+                .ok_or(ModelFileError::InvalidEncoding)?;
+            let name: &[u8] = map.get(offset + 4..offset + 4 + len)
+                .ok_or(ModelFileError::InvalidEncoding)?;
+            names.push(String::from_utf8(name.to_vec()).map_err(|_| ModelFileError::InvalidEncoding)?);
+            offset += 4 + len;
+        }
+
+        let hyperparameters: Hyperparameters = Hyperparameters {
+            binarize: if binarize.is_nan() { None } else { Some(binarize) },
+            ..Hyperparameters::default()
+        };
+
+        Ok(MappedModel { map, num_labels, num_features, hyperparameters, labels: ClassLabel::from_names(&names) })
+    }
+
+    pub fn num_labels(&self) -> usize {
+        self.num_labels
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    pub fn label_names(&self) -> Vec<String> {
+        self.labels.iter()
+            .map(|label| label.get_name().to_string())
+            .collect::<Vec<String>>()
+    }
+
+    pub fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError> {
+        match self.labels.get(index) {
+            Some(label) => Ok(Box::new(label.clone())),
+            None => Err(ModelError::LabelNotFound)
+        }
+    }
+
+    /// Joint log10 likelihood of the sample under each class, computed as
+    /// the Gaussian model does so both give identical results. Missing (NaN)
+    /// features are marginalized out.
+    pub fn log_likelihoods(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        if sample_features.len() != self.num_features {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        let means_start: usize = HEADER_LEN + 8 * self.num_labels;
+        let stds_start: usize = means_start + 8 * self.num_labels * self.num_features;
+
+        Ok((0..self.num_labels).map(|class| {
+            let row: usize = 8 * class * self.num_features;
+
+            let feature_likelihoods: f64 = sample_features.iter().enumerate()
+                .filter(|(_, value)| !value.is_nan())
+                .fold(0.0, |total, (idx, &value)| {
+                    let mean: f64 = read_f64(&self.map, means_start + row + 8 * idx);
+                    let std: f64 = read_f64(&self.map, stds_start + row + 8 * idx);
+                    let transformed: f64 = self.hyperparameters.transform(value);
+                    total + gaussian_classification::normal_pdf(transformed, mean, std).log10()
+                });
+
+            feature_likelihoods + read_f64(&self.map, HEADER_LEN + 8 * class)
+        }).collect::<Vec<f64>>())
+    }
+
+    pub fn classify(&self, sample_features: &[f64]) -> Result<Box<dyn Label>, ModelError> {
+        let log_likelihoods: Vec<f64> = self.log_likelihoods(sample_features)?;
+        self.label(prediction::argmax(&log_likelihoods))
+    }
+
+    pub fn posteriors(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        Ok(prediction::normalize_log10(&self.log_likelihoods(sample_features)?))
+    }
+}
+
+#[cfg(test)]
+mod mapped_tests {
+    use crate::ml::{dataset::Dataset, envelope, model::Model, temp_path};
+    use crate::ml::mapped::*;
+    use crate::model::{ModelKind, gaussian_nb::GaussianNaiveBayes};
+
+    #[test]
+    fn test_mapped_model_matches_gaussian() {
+        let names: Vec<String> = vec![String::from("low"), String::from("high")];
+        let samples: Vec<Vec<f64>> = vec![
+            vec![1.0, 2.0, 0.0], vec![2.0, 1.0, 0.0], vec![1.5, f64::NAN, 1.0],
+            vec![8.0, 9.0, 1.0], vec![9.0, 7.0, 1.0], vec![7.5, 8.0, 0.0]
+        ];
+        let dataset: Dataset = Dataset::with_label_names(names.clone(), vec![0, 0, 0, 1, 1, 1], samples);
+
+        let mut model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        model.fit(&dataset).unwrap();
+
+        let path = temp_path("mapped_model_test.nbmap");
+        let path: &str = path.to_str().unwrap();
+        model.to_mapped(path).unwrap();
+
+        let mapped: MappedModel = MappedModel::open(path).unwrap();
+        assert!(detect(path).unwrap());
+        assert_eq!(envelope::detect_kind(path).unwrap(), Some(ModelKind::Gaussian));
+        assert_eq!(mapped.num_features(), 3);
+        assert_eq!(mapped.label_names(), names);
+
+        for sample in [[1.2, 1.8, 0.0], [8.5, f64::NAN, 1.0], [5.0, 5.0, 0.5]].iter() {
+            assert_eq!(mapped.posteriors(sample).unwrap(), model.posteriors(sample).unwrap());
+            assert_eq!(mapped.classify(sample).unwrap().get_index(),
+                model.classify(sample).unwrap().get_index());
+        }
+        assert!(mapped.posteriors(&[1.0]).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
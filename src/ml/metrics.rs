@@ -0,0 +1,55 @@
+extern crate ndarray;
+
+use self::ndarray::{prelude::*, Array};
+use std::vec::Vec;
+
+/// One-vs-rest scores for a single class of a confusion matrix.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ClassMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub support: usize
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 { 0.0 } else { numerator as f64 / denominator as f64 }
+}
+
+/// Computes per-class metrics from a matrix with rows = actual label and
+/// columns = predicted label. Undefined ratios are reported as zero.
+pub fn class_metrics(confusion_matrix: &Array<usize, Ix2>) -> Vec<ClassMetrics> {
+    (0..confusion_matrix.nrows()).map(|class| {
+        let true_positives: usize = confusion_matrix[[class, class]];
+        let support: usize = confusion_matrix.row(class).sum();
+        let predicted: usize = confusion_matrix.column(class).sum();
+
+        let precision: f64 = ratio(true_positives, predicted);
+        let recall: f64 = ratio(true_positives, support);
+        let f1: f64 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        };
+
+        ClassMetrics {
+            precision,
+            recall,
+            f1,
+            support
+        }
+    }).collect::<Vec<ClassMetrics>>()
+}
+
+/// Mean and population standard deviation of the values.
+pub fn mean_std(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+
+    let n: f64 = values.len() as f64;
+    let mean: f64 = values.iter().sum::<f64>() / n;
+    let variance: f64 = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+
+    (mean, variance.sqrt())
+}
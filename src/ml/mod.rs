@@ -1,4 +1,53 @@
 pub mod model;
+pub mod binary;
+pub mod envelope;
+pub mod mapped;
 pub mod label;
 pub mod error;
-pub mod feature;
\ No newline at end of file
+pub mod feature;
+pub mod prediction;
+pub mod abstention;
+pub mod calibration;
+pub mod dataset;
+pub mod idx;
+pub mod sparse;
+pub mod arff;
+pub mod npy;
+pub mod pmml;
+pub mod onnx;
+pub mod codegen;
+pub mod inspection;
+pub mod diff;
+pub mod explanation;
+pub mod importance;
+pub mod selection;
+pub mod image;
+pub mod metrics;
+pub mod validation;
+pub mod hyperparameters;
+pub mod tuning;
+
+/// A path in the temporary directory that no other test run shares.
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("{}-{}", std::process::id(), name))
+}
+
+/// A dataset whose classes are named "a", "b", ... in label order, and a
+/// Gaussian model fitted to it.
+#[cfg(test)]
+pub(crate) fn fitted_gaussian(labels: Vec<usize>, samples: Vec<Vec<f64>>)
+        -> (dataset::Dataset, crate::model::gaussian_nb::GaussianNaiveBayes) {
+    use crate::ml::model::Model;
+
+    let num_labels: usize = labels.iter().max().map_or(0, |&label| label + 1);
+    let names: Vec<String> = (b'a'..).take(num_labels)
+        .map(|name| (name as char).to_string())
+        .collect::<Vec<String>>();
+    let dataset: dataset::Dataset = dataset::Dataset::with_label_names(names, labels, samples);
+
+    let mut model: crate::model::gaussian_nb::GaussianNaiveBayes =
+        crate::model::gaussian_nb::GaussianNaiveBayes::from_label_names(&[]);
+    model.fit(&dataset).unwrap();
+    (dataset, model)
+}
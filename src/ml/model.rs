@@ -1,31 +1,168 @@
 extern crate num_traits;
 extern crate ndarray;
+extern crate serde;
 
-use std::{vec::Vec, string::String, error::Error};
-use crate::ml::{label::Label, error::ModelError};
+use std::{vec::Vec, error::Error, fs, string::String};
+use crate::ml::{label::Label, error::{ModelError, ModelFileError, PmmlError}, dataset::Dataset};
+use crate::ml::{inspection::ModelSummary, pmml::PmmlModel};
+use crate::ml::{abstention::AbstentionRule, binary::{BinaryReader, BinaryWriter, Compression}};
+use crate::ml::envelope::{self, ModelEnvelope, ModelMetadata};
+use crate::model::ModelKind;
+use crate::ml::hyperparameters::Hyperparameters;
+use crate::ml::prediction::{self, Prediction};
 use self::ndarray::{prelude::*, Array};
 use self::num_traits::ToPrimitive;
+use self::serde::{Serialize, de::DeserializeOwned};
 use core::str::FromStr;
 
-pub trait Model {    
-    fn from_labels(file_path: &String) -> Self;
+pub trait Model {
+    fn from_labels(file_path: &str) -> Self;
 
-    fn from_json(file_path: &String) -> Self;
+    /// An untrained model for the named classes, indexed in the given order.
+    /// With no names, the classes are taken from the first dataset the model
+    /// is fit to.
+    fn from_label_names(label_names: &[String]) -> Self;
 
-    fn to_json(&self, file_path: &String);
+    fn from_json(file_path: &str) -> Self;
 
-    fn train<Num: ToPrimitive + Copy + FromStr>(&mut self, file_path: &String) 
-        -> Result<(), Box<dyn Error>>;
+    fn to_json(&self, file_path: &str) -> Result<(), Box<dyn Error>>;
 
-    fn test<Num: ToPrimitive + Copy + FromStr>
-        (&self, file_path: &String, multithreaded: bool) 
-        -> Result<f64, Box<dyn Error>>;
+    /// The kind of naive Bayes model, as recorded in saved model files.
+    fn kind() -> ModelKind;
+
+    fn from_binary(file_path: &str) -> Self;
+
+    fn to_binary(&self, file_path: &str, compression: Compression) -> Result<(), Box<dyn Error>>;
+
+    /// Writes the model's fields into the body of a binary model file.
+    fn encode_binary(&self, writer: &mut BinaryWriter);
+
+    /// Reads the fields written by `encode_binary`.
+    fn decode_binary(reader: &mut BinaryReader) -> Result<Self, ModelFileError> where Self: Sized;
+
+    /// Saves the model in the memory-mapped layout read by `MappedModel`,
+    /// which only some models support.
+    fn to_mapped(&self, _file_path: &str) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(ModelFileError::UnsupportedLayout))
+    }
+
+    /// Saves the model as an ONNX graph, which only some models support.
+    fn to_onnx(&self, _file_path: &str) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(ModelFileError::UnsupportedLayout))
+    }
+
+    /// The model as a PMML naive Bayes model, which only some models can be
+    /// expressed as.
+    fn to_pmml(&self) -> Result<PmmlModel, Box<dyn Error>> {
+        Err(Box::new(PmmlError::UnsupportedModel))
+    }
 
-    fn classify<Num: ToPrimitive + Copy + FromStr>
-        (&self, sample_features: &Vec<String>) 
+    fn from_pmml(_pmml: &PmmlModel) -> Result<Self, Box<dyn Error>> where Self: Sized {
+        Err(Box::new(PmmlError::UnsupportedModel))
+    }
+
+    fn num_labels(&self) -> usize;
+
+    /// Class names in index order.
+    fn label_names(&self) -> Vec<String>;
+
+    /// What the trained model learned about each class and feature.
+    fn summary(&self) -> Result<ModelSummary, ModelError>;
+
+    fn get_hyperparameters(&self) -> &Hyperparameters;
+
+    /// Replaces the training settings. Takes effect on the next `fit`.
+    fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters);
+
+    fn fit(&mut self, dataset: &Dataset) -> Result<(), Box<dyn Error>>;
+
+    fn predict(&self, dataset: &Dataset, multithreaded: bool)
+        -> Result<Vec<Prediction>, ModelError>;
+
+    fn classify(&self, sample_features: &[f64])
         -> Result<Box<dyn Label>, ModelError>;
 
+    fn posteriors(&self, sample_features: &[f64])
+        -> Result<Vec<f64>, ModelError>;
+
+    /// The log10 prior of each class, and the log10 likelihood of each of
+    /// the sample's features under each class, indexed `[class][feature]`.
+    /// Missing (NaN) features have a likelihood term of 0.
+    fn log_likelihood_terms(&self, sample_features: &[f64])
+        -> Result<(Vec<f64>, Vec<Vec<f64>>), ModelError>;
+
+    fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError>;
+
+    fn train<Num: ToPrimitive + Copy + FromStr>(&mut self, file_path: &str)
+            -> Result<(), Box<dyn Error>> {
+        self.fit(&Dataset::from_csv::<Num>(file_path)?)
+    }
+
+    fn evaluate<Num: ToPrimitive + Copy + FromStr>
+            (&self, file_path: &str, multithreaded: bool)
+            -> Result<Vec<Prediction>, Box<dyn Error>> {
+        let dataset: Dataset = Dataset::from_csv::<Num>(file_path)?;
+        Ok(self.predict(&dataset, multithreaded)?)
+    }
+
+    fn test<Num: ToPrimitive + Copy + FromStr>
+            (&self, file_path: &str, multithreaded: bool)
+            -> Result<f64, Box<dyn Error>> {
+        let predictions: Vec<Prediction> =
+            self.evaluate::<Num>(file_path, multithreaded)?;
+        let confusion_matrix: Array<usize, Ix2> =
+            prediction::confusion_matrix(&predictions, self.num_labels());
+
+        println!("Confusion Matrix:");
+        println!("{:?}\n", confusion_matrix);
+
+        Ok(Self::calculate_accuracy(&confusion_matrix))
+    }
+
+    /// Classifies the sample unless the rule rejects its posteriors, in which
+    /// case `None` is returned so the sample can be routed for review.
+    fn classify_or_abstain(&self, sample_features: &[f64], rule: &AbstentionRule)
+            -> Result<Option<Box<dyn Label>>, ModelError> {
+        let posteriors: Vec<f64> = self.posteriors(sample_features)?;
+
+        if rule.abstains(&posteriors) {
+            return Ok(None);
+        }
+
+        self.label(prediction::argmax(&posteriors)).map(Some)
+    }
+
     fn calculate_accuracy(confusion_matrix: &Array<usize, Ix2>) -> f64 {
         confusion_matrix.diag().sum() as f64 / confusion_matrix.sum() as f64
     }
-} 
+}
+
+/// Loads a model saved by `write_json`, migrating older schemas and
+/// panicking like the rest of the model loading code if the file is
+/// missing or malformed.
+pub fn read_json<M: DeserializeOwned>(file_path: &str, kind: ModelKind) -> M {
+    eprint!("Loading model from {}...", file_path);
+
+    let data = fs::read_to_string(file_path).expect("Unable to read JSON file.");
+    let value: serde_json::Value = serde_json::from_str(&data).expect("Could not deserialize JSON.");
+    let envelope: ModelEnvelope<M> = match envelope::from_value(value, kind) {
+        Ok(envelope) => envelope,
+        Err(e) => panic!("Could not deserialize JSON: {}", e)
+    };
+
+    eprintln!("done.");
+
+    envelope.model
+}
+
+/// Saves the model and its metadata as JSON.
+pub fn write_json<M: Serialize>(model: &M, metadata: &ModelMetadata, file_path: &str)
+        -> Result<(), Box<dyn Error>> {
+    eprint!("Saving model to {}...", file_path);
+
+    let envelope: ModelEnvelope<&M> = ModelEnvelope { metadata: metadata.clone(), model };
+    fs::write(file_path, serde_json::to_string(&envelope)?)?;
+
+    eprintln!("done.");
+    Ok(())
+}
@@ -0,0 +1,321 @@
+extern crate ndarray;
+extern crate zip;
+
+use crate::ml::{error::DatasetError, dataset::Dataset};
+use self::ndarray::{prelude::*, Array, ArrayD, IxDyn};
+use std::{error::Error, fs, io::{Read, Write}, string::String, vec::Vec};
+
+static MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Array names tried, in order, for the features and labels of an `.npz`.
+static FEATURE_NAMES: [&str; 3] = ["X", "x", "features"];
+static LABEL_NAMES: [&str; 2] = ["y", "labels"];
+
+/// The byte width of an element and how to widen it to `f64`.
+type ElementReader = (usize, fn(&[u8]) -> f64);
+
+/// An array stored in an `.npz` archive, with its name.
+pub type NamedArray = (String, ArrayD<f64>);
+
+/// Element types that can be written to an `.npy` file.
+pub trait NpyElement: Copy {
+    /// The NumPy type string, such as `<f8`.
+    fn descr() -> &'static str;
+
+    fn write_le(&self, out: &mut Vec<u8>);
+}
+
+impl NpyElement for f64 {
+    fn descr() -> &'static str {
+        "<f8"
+    }
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for usize {
+    fn descr() -> &'static str {
+        "<u8"
+    }
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(*self as u64).to_le_bytes());
+    }
+}
+
+/// Finds the value of `key` in a header dict such as
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }`.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, DatasetError> {
+    let pattern: String = format!("'{}':", key);
+    let start: usize = header.find(&pattern).ok_or(DatasetError::InvalidHeader)? + pattern.len();
+    let rest: &str = header[start..].trim_start();
+
+    // Tuples hold commas, so read shapes up to their closing parenthesis
+    let end: usize = if rest.starts_with('(') {
+        rest.find(')').ok_or(DatasetError::InvalidHeader)? + 1
+    } else {
+        rest.find([',', '}']).ok_or(DatasetError::InvalidHeader)?
+    };
+
+    Ok(rest[..end].trim())
+}
+
+/// Reads one little-endian (or single byte) element as `f64`.
+fn element_reader(descr: &str) -> Result<ElementReader, DatasetError> {
+    let reader: ElementReader = match descr {
+        "<f4" => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        "<f8" => (8, |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+        "|u1" | "<u1" => (1, |b| b[0] as f64),
+        "<u2" => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f64),
+        "<u4" => (4, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        "<u8" => (8, |b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64),
+        "|i1" | "<i1" => (1, |b| b[0] as i8 as f64),
+        "<i2" => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f64),
+        "<i4" => (4, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        "<i8" => (8, |b| i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64),
+        // Big-endian, complex, object and string arrays are not supported
+        _ => return Err(DatasetError::InvalidValue)
+    };
+
+    Ok(reader)
+}
+
+/// Parses the bytes of an `.npy` file into an array of `f64`.
+pub fn parse_npy(bytes: &[u8]) -> Result<ArrayD<f64>, DatasetError> {
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err(DatasetError::InvalidHeader);
+    }
+
+    // Version 1 stores the header length in two bytes, later versions in four
+    let (header_start, header_len): (usize, usize) = match bytes[6] {
+        1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+        2 | 3 if bytes.len() >= 12 =>
+            (12, u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize),
+        _ => return Err(DatasetError::InvalidHeader)
+    };
+    let data_start: usize = header_start + header_len;
+    let header: &str = bytes.get(header_start..data_start)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or(DatasetError::InvalidHeader)?;
+
+    let descr: &str = header_value(header, "descr")?.trim_matches('\'');
+    let fortran_order: bool = header_value(header, "fortran_order")? == "True";
+    let shape: Vec<usize> = header_value(header, "shape")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(|dim| dim.trim())
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>().map_err(|_| DatasetError::InvalidHeader))
+        .collect::<Result<Vec<usize>, DatasetError>>()?;
+
+    let (size, read): ElementReader = element_reader(descr)?;
+    let data: &[u8] = &bytes[data_start..];
+    let expected: usize = shape.iter()
+        .try_fold(size, |total, &dim| total.checked_mul(dim))
+        .ok_or(DatasetError::InvalidHeader)?;
+    if data.len() != expected {
+        return Err(DatasetError::InconsistentRow);
+    }
+
+    let values: Vec<f64> = data.chunks(size).map(read).collect::<Vec<f64>>();
+    let dims: IxDyn = IxDyn(&shape);
+    let array: Result<ArrayD<f64>, _> = if fortran_order {
+        Array::from_shape_vec(dims.f(), values)
+    } else {
+        Array::from_shape_vec(dims, values)
+    };
+
+    array.map_err(|_| DatasetError::InconsistentRow)
+}
+
+pub fn read_npy(file_path: &str) -> Result<ArrayD<f64>, Box<dyn Error>> {
+    Ok(parse_npy(&fs::read(file_path)?)?)
+}
+
+/// Reads every array in an `.npz` archive, named without the `.npy`
+/// extension. Both stored and deflated archives are supported.
+pub fn read_npz(file_path: &str) -> Result<Vec<NamedArray>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(file_path)?)?;
+    let mut arrays: Vec<NamedArray> = Vec::new();
+
+    for idx in 0..archive.len() {
+        let mut entry = archive.by_index(idx)?;
+        let name: String = entry.name().trim_end_matches(".npy").to_string();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        arrays.push((name, parse_npy(&bytes)?));
+    }
+
+    Ok(arrays)
+}
+
+/// Serializes an array as a version 1 `.npy` file in C order.
+pub fn to_npy_bytes<T: NpyElement, D: Dimension>(array: &Array<T, D>) -> Vec<u8> {
+    let shape: String = match array.shape() {
+        [single] => format!("({},)", single),
+        dims => format!("({})", dims.iter().map(|d| d.to_string()).collect::<Vec<String>>().join(", "))
+    };
+    let mut header: String = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        T::descr(), shape);
+
+    // Pad with spaces so the data starts on a 64 byte boundary
+    let unpadded: usize = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(unpadded + array.len() * 8);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+
+    // Iteration follows logical order, which is C order
+    for value in array.iter() {
+        value.write_le(&mut bytes);
+    }
+
+    bytes
+}
+
+pub fn write_npy<T: NpyElement, D: Dimension>(file_path: &str, array: &Array<T, D>)
+        -> Result<(), Box<dyn Error>> {
+    fs::File::create(file_path)?.write_all(&to_npy_bytes(array))?;
+    Ok(())
+}
+
+/// Names a class by its label value, dropping the fraction of whole numbers
+/// so that a label of `3.0` becomes class `3`.
+fn label_name(value: f64) -> String {
+    if value.fract() == 0.0 { format!("{}", value as i64) } else { value.to_string() }
+}
+
+/// Builds a dataset from a 2-D feature matrix and a vector of label values.
+pub fn to_dataset(features: &ArrayD<f64>, labels: &ArrayD<f64>) -> Result<Dataset, DatasetError> {
+    let features: ArrayView2<f64> = features.view().into_dimensionality::<Ix2>()
+        .map_err(|_| DatasetError::InconsistentRow)?;
+    let labels: ArrayView1<f64> = labels.view().into_dimensionality::<Ix1>()
+        .map_err(|_| DatasetError::InvalidLabel)?;
+
+    if features.nrows() != labels.len() {
+        return Err(DatasetError::InconsistentRow);
+    }
+
+    let mut dataset: Dataset = Dataset::default();
+    for (row, &label) in features.outer_iter().zip(labels.iter()) {
+        dataset.push_named(&label_name(label), row.to_vec())?;
+    }
+
+    dataset.sort_label_names();
+    Ok(dataset)
+}
+
+fn samples(features: &ArrayD<f64>) -> Result<Vec<Vec<f64>>, DatasetError> {
+    let features: ArrayView2<f64> = features.view().into_dimensionality::<Ix2>()
+        .map_err(|_| DatasetError::InconsistentRow)?;
+
+    Ok(features.outer_iter().map(|row| row.to_vec()).collect::<Vec<Vec<f64>>>())
+}
+
+fn find_array<'a>(arrays: &'a [NamedArray], names: &[&str])
+        -> Option<&'a ArrayD<f64>> {
+    names.iter().find_map(|name| arrays.iter()
+        .find(|(array_name, _)| array_name == name)
+        .map(|(_, array)| array))
+}
+
+/// Reads labelled data from `FEATURES.npy,LABELS.npy` or from an `.npz`
+/// holding the features as `X` (or `x`, `features`) and the labels as `y`
+/// (or `labels`).
+pub fn read_dataset(file_path: &str) -> Result<Dataset, Box<dyn Error>> {
+    if file_path.ends_with(".npz") {
+        let arrays: Vec<NamedArray> = read_npz(file_path)?;
+        let features: &ArrayD<f64> = find_array(&arrays, &FEATURE_NAMES)
+            .ok_or(DatasetError::UnknownColumn)?;
+        let labels: &ArrayD<f64> = find_array(&arrays, &LABEL_NAMES)
+            .ok_or(DatasetError::MissingLabel)?;
+
+        return Ok(to_dataset(features, labels)?);
+    }
+
+    let (features_path, labels_path) = file_path.split_once(',')
+        .ok_or(DatasetError::MissingLabel)?;
+    Ok(to_dataset(&read_npy(features_path)?, &read_npy(labels_path)?)?)
+}
+
+/// Reads the rows of a feature matrix, from an `.npy` or the features of
+/// an `.npz`. Any labels file after a comma is ignored.
+pub fn read_samples(file_path: &str) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    if file_path.ends_with(".npz") {
+        let arrays: Vec<NamedArray> = read_npz(file_path)?;
+        let features: &ArrayD<f64> = find_array(&arrays, &FEATURE_NAMES)
+            .ok_or(DatasetError::UnknownColumn)?;
+        return Ok(samples(features)?);
+    }
+
+    let features_path: &str = file_path.split(',').next().unwrap_or(file_path);
+    Ok(samples(&read_npy(features_path)?)?)
+}
+
+#[cfg(test)]
+mod npy_tests {
+    use crate::ml::npy::*;
+    use crate::ml::temp_path;
+
+    fn npy_bytes(descr: &str, fortran: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let header: String = format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}\n",
+            descr, if fortran { "True" } else { "False" }, shape);
+        let mut bytes: Vec<u8> = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_dtypes_and_order() {
+        let c_order: ArrayD<f64> = parse_npy(&npy_bytes("|u1", false, "(2, 3)", &[1, 2, 3, 4, 5, 6])).unwrap();
+        let f_order: ArrayD<f64> = parse_npy(&npy_bytes("|u1", true, "(2, 3)", &[1, 4, 2, 5, 3, 6])).unwrap();
+        assert_eq!(c_order, f_order);
+        assert_eq!(c_order[[1, 0]], 4.0);
+
+        let floats: Vec<u8> = [1.5f32, -2.0f32].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        let parsed: ArrayD<f64> = parse_npy(&npy_bytes("<f4", false, "(2,)", &floats)).unwrap();
+        assert_eq!(parsed.as_slice().unwrap(), &[1.5, -2.0]);
+
+        assert_eq!(parse_npy(&npy_bytes(">f8", false, "(1,)", &[0; 8])).err(), Some(DatasetError::InvalidValue));
+        assert_eq!(parse_npy(&npy_bytes("<u2", false, "(3,)", &[0; 4])).err(), Some(DatasetError::InconsistentRow));
+        assert_eq!(parse_npy(&npy_bytes("<f8", false, "(4294967296, 4294967296)", &[0; 8])).err(),
+            Some(DatasetError::InvalidHeader));
+    }
+
+    #[test]
+    fn test_round_trip_and_npz() {
+        let matrix: Array2<usize> = array![[3, 0], [1, 2]];
+        let parsed: ArrayD<f64> = parse_npy(&to_npy_bytes(&matrix)).unwrap();
+        assert_eq!(parsed.shape(), &[2, 2]);
+        assert_eq!(parsed[[1, 0]], 1.0);
+        assert_eq!(to_npy_bytes(&matrix).len() % 64, 32);
+
+        let path = temp_path("npy_test.npz");
+        {
+            let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            let options = zip::write::FileOptions::default();
+            writer.start_file("X.npy", options).unwrap();
+            writer.write_all(&to_npy_bytes(&array![[0.5, 1.0], [2.0, 3.0], [4.0, 5.0]])).unwrap();
+            writer.start_file("y.npy", options).unwrap();
+            writer.write_all(&to_npy_bytes(&array![2.0, 0.0, 2.0])).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let dataset: Dataset = read_dataset(path.to_str().unwrap()).unwrap();
+        assert_eq!(dataset.get_label_names(), &["0", "2"]);
+        assert_eq!(dataset.get_labels(), &[1, 0, 1]);
+        assert_eq!(dataset.get_sample(2), &[4.0, 5.0]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
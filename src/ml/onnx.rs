@@ -0,0 +1,304 @@
+extern crate serde_json;
+
+use crate::ml::error::ModelError;
+use std::{error::Error, f64::consts::{LN_10, PI}, fs, string::String, vec::Vec};
+
+/// File extension of ONNX models.
+pub static EXTENSION: &str = ".onnx";
+
+/// The operator set the exported graphs are written against.
+static OPSET_VERSION: i64 = 13;
+
+/// The ONNX IR version matching `OPSET_VERSION`.
+static IR_VERSION: i64 = 7;
+
+// Protocol buffer wire types and the ONNX tensor element types we use
+const VARINT: u64 = 0;
+const LENGTH_DELIMITED: u64 = 2;
+const INT64: i64 = 7;
+const DOUBLE: i64 = 11;
+const INT_ATTRIBUTE: i64 = 2;
+
+/// Whether a model saved at this path should be exported as ONNX.
+pub fn is_onnx_path(file_path: &str) -> bool {
+    file_path.ends_with(EXTENSION)
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_int(field: u64, value: i64, out: &mut Vec<u8>) {
+    write_varint(field << 3 | VARINT, out);
+    write_varint(value as u64, out);
+}
+
+fn write_bytes(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+    write_varint(field << 3 | LENGTH_DELIMITED, out);
+    write_varint(bytes.len() as u64, out);
+    out.extend_from_slice(bytes);
+}
+
+fn write_string(field: u64, s: &str, out: &mut Vec<u8>) {
+    write_bytes(field, s.as_bytes(), out);
+}
+
+/// A `TensorProto` of doubles stored as raw little-endian data.
+fn double_tensor(name: &str, dims: &[usize], values: &[f64]) -> Vec<u8> {
+    let mut tensor: Vec<u8> = Vec::new();
+    for &dim in dims.iter() {
+        write_int(1, dim as i64, &mut tensor);
+    }
+    write_int(2, DOUBLE, &mut tensor);
+    write_string(8, name, &mut tensor);
+    let data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>();
+    write_bytes(9, &data, &mut tensor);
+    tensor
+}
+
+fn int_tensor(name: &str, values: &[i64]) -> Vec<u8> {
+    let mut tensor: Vec<u8> = Vec::new();
+    write_int(1, values.len() as i64, &mut tensor);
+    write_int(2, INT64, &mut tensor);
+    write_string(8, name, &mut tensor);
+    let data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>();
+    write_bytes(9, &data, &mut tensor);
+    tensor
+}
+
+/// A `NodeProto` with integer attributes.
+fn node(op_type: &str, inputs: &[&str], outputs: &[&str], attributes: &[(&str, i64)]) -> Vec<u8> {
+    let mut node: Vec<u8> = Vec::new();
+    for input in inputs.iter() {
+        write_string(1, input, &mut node);
+    }
+    for output in outputs.iter() {
+        write_string(2, output, &mut node);
+    }
+    write_string(3, outputs[0], &mut node);
+    write_string(4, op_type, &mut node);
+    for (name, value) in attributes.iter() {
+        let mut attribute: Vec<u8> = Vec::new();
+        write_string(1, name, &mut attribute);
+        write_int(3, *value, &mut attribute);
+        write_int(20, INT_ATTRIBUTE, &mut attribute);
+        write_bytes(5, &attribute, &mut node);
+    }
+    node
+}
+
+/// A `ValueInfoProto` for a tensor whose first dimension is the batch size.
+fn value_info(name: &str, elem_type: i64, dims: &[usize]) -> Vec<u8> {
+    let mut shape: Vec<u8> = Vec::new();
+    let mut batch: Vec<u8> = Vec::new();
+    write_string(2, "N", &mut batch);
+    write_bytes(1, &batch, &mut shape);
+    for &dim in dims.iter() {
+        let mut fixed: Vec<u8> = Vec::new();
+        write_int(1, dim as i64, &mut fixed);
+        write_bytes(1, &fixed, &mut shape);
+    }
+
+    let mut tensor_type: Vec<u8> = Vec::new();
+    write_int(1, elem_type, &mut tensor_type);
+    write_bytes(2, &shape, &mut tensor_type);
+    let mut type_proto: Vec<u8> = Vec::new();
+    write_bytes(1, &tensor_type, &mut type_proto);
+
+    let mut info: Vec<u8> = Vec::new();
+    write_string(1, name, &mut info);
+    write_bytes(2, &type_proto, &mut info);
+    info
+}
+
+/// Compiles a Gaussian model into an ONNX graph of elementwise operators.
+/// The graph takes `features`, a batch of samples as doubles, and outputs
+/// the index of the most likely class as `label` and the posteriors as
+/// `probabilities`; the class names are kept in the `labels` metadata
+/// entry. Each class's log likelihood is the sum over features of
+/// `-0.5 * ((x - mean) / std)^2 - ln(std * sqrt(2 pi))` plus the log prior,
+/// with missing (NaN) features contributing nothing, as in `classify`.
+///
+/// These log densities are computed analytically, so they stay finite where
+/// `classify` takes the log of a density that has underflowed to zero and
+/// scores the class as impossible. The graph then
+/// still ranks such classes by how far off they are, and may pick a
+/// different class than `classify` for samples that every class finds
+/// impossibly unlikely in some feature, such as a stray mark on a pixel
+/// that is blank throughout the training images.
+///
+/// `log_priors` are log10 priors, and the means and standard deviations
+/// hold every feature of the first class, then every feature of the second
+/// and so on.
+pub fn gaussian_graph(label_names: &[String], log_priors: &[f64], means: &[f64], stds: &[f64],
+        binarize: Option<f64>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let num_labels: usize = label_names.len();
+    let num_features: usize = means.len().checked_div(num_labels).unwrap_or(0);
+    if log_priors.len() != num_labels || means.len() != num_labels * num_features
+            || stds.len() != means.len() {
+        return Err(Box::new(ModelError::FeatureCountMismatch));
+    }
+
+    let inv_stds: Vec<f64> = stds.iter().map(|std| 1.0 / std).collect::<Vec<f64>>();
+    let log_norms: Vec<f64> = stds.iter()
+        .map(|std| std.ln() + 0.5 * (2.0 * PI).ln())
+        .collect::<Vec<f64>>();
+    let ln_priors: Vec<f64> = log_priors.iter().map(|prior| prior * LN_10).collect::<Vec<f64>>();
+    let params: [usize; 2] = [num_labels, num_features];
+
+    let mut initializers: Vec<Vec<u8>> = vec![
+        double_tensor("means", &params, means),
+        double_tensor("inv_stds", &params, &inv_stds),
+        double_tensor("log_norms", &params, &log_norms),
+        double_tensor("log_priors", &[num_labels], &ln_priors),
+        double_tensor("neg_half", &[], &[-0.5]),
+        double_tensor("zero", &[], &[0.0]),
+        int_tensor("class_axis", &[1]),
+        int_tensor("feature_axis", &[2])
+    ];
+
+    // Samples become [N, 1, F] so they broadcast against [C, F] parameters
+    let mut nodes: Vec<Vec<u8>> = vec![node("Unsqueeze", &["features", "class_axis"], &["samples"], &[])];
+    let samples: &str = match binarize {
+        Some(threshold) => {
+            initializers.push(double_tensor("threshold", &[], &[threshold]));
+            initializers.push(double_tensor("one", &[], &[1.0]));
+            nodes.push(node("Greater", &["samples", "threshold"], &["above"], &[]));
+            nodes.push(node("Where", &["above", "one", "zero"], &["binarized"], &[]));
+            "binarized"
+        },
+        None => "samples"
+    };
+
+    nodes.extend(vec![
+        node("Sub", &[samples, "means"], &["centered"], &[]),
+        node("Mul", &["centered", "inv_stds"], &["scaled"], &[]),
+        node("Mul", &["scaled", "scaled"], &["squared"], &[]),
+        node("Mul", &["squared", "neg_half"], &["exponents"], &[]),
+        node("Sub", &["exponents", "log_norms"], &["densities"], &[]),
+        // NaN is the only value unequal to itself
+        node("Equal", &["samples", "samples"], &["present"], &[]),
+        node("Where", &["present", "densities", "zero"], &["observed"], &[]),
+        node("ReduceSum", &["observed", "feature_axis"], &["likelihoods"], &[("keepdims", 0)]),
+        node("Add", &["likelihoods", "log_priors"], &["scores"], &[]),
+        node("ArgMax", &["scores"], &["label"], &[("axis", 1), ("keepdims", 0)]),
+        node("Softmax", &["scores"], &["probabilities"], &[("axis", 1)])
+    ]);
+
+    let mut graph: Vec<u8> = Vec::new();
+    for node in nodes.iter() {
+        write_bytes(1, node, &mut graph);
+    }
+    write_string(2, "gaussian_naive_bayes", &mut graph);
+    for initializer in initializers.iter() {
+        write_bytes(5, initializer, &mut graph);
+    }
+    write_bytes(11, &value_info("features", DOUBLE, &[num_features]), &mut graph);
+    write_bytes(12, &value_info("label", INT64, &[]), &mut graph);
+    write_bytes(12, &value_info("probabilities", DOUBLE, &[num_labels]), &mut graph);
+
+    let mut opset: Vec<u8> = Vec::new();
+    write_string(1, "", &mut opset);
+    write_int(2, OPSET_VERSION, &mut opset);
+
+    let mut labels: Vec<u8> = Vec::new();
+    write_string(1, "labels", &mut labels);
+    write_string(2, &serde_json::to_string(label_names)?, &mut labels);
+
+    let mut model: Vec<u8> = Vec::new();
+    write_int(1, IR_VERSION, &mut model);
+    write_string(2, env!("CARGO_PKG_NAME"), &mut model);
+    write_string(3, env!("CARGO_PKG_VERSION"), &mut model);
+    write_bytes(7, &graph, &mut model);
+    write_bytes(8, &opset, &mut model);
+    write_bytes(14, &labels, &mut model);
+    Ok(model)
+}
+
+/// Writes a Gaussian model as an ONNX graph; see `gaussian_graph`.
+pub fn write_gaussian(file_path: &str, label_names: &[String], log_priors: &[f64], means: &[f64],
+        stds: &[f64], binarize: Option<f64>) -> Result<(), Box<dyn Error>> {
+    let bytes: Vec<u8> = gaussian_graph(label_names, log_priors, means, stds, binarize)?;
+
+    eprint!("Saving model to {}...", file_path);
+    fs::write(file_path, bytes)?;
+    eprintln!("done.");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod onnx_tests {
+    use crate::ml::{dataset::{Dataset, DatasetSpec}, hyperparameters::Hyperparameters, model::Model, temp_path};
+    use crate::model::gaussian_nb::GaussianNaiveBayes;
+    use std::fs;
+    use tract_onnx::prelude::*;
+
+    fn run_graph(path: &str, dataset: &Dataset) -> (Vec<i64>, Vec<f64>) {
+        let samples: Vec<f64> = dataset.iter().flat_map(|(_, sample)| sample.to_vec()).collect::<Vec<f64>>();
+        let shape: [usize; 2] = [dataset.len(), dataset.num_features()];
+
+        let graph = tract_onnx::onnx().model_for_path(path).unwrap()
+            .with_input_fact(0, f64::fact(shape).into()).unwrap()
+            .with_output_fact(0, InferenceFact::default()).unwrap()
+            .with_output_fact(1, InferenceFact::default()).unwrap()
+            .into_optimized().unwrap()
+            .into_runnable().unwrap();
+        let input: Tensor = tract_ndarray::Array2::from_shape_vec(shape, samples).unwrap().into();
+        let outputs = graph.run(tvec!(input.into())).unwrap();
+
+        (outputs[0].as_slice::<i64>().unwrap().to_vec(), outputs[1].as_slice::<f64>().unwrap().to_vec())
+    }
+
+    #[test]
+    fn test_graph_matches_classify() {
+        let train_path = temp_path("onnx_train_test.csv");
+        let test_path = temp_path("onnx_test_test.csv");
+        fs::write(&train_path, "label,x,y,z\n\
+            a,1,2,7\na,2,1.5,6\na,1.5,3,7.5\nb,8,9,7\nb,9,7,6.5\nb,7.5,8,8\nc,4,1,1\nc,5,2,0\n").unwrap();
+        fs::write(&test_path, "label,x,y,z\n\
+            a,1.2,2.2,7\nb,8.5,8,7\nc,4.5,1.5,0.5\nb,?,8,7\na,5,5,5\n").unwrap();
+
+        let spec: DatasetSpec = DatasetSpec::default();
+        let train: Dataset = spec.read::<f64>(train_path.to_str().unwrap()).unwrap();
+        let test: Dataset = spec.read::<f64>(test_path.to_str().unwrap()).unwrap();
+
+        let mut model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        model.fit(&train).unwrap();
+
+        let path = temp_path("onnx_graph_test.onnx");
+        let path: &str = path.to_str().unwrap();
+        model.to_onnx(path).unwrap();
+
+        let (labels, probabilities) = run_graph(path, &test);
+        for (idx, (_, sample)) in test.iter().enumerate() {
+            assert_eq!(labels[idx] as usize, model.classify(sample).unwrap().get_index());
+
+            let posteriors: Vec<f64> = model.posteriors(sample).unwrap();
+            let row: &[f64] = &probabilities[idx * 3..(idx + 1) * 3];
+            for (expected, actual) in posteriors.iter().zip(row.iter()) {
+                assert_abs_diff_eq!(expected, actual, epsilon=1e-9);
+            }
+        }
+
+        // Binarized inputs go through the threshold nodes first
+        let mut hyperparameters: Hyperparameters = *model.get_hyperparameters();
+        hyperparameters.binarize = Some(3.0);
+        model.set_hyperparameters(hyperparameters);
+        model.fit(&train).unwrap();
+        model.to_onnx(path).unwrap();
+
+        let (labels, _) = run_graph(path, &test);
+        for (idx, (_, sample)) in test.iter().enumerate() {
+            assert_eq!(labels[idx] as usize, model.classify(sample).unwrap().get_index());
+        }
+
+        for path in [train_path.to_str().unwrap(), test_path.to_str().unwrap(), path].iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
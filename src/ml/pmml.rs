@@ -0,0 +1,434 @@
+extern crate roxmltree;
+
+use crate::ml::{error::PmmlError, hyperparameters::{Hyperparameters, PriorMode}};
+use self::roxmltree::{Document, Node};
+use std::{error::Error, fmt::Write, fs, string::String, vec::Vec};
+
+/// File extension of PMML documents.
+pub static EXTENSION: &str = ".pmml";
+
+static NAMESPACE: &str = "http://www.dmg.org/PMML-4_4";
+
+/// Name of the target field; input fields are named `x0`, `x1` and so on.
+static TARGET: &str = "class";
+
+/// Class counts that are not whole numbers, as from tools that write the
+/// priors as probabilities, are scaled to about this many rows so rounding
+/// them to whole rows barely moves the priors.
+static FRACTIONAL_ROWS: f64 = 1e6;
+
+/// How one input field is distributed within each class.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BayesInput {
+    /// A continuous field with a normal distribution per class.
+    Gaussian { means: Vec<f64>, variances: Vec<f64> },
+    /// A categorical field with the count of each value in each class,
+    /// indexed `[class][value]`.
+    Categorical { values: Vec<String>, counts: Vec<Vec<f64>> }
+}
+
+/// A naive Bayes model as a PMML `NaiveBayesModel` describes it. Counts are
+/// stored already smoothed, so any PMML consumer reproduces our
+/// probabilities; the smoothing is recorded in `Extension` elements so it
+/// can be taken back out when the document is imported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PmmlModel {
+    pub labels: Vec<String>,
+    pub inputs: Vec<BayesInput>,
+    pub class_counts: Vec<f64>,
+    pub hyperparameters: Hyperparameters
+}
+
+/// Whether a model saved at this path should be exported as PMML.
+pub fn is_pmml_path(file_path: &str) -> bool {
+    file_path.ends_with(EXTENSION)
+}
+
+/// The BayesOutput count of each class, whose proportions are the priors
+/// the model uses.
+pub fn prior_counts(class_sizes: &[usize], hyperparameters: &Hyperparameters) -> Vec<f64> {
+    match hyperparameters.prior {
+        PriorMode::Uniform => vec![1.0; class_sizes.len()],
+        PriorMode::Empirical => class_sizes.iter()
+            .map(|&size| size as f64 + hyperparameters.alpha)
+            .collect::<Vec<f64>>()
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn field_name(idx: usize) -> String {
+    format!("x{}", idx)
+}
+
+impl PmmlModel {
+    /// The number of training rows in each class, as recorded in the priors.
+    /// Fractional counts are scaled up first, so their proportions survive.
+    pub fn class_sizes(&self) -> Vec<usize> {
+        let alpha: f64 = match self.hyperparameters.prior {
+            PriorMode::Empirical => self.hyperparameters.alpha,
+            PriorMode::Uniform => 0.0
+        };
+        let total: f64 = self.class_counts.iter().sum::<f64>();
+        let scale: f64 = if self.class_counts.iter().all(|&count| (count - alpha).fract() == 0.0) || total <= 0.0 {
+            1.0
+        } else {
+            FRACTIONAL_ROWS / total
+        };
+
+        self.class_counts.iter()
+            .map(|&count| (count * scale - alpha).round().max(0.0) as usize)
+            .collect::<Vec<usize>>()
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml: String = String::new();
+        let h: &Hyperparameters = &self.hyperparameters;
+        let prior: &str = match h.prior {
+            PriorMode::Empirical => "empirical",
+            PriorMode::Uniform => "uniform"
+        };
+
+        // Writing to a String cannot fail
+        let _ = writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        let _ = writeln!(xml, "<PMML xmlns=\"{}\" version=\"4.4\">", NAMESPACE);
+        let _ = writeln!(xml, "  <Header description=\"Naive Bayes classifier\">");
+        let _ = writeln!(xml, "    <Application name=\"{}\" version=\"{}\"/>",
+            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(xml, "  </Header>");
+
+        let _ = writeln!(xml, "  <DataDictionary numberOfFields=\"{}\">", self.inputs.len() + 1);
+        for (idx, input) in self.inputs.iter().enumerate() {
+            match input {
+                BayesInput::Gaussian { .. } => {
+                    let _ = writeln!(xml, "    <DataField name=\"{}\" optype=\"continuous\" dataType=\"double\"/>",
+                        field_name(idx));
+                },
+                BayesInput::Categorical { values, .. } => {
+                    let _ = writeln!(xml, "    <DataField name=\"{}\" optype=\"categorical\" dataType=\"string\">",
+                        field_name(idx));
+                    for value in values.iter() {
+                        let _ = writeln!(xml, "      <Value value=\"{}\"/>", escape(value));
+                    }
+                    let _ = writeln!(xml, "    </DataField>");
+                }
+            }
+        }
+        let _ = writeln!(xml, "    <DataField name=\"{}\" optype=\"categorical\" dataType=\"string\">", TARGET);
+        for label in self.labels.iter() {
+            let _ = writeln!(xml, "      <Value value=\"{}\"/>", escape(label));
+        }
+        let _ = writeln!(xml, "    </DataField>");
+        let _ = writeln!(xml, "  </DataDictionary>");
+
+        let _ = writeln!(xml, "  <NaiveBayesModel functionName=\"classification\" threshold=\"0\">");
+        let _ = writeln!(xml, "    <Extension name=\"prior\" value=\"{}\"/>", prior);
+        let _ = writeln!(xml, "    <Extension name=\"alpha\" value=\"{}\"/>", h.alpha);
+        let _ = writeln!(xml, "    <Extension name=\"smoothing\" value=\"{}\"/>", h.smoothing);
+
+        let _ = writeln!(xml, "    <MiningSchema>");
+        for idx in 0..self.inputs.len() {
+            let _ = writeln!(xml, "      <MiningField name=\"{}\"/>", field_name(idx));
+        }
+        let _ = writeln!(xml, "      <MiningField name=\"{}\" usageType=\"target\"/>", TARGET);
+        let _ = writeln!(xml, "    </MiningSchema>");
+
+        let _ = writeln!(xml, "    <BayesInputs>");
+        for (idx, input) in self.inputs.iter().enumerate() {
+            let _ = writeln!(xml, "      <BayesInput fieldName=\"{}\">", field_name(idx));
+            match input {
+                BayesInput::Gaussian { means, variances } => {
+                    let _ = writeln!(xml, "        <TargetValueStats>");
+                    for ((label, mean), variance) in self.labels.iter().zip(means).zip(variances) {
+                        let _ = writeln!(xml, "          <TargetValueStat value=\"{}\">", escape(label));
+                        let _ = writeln!(xml, "            <GaussianDistribution mean=\"{}\" variance=\"{}\"/>",
+                            mean, variance);
+                        let _ = writeln!(xml, "          </TargetValueStat>");
+                    }
+                    let _ = writeln!(xml, "        </TargetValueStats>");
+                },
+                BayesInput::Categorical { values, counts } => {
+                    for (value_idx, value) in values.iter().enumerate() {
+                        let _ = writeln!(xml, "        <PairCounts value=\"{}\">", escape(value));
+                        let _ = writeln!(xml, "          <TargetValueCounts>");
+                        for (label, class_counts) in self.labels.iter().zip(counts) {
+                            let _ = writeln!(xml, "            <TargetValueCount value=\"{}\" count=\"{}\"/>",
+                                escape(label), class_counts[value_idx]);
+                        }
+                        let _ = writeln!(xml, "          </TargetValueCounts>");
+                        let _ = writeln!(xml, "        </PairCounts>");
+                    }
+                }
+            }
+            let _ = writeln!(xml, "      </BayesInput>");
+        }
+        let _ = writeln!(xml, "    </BayesInputs>");
+
+        let _ = writeln!(xml, "    <BayesOutput fieldName=\"{}\">", TARGET);
+        let _ = writeln!(xml, "      <TargetValueCounts>");
+        for (label, count) in self.labels.iter().zip(self.class_counts.iter()) {
+            let _ = writeln!(xml, "        <TargetValueCount value=\"{}\" count=\"{}\"/>", escape(label), count);
+        }
+        let _ = writeln!(xml, "      </TargetValueCounts>");
+        let _ = writeln!(xml, "    </BayesOutput>");
+        let _ = writeln!(xml, "  </NaiveBayesModel>");
+        let _ = writeln!(xml, "</PMML>");
+
+        xml
+    }
+
+    /// Reads a PMML `NaiveBayesModel` with continuous fields described by
+    /// `GaussianDistribution`s and categorical ones by `PairCounts`. Inputs
+    /// are taken in data dictionary order. Documents without our extensions
+    /// are read as unsmoothed counts and empirical priors.
+    pub fn parse(xml: &str) -> Result<PmmlModel, Box<dyn Error>> {
+        let document: Document = Document::parse(xml)?;
+        let model: Node = child(document.root_element(), "NaiveBayesModel")?;
+        let threshold: f64 = match model.attribute("threshold") {
+            Some(_) => number(model, "threshold")?,
+            None => 0.0
+        };
+        let output: Node = child(model, "BayesOutput")?;
+        let target: &str = output.attribute("fieldName").ok_or(PmmlError::InvalidDocument)?;
+
+        let fields: Vec<Node> = children(child(document.root_element(), "DataDictionary")?, "DataField");
+        let values_of = |field: &Node| children(*field, "Value").iter()
+            .filter_map(|value| value.attribute("value").map(String::from))
+            .collect::<Vec<String>>();
+
+        let target_field: Option<&Node> = fields.iter().find(|field| field.attribute("name") == Some(target));
+        let output_counts: Vec<(String, f64)> = target_value_counts(child(output, "TargetValueCounts")?)?;
+        let labels: Vec<String> = match target_field.map(values_of) {
+            Some(values) if !values.is_empty() => values,
+            _ => output_counts.iter().map(|(label, _)| label.clone()).collect::<Vec<String>>()
+        };
+        let label_index = |label: &str| labels.iter().position(|name| name == label)
+            .ok_or(PmmlError::UnknownValue);
+
+        let mut class_counts: Vec<f64> = vec![0.0; labels.len()];
+        for (label, count) in output_counts.iter() {
+            class_counts[label_index(label)?] = *count;
+        }
+
+        let inputs_node: Node = child(model, "BayesInputs")?;
+        let mut inputs: Vec<BayesInput> = Vec::new();
+        for field in fields.iter().filter(|field| field.attribute("name") != Some(target)) {
+            let name: &str = field.attribute("name").ok_or(PmmlError::InvalidDocument)?;
+            let input: Node = children(inputs_node, "BayesInput").into_iter()
+                .find(|input| input.attribute("fieldName") == Some(name))
+                .ok_or(PmmlError::InvalidDocument)?;
+
+            if let Ok(stats) = child(input, "TargetValueStats") {
+                let mut means: Vec<f64> = vec![0.0; labels.len()];
+                let mut variances: Vec<f64> = vec![1.0; labels.len()];
+                for stat in children(stats, "TargetValueStat") {
+                    let class: usize = label_index(stat.attribute("value").unwrap_or_default())?;
+                    let distribution: Node = child(stat, "GaussianDistribution")?;
+                    means[class] = number(distribution, "mean")?;
+                    variances[class] = number(distribution, "variance")?;
+                }
+                inputs.push(BayesInput::Gaussian { means, variances });
+            } else {
+                let pairs: Vec<Node> = children(input, "PairCounts");
+                let mut values: Vec<String> = values_of(field);
+                if values.is_empty() {
+                    values = pairs.iter().filter_map(|pair| pair.attribute("value").map(String::from))
+                        .collect::<Vec<String>>();
+                }
+
+                let mut counts: Vec<Vec<f64>> = vec![vec![0.0; values.len()]; labels.len()];
+                for pair in pairs.iter() {
+                    let value: &str = pair.attribute("value").ok_or(PmmlError::InvalidDocument)?;
+                    let value_idx: usize = values.iter().position(|name| name == value)
+                        .ok_or(PmmlError::UnknownValue)?;
+                    for (label, count) in target_value_counts(child(*pair, "TargetValueCounts")?)? {
+                        counts[label_index(&label)?][value_idx] = count;
+                    }
+                }
+                if threshold > 0.0 {
+                    for class_counts in counts.iter_mut() {
+                        apply_threshold(class_counts, threshold)?;
+                    }
+                }
+                inputs.push(BayesInput::Categorical { values, counts });
+            }
+        }
+
+        let extension = |name: &str| children(model, "Extension").into_iter()
+            .find(|extension| extension.attribute("name") == Some(name))
+            .and_then(|extension| extension.attribute("value"));
+        let hyperparameters: Hyperparameters = Hyperparameters {
+            prior: extension("prior").map_or(Ok(PriorMode::Empirical), str::parse::<PriorMode>)?,
+            alpha: extension("alpha").map_or(Ok(0.0), str::parse::<f64>)?,
+            smoothing: extension("smoothing").map_or(Ok(0.0), str::parse::<f64>)?,
+            ..Hyperparameters::default()
+        };
+
+        Ok(PmmlModel { labels, inputs, class_counts, hyperparameters })
+    }
+}
+
+/// Gives the values a class never saw the count that makes their
+/// probability `threshold`, which PMML uses in place of a zero probability.
+fn apply_threshold(counts: &mut [f64], threshold: f64) -> Result<(), PmmlError> {
+    let total: f64 = counts.iter().sum::<f64>();
+    let num_unseen: f64 = counts.iter().filter(|&&count| count == 0.0).count() as f64;
+    if num_unseen == 0.0 || total <= 0.0 {
+        return Ok(());
+    }
+    if num_unseen * threshold >= 1.0 {
+        return Err(PmmlError::InvalidDocument);
+    }
+
+    // Each unseen value gets t / (1 - m t) of the seen total, so its share
+    // of the new total is exactly t
+    let unseen: f64 = threshold * total / (1.0 - num_unseen * threshold);
+    for count in counts.iter_mut().filter(|count| **count == 0.0) {
+        *count = unseen;
+    }
+    Ok(())
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Vec<Node<'a, 'input>> {
+    node.children()
+        .filter(|child| child.is_element() && child.tag_name().name() == name)
+        .collect::<Vec<Node>>()
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<Node<'a, 'input>, PmmlError> {
+    children(node, name).into_iter().next().ok_or(PmmlError::InvalidDocument)
+}
+
+fn number(node: Node, attribute: &str) -> Result<f64, PmmlError> {
+    node.attribute(attribute)
+        .and_then(|value| value.parse::<f64>().ok())
+        .ok_or(PmmlError::InvalidDocument)
+}
+
+fn target_value_counts(node: Node) -> Result<Vec<(String, f64)>, PmmlError> {
+    children(node, "TargetValueCount").iter()
+        .map(|count| Ok((count.attribute("value").ok_or(PmmlError::InvalidDocument)?.to_string(),
+            number(*count, "count")?)))
+        .collect::<Result<Vec<(String, f64)>, PmmlError>>()
+}
+
+pub fn read_pmml(file_path: &str) -> Result<PmmlModel, Box<dyn Error>> {
+    eprint!("Loading model from {}...", file_path);
+    let model: PmmlModel = PmmlModel::parse(&fs::read_to_string(file_path)?)?;
+    eprintln!("done.");
+
+    Ok(model)
+}
+
+pub fn write_pmml(model: &PmmlModel, file_path: &str) -> Result<(), Box<dyn Error>> {
+    eprint!("Saving model to {}...", file_path);
+    fs::write(file_path, model.to_xml())?;
+    eprintln!("done.");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod pmml_tests {
+    use crate::ml::pmml::*;
+    use crate::ml::{arff::ArffFile, dataset::Dataset, model::Model};
+    use crate::model::{gaussian_nb::GaussianNaiveBayes, mixed_nb::MixedNaiveBayes};
+
+    fn assert_same_posteriors<A: Model, B: Model>(a: &A, b: &B, samples: &[Vec<f64>]) {
+        for sample in samples.iter() {
+            let (expected, actual) = (a.posteriors(sample).unwrap(), b.posteriors(sample).unwrap());
+            for (e, a) in expected.iter().zip(actual.iter()) {
+                assert_relative_eq!(e, a, max_relative=1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gaussian_round_trip() {
+        let names: Vec<String> = vec![String::from("a & b"), String::from("<c>")];
+        let samples: Vec<Vec<f64>> = vec![
+            vec![1.0, 2.0], vec![2.0, 1.5], vec![1.5, 3.0],
+            vec![8.0, 9.0], vec![9.0, 7.0]
+        ];
+        let dataset: Dataset = Dataset::with_label_names(names.clone(), vec![0, 0, 0, 1, 1], samples.clone());
+
+        let mut model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        model.fit(&dataset).unwrap();
+
+        let xml: String = model.to_pmml().unwrap().to_xml();
+        assert!(xml.contains("<GaussianDistribution mean="));
+        assert!(xml.contains("<TargetValueCount value=\"a &amp; b\" count=\"3\"/>"));
+
+        let imported: GaussianNaiveBayes = GaussianNaiveBayes::from_pmml(&PmmlModel::parse(&xml).unwrap()).unwrap();
+        assert_eq!(imported.label_names(), names);
+        assert_same_posteriors(&model, &imported, &samples);
+        assert!(MixedNaiveBayes::from_pmml(&PmmlModel::parse(&xml).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_categorical_round_trip() {
+        let arff: ArffFile = ArffFile::parse("@relation r\n\
+            @attribute colour {red, green, blue}\n\
+            @attribute size numeric\n\
+            @attribute class {apple, lime}\n\
+            @data\n\
+            red,7,apple\nred,8,apple\ngreen,7.5,apple\n\
+            green,3,lime\nblue,2,lime\ngreen,4,lime\n").unwrap();
+        let dataset: Dataset = arff.to_dataset(None).unwrap();
+
+        let mut model: MixedNaiveBayes = MixedNaiveBayes::from_label_names(&[]);
+        model.fit(&dataset).unwrap();
+
+        let pmml: PmmlModel = PmmlModel::parse(&model.to_pmml().unwrap().to_xml()).unwrap();
+        // Counts carry the Laplace smoothing so other consumers agree with us
+        assert_eq!(pmml.inputs[0], BayesInput::Categorical {
+            values: vec![String::from("red"), String::from("green"), String::from("blue")],
+            counts: vec![vec![3.0, 2.0, 1.0], vec![1.0, 3.0, 2.0]]
+        });
+
+        let imported: MixedNaiveBayes = MixedNaiveBayes::from_pmml(&pmml).unwrap();
+        assert_eq!(imported.get_feature_types(), model.get_feature_types());
+        assert_same_posteriors(&model, &imported,
+            &[vec![0.0, 7.0], vec![2.0, 5.0], vec![1.0, f64::NAN]]);
+
+        assert!(GaussianNaiveBayes::from_pmml(&pmml).is_err());
+    }
+
+    #[test]
+    fn test_foreign_priors_and_threshold() {
+        let xml: &str = "<PMML xmlns=\"http://www.dmg.org/PMML-4_4\" version=\"4.4\">\
+            <DataDictionary>\
+              <DataField name=\"colour\" optype=\"categorical\"><Value value=\"red\"/><Value value=\"blue\"/></DataField>\
+              <DataField name=\"class\" optype=\"categorical\"><Value value=\"a\"/><Value value=\"b\"/></DataField>\
+            </DataDictionary>\
+            <NaiveBayesModel functionName=\"classification\" threshold=\"0.1\">\
+              <BayesInputs><BayesInput fieldName=\"colour\">\
+                <PairCounts value=\"red\"><TargetValueCounts>\
+                  <TargetValueCount value=\"a\" count=\"4\"/><TargetValueCount value=\"b\" count=\"0\"/>\
+                </TargetValueCounts></PairCounts>\
+                <PairCounts value=\"blue\"><TargetValueCounts>\
+                  <TargetValueCount value=\"a\" count=\"0\"/><TargetValueCount value=\"b\" count=\"2\"/>\
+                </TargetValueCounts></PairCounts>\
+              </BayesInput></BayesInputs>\
+              <BayesOutput fieldName=\"class\"><TargetValueCounts>\
+                <TargetValueCount value=\"a\" count=\"0.3\"/><TargetValueCount value=\"b\" count=\"0.7\"/>\
+              </TargetValueCounts></BayesOutput>\
+            </NaiveBayesModel></PMML>";
+        let pmml: PmmlModel = PmmlModel::parse(xml).unwrap();
+
+        // Probabilities are scaled to rows rather than rounded away
+        assert_eq!(pmml.class_sizes(), vec![300000, 700000]);
+
+        // Unseen values take the threshold probability
+        match &pmml.inputs[0] {
+            BayesInput::Categorical { counts, .. } => {
+                assert_abs_diff_eq!(counts[0][1] / counts[0].iter().sum::<f64>(), 0.1, epsilon=1e-12);
+                assert_abs_diff_eq!(counts[1][0] / counts[1].iter().sum::<f64>(), 0.1, epsilon=1e-12);
+            },
+            input => panic!("Expected a categorical input, not {:?}", input)
+        }
+        assert!(MixedNaiveBayes::from_pmml(&pmml).is_ok());
+    }
+}
@@ -0,0 +1,75 @@
+extern crate ndarray;
+
+use self::ndarray::{prelude::*, Array};
+use std::vec::Vec;
+
+/// The posterior distribution a model produced for a single labelled sample.
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    actual: usize,
+    posteriors: Vec<f64>
+}
+
+impl Prediction {
+    pub fn new(actual: usize, posteriors: Vec<f64>) -> Prediction {
+        Prediction {
+            actual,
+            posteriors
+        }
+    }
+
+    pub fn get_actual(&self) -> usize {
+        self.actual
+    }
+
+    pub fn get_posteriors(&self) -> &[f64] {
+        &self.posteriors
+    }
+
+    pub fn get_predicted(&self) -> usize {
+        argmax(&self.posteriors)
+    }
+
+    pub fn is_correct(&self) -> bool {
+        self.get_predicted() == self.actual
+    }
+}
+
+/// Index of the largest value, preferring the first index on ties.
+pub fn argmax(values: &[f64]) -> usize {
+    values.iter().enumerate().fold(0, |best, (idx, &value)| {
+        if value > values[best] { idx } else { best }
+    })
+}
+
+/// Converts per-class joint log10 likelihoods into normalized posteriors.
+pub fn normalize_log10(log_likelihoods: &[f64]) -> Vec<f64> {
+    let max: f64 = log_likelihoods.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+    // Every class underflowed, so there is nothing to prefer one over another
+    if !max.is_finite() {
+        let uniform: f64 = 1.0 / log_likelihoods.len() as f64;
+        return vec![uniform; log_likelihoods.len()];
+    }
+
+    // Shift by the maximum so the largest term is exactly 1.0 before scaling
+    let scaled: Vec<f64> = log_likelihoods.iter()
+        .map(|l| 10f64.powf(l - max))
+        .collect::<Vec<f64>>();
+    let total: f64 = scaled.iter().sum();
+
+    scaled.iter().map(|p| p / total).collect::<Vec<f64>>()
+}
+
+/// Builds a confusion matrix with rows = actual label, columns = predicted.
+pub fn confusion_matrix(predictions: &[Prediction], num_labels: usize)
+        -> Array<usize, Ix2> {
+    let mut matrix: Array<usize, Ix2> =
+        Array::<usize, Ix2>::zeros((num_labels, num_labels).f());
+
+    for prediction in predictions.iter() {
+        matrix[[prediction.get_actual(), prediction.get_predicted()]] += 1;
+    }
+
+    matrix
+}
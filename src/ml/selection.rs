@@ -0,0 +1,618 @@
+extern crate serde;
+
+use crate::ml::{dataset::{Dataset, FeatureType}, error::{DatasetError, ModelError, ModelFileError, PmmlError}};
+use crate::ml::{inspection::{self, ModelSummary}, label::Label, model::{self, Model}, pmml::PmmlModel};
+use crate::ml::{hyperparameters::Hyperparameters, prediction::Prediction};
+use crate::ml::{binary::{self, BinaryReader, BinaryWriter, Compression}, envelope::ModelMetadata};
+use crate::ml::sparse::{SparseModel, SparseDataset, SparseRow};
+use crate::model::ModelKind;
+use self::serde::{Serialize, Deserialize, de::DeserializeOwned};
+use std::{borrow::Cow, error::Error, fmt, str::FromStr, string::String, vec::Vec};
+
+/// Number of equal-width bins continuous features are split into to
+/// estimate their mutual information with the class.
+static MI_BINS: usize = 16;
+
+/// How each feature is scored against the class when selecting features.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionMethod {
+    /// Mutual information between the binned feature and the class, in nats.
+    MutualInformation,
+    /// The chi-squared statistic of the per-class sums of non-negative
+    /// feature values, such as counts, against those expected from the
+    /// class sizes. Numeric features only.
+    ChiSquared,
+    /// The one-way ANOVA F statistic of the class means. Numeric features
+    /// only.
+    AnovaF
+}
+
+impl FromStr for SelectionMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SelectionMethod, String> {
+        match s {
+            "mutual-information" => Ok(SelectionMethod::MutualInformation),
+            "chi-squared" => Ok(SelectionMethod::ChiSquared),
+            "anova-f" => Ok(SelectionMethod::AnovaF),
+            _ => Err(format!("Unknown feature selection method '{}'.", s))
+        }
+    }
+}
+
+impl fmt::Display for SelectionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SelectionMethod::MutualInformation => write!(f, "mutual-information"),
+            SelectionMethod::ChiSquared => write!(f, "chi-squared"),
+            SelectionMethod::AnovaF => write!(f, "anova-f")
+        }
+    }
+}
+
+/// Which scored features are kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionRule {
+    /// The given number of highest-scoring features, preferring earlier
+    /// features on ties.
+    Top(usize),
+    /// Every feature scoring at least the threshold.
+    Threshold(f64)
+}
+
+/// The features a model was trained on, kept with the model so samples
+/// with every feature can be classified as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeatureSelection {
+    pub method: SelectionMethod,
+    /// Indices of the kept features, in ascending order.
+    pub features: Vec<usize>,
+    /// The number of features samples have before selection.
+    pub num_features: usize
+}
+
+impl FeatureSelection {
+    /// Scores every feature of the training data and keeps those the rule
+    /// selects.
+    pub fn fit(dataset: &Dataset, method: SelectionMethod, rule: SelectionRule)
+            -> Result<FeatureSelection, DatasetError> {
+        Ok(FeatureSelection {
+            method,
+            features: select(&scores(dataset, method)?, rule),
+            num_features: dataset.num_features()
+        })
+    }
+
+    /// The sample's kept features.
+    pub fn project(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        if sample_features.len() != self.num_features {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        Ok(self.features.iter().map(|&f| sample_features[f]).collect::<Vec<f64>>())
+    }
+
+    /// The dataset's kept features.
+    pub fn project_dataset(&self, dataset: &Dataset) -> Result<Dataset, ModelError> {
+        if dataset.num_features() != self.num_features {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        Ok(dataset.select(&self.features))
+    }
+
+    /// The row's kept features, renumbered in selection order.
+    pub fn project_row(&self, row: &SparseRow) -> SparseRow {
+        SparseRow::new(row.iter()
+            .filter_map(|(idx, value)| self.features.binary_search(&idx).ok().map(|f| (f, value)))
+            .collect::<Vec<(usize, f64)>>())
+    }
+
+    /// Spreads per-class terms of the kept features back out to every
+    /// feature, with terms of 0 for the features that were dropped.
+    pub fn expand(&self, terms: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+        terms.into_iter().map(|class_terms| {
+            let mut expanded: Vec<f64> = vec![0.0; self.num_features];
+            for (&f, term) in self.features.iter().zip(class_terms) {
+                expanded[f] = term;
+            }
+            expanded
+        }).collect::<Vec<Vec<f64>>>()
+    }
+}
+
+/// Wraps a model trained on selected features. The wrapped model's fields
+/// are flattened so a model file without a selection loads with none, and
+/// the wrapped model is given only the selected features of every sample.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SelectedModel<M> {
+    #[serde(flatten)]
+    model: M,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    selection: Option<FeatureSelection>
+}
+
+impl<M: Model> SelectedModel<M> {
+    pub fn new(model: M) -> SelectedModel<M> {
+        SelectedModel {
+            model,
+            selection: None
+        }
+    }
+
+    pub fn get_model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn get_selection(&self) -> Option<&FeatureSelection> {
+        self.selection.as_ref()
+    }
+
+    /// Restricts the model to the selected features. Takes effect on the
+    /// next `fit`.
+    pub fn set_selection(&mut self, selection: FeatureSelection) {
+        self.selection = Some(selection);
+    }
+
+    /// The sample's features that the wrapped model was trained on.
+    fn project<'a>(&self, sample_features: &'a [f64]) -> Result<Cow<'a, [f64]>, ModelError> {
+        match self.selection.as_ref() {
+            Some(selection) => selection.project(sample_features).map(Cow::Owned),
+            None => Ok(Cow::Borrowed(sample_features))
+        }
+    }
+
+    fn project_dataset<'a>(&self, dataset: &'a Dataset) -> Result<Cow<'a, Dataset>, ModelError> {
+        match self.selection.as_ref() {
+            Some(selection) => selection.project_dataset(dataset).map(Cow::Owned),
+            None => Ok(Cow::Borrowed(dataset))
+        }
+    }
+}
+
+impl<M: Model + Serialize + DeserializeOwned> Model for SelectedModel<M> {
+    fn from_labels(file_path: &str) -> SelectedModel<M> {
+        SelectedModel::new(M::from_labels(file_path))
+    }
+
+    fn from_label_names(label_names: &[String]) -> SelectedModel<M> {
+        SelectedModel::new(M::from_label_names(label_names))
+    }
+
+    fn from_json(file_path: &str) -> SelectedModel<M> {
+        model::read_json(file_path, M::kind())
+    }
+
+    fn to_json(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        model::write_json(self, &ModelMetadata::new(M::kind(), *self.get_hyperparameters()), file_path)
+    }
+
+    fn kind() -> ModelKind {
+        M::kind()
+    }
+
+    fn from_binary(file_path: &str) -> SelectedModel<M> {
+        binary::read_binary(file_path, M::kind())
+    }
+
+    fn to_binary(&self, file_path: &str, compression: Compression) -> Result<(), Box<dyn Error>> {
+        let metadata: ModelMetadata = ModelMetadata::new(M::kind(), *self.get_hyperparameters());
+        binary::write_binary(self, &metadata, file_path, compression)
+    }
+
+    /// The wrapped model's fields, followed by the selection if there is one.
+    fn encode_binary(&self, writer: &mut BinaryWriter) {
+        self.model.encode_binary(writer);
+        writer.write_option(self.selection.as_ref(), |writer, selection| {
+            writer.write_u8(match selection.method {
+                SelectionMethod::MutualInformation => 0,
+                SelectionMethod::ChiSquared => 1,
+                SelectionMethod::AnovaF => 2
+            });
+            writer.write_usize(selection.features.len());
+            writer.write_sizes(&selection.features);
+            writer.write_usize(selection.num_features);
+        });
+    }
+
+    fn decode_binary(reader: &mut BinaryReader) -> Result<SelectedModel<M>, ModelFileError> {
+        let model: M = M::decode_binary(reader)?;
+        let selection: Option<FeatureSelection> = reader.read_option(|reader| {
+            let method: SelectionMethod = match reader.read_u8()? {
+                0 => SelectionMethod::MutualInformation,
+                1 => SelectionMethod::ChiSquared,
+                2 => SelectionMethod::AnovaF,
+                _ => return Err(ModelFileError::InvalidEncoding)
+            };
+            let len: usize = reader.read_count()?;
+            let features: Vec<usize> = reader.read_sizes(len)?;
+            let num_features: usize = reader.read_usize()?;
+            // Samples are indexed by the selected features
+            if features.windows(2).any(|pair| pair[0] >= pair[1])
+                    || features.last().is_some_and(|&f| f >= num_features) {
+                return Err(ModelFileError::InvalidEncoding);
+            }
+
+            Ok(FeatureSelection {
+                method,
+                features,
+                num_features
+            })
+        })?;
+
+        Ok(SelectedModel {
+            model,
+            selection
+        })
+    }
+
+    fn to_mapped(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        if self.selection.is_some() {
+            return Err(Box::new(ModelFileError::UnsupportedLayout));
+        }
+
+        self.model.to_mapped(file_path)
+    }
+
+    fn to_onnx(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        if self.selection.is_some() {
+            return Err(Box::new(ModelFileError::UnsupportedLayout));
+        }
+
+        self.model.to_onnx(file_path)
+    }
+
+    /// PMML has no way to drop features, so only models without a
+    /// selection can be exported.
+    fn to_pmml(&self) -> Result<PmmlModel, Box<dyn Error>> {
+        if self.selection.is_some() {
+            return Err(Box::new(PmmlError::UnsupportedModel));
+        }
+
+        self.model.to_pmml()
+    }
+
+    fn from_pmml(pmml: &PmmlModel) -> Result<SelectedModel<M>, Box<dyn Error>> {
+        Ok(SelectedModel::new(M::from_pmml(pmml)?))
+    }
+
+    fn num_labels(&self) -> usize {
+        self.model.num_labels()
+    }
+
+    fn label_names(&self) -> Vec<String> {
+        self.model.label_names()
+    }
+
+    /// The underlying model's summary, with selected features numbered as
+    /// they are in the samples.
+    fn summary(&self) -> Result<ModelSummary, ModelError> {
+        let mut summary: ModelSummary = self.model.summary()?;
+
+        if let Some(selection) = self.selection.as_ref() {
+            for feature in summary.features.iter_mut() {
+                feature.feature = selection.features[feature.feature];
+            }
+            summary.selected_features = Some(selection.features.len());
+            summary.num_features = selection.num_features;
+        }
+
+        Ok(summary)
+    }
+
+    fn get_hyperparameters(&self) -> &Hyperparameters {
+        self.model.get_hyperparameters()
+    }
+
+    fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters) {
+        self.model.set_hyperparameters(hyperparameters);
+    }
+
+    fn fit(&mut self, dataset: &Dataset) -> Result<(), Box<dyn Error>> {
+        let dataset: Cow<Dataset> = self.project_dataset(dataset)?;
+        self.model.fit(&dataset)
+    }
+
+    fn predict(&self, dataset: &Dataset, multithreaded: bool)
+            -> Result<Vec<Prediction>, ModelError> {
+        let dataset: Cow<Dataset> = self.project_dataset(dataset)?;
+        self.model.predict(&dataset, multithreaded)
+    }
+
+    fn classify(&self, sample_features: &[f64]) -> Result<Box<dyn Label>, ModelError> {
+        self.model.classify(&self.project(sample_features)?)
+    }
+
+    fn posteriors(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        self.model.posteriors(&self.project(sample_features)?)
+    }
+
+    /// The terms of the underlying model, with terms of 0 for the features
+    /// left out by selection.
+    fn log_likelihood_terms(&self, sample_features: &[f64])
+            -> Result<(Vec<f64>, Vec<Vec<f64>>), ModelError> {
+        let (log_priors, terms): (Vec<f64>, Vec<Vec<f64>>) =
+            self.model.log_likelihood_terms(&self.project(sample_features)?)?;
+
+        match self.selection.as_ref() {
+            Some(selection) => Ok((log_priors, selection.expand(terms))),
+            None => Ok((log_priors, terms))
+        }
+    }
+
+    fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError> {
+        self.model.label(index)
+    }
+}
+
+impl<M: SparseModel + Serialize + DeserializeOwned> SparseModel for SelectedModel<M> {
+    /// Selection needs every feature of every row, so sparse rows are
+    /// expanded when features were selected.
+    fn fit_sparse(&mut self, dataset: &SparseDataset) -> Result<(), Box<dyn Error>> {
+        if self.selection.is_some() {
+            return self.fit(&dataset.to_dense()?);
+        }
+
+        self.model.fit_sparse(dataset)
+    }
+
+    fn sparse_posteriors(&self, rows: &[SparseRow]) -> Result<Vec<Vec<f64>>, ModelError> {
+        match self.selection.as_ref() {
+            Some(selection) => self.model.sparse_posteriors(&rows.iter()
+                .map(|row| selection.project_row(row))
+                .collect::<Vec<SparseRow>>()),
+            None => self.model.sparse_posteriors(rows)
+        }
+    }
+}
+
+/// Each feature's score by the method, in feature order. Missing (NaN)
+/// values are left out. Sums and means of nominal features' category
+/// indices mean nothing, so only mutual information scores them.
+pub fn scores(dataset: &Dataset, method: SelectionMethod) -> Result<Vec<f64>, DatasetError> {
+    let nominal: bool = dataset.get_feature_types().iter()
+        .any(|feature_type| matches!(feature_type, FeatureType::Nominal(_)));
+    if nominal && method != SelectionMethod::MutualInformation {
+        return Err(DatasetError::NominalFeature);
+    }
+
+    match method {
+        SelectionMethod::MutualInformation => Ok(mutual_information(dataset)),
+        SelectionMethod::ChiSquared => chi_squared(dataset),
+        SelectionMethod::AnovaF => Ok(anova_f(dataset))
+    }
+}
+
+/// The values of one feature with the label of their rows, skipping
+/// missing values.
+fn column(dataset: &Dataset, feature: usize) -> Vec<(usize, f64)> {
+    dataset.iter()
+        .map(|(label, sample)| (label, sample[feature]))
+        .filter(|(_, value)| !value.is_nan())
+        .collect::<Vec<(usize, f64)>>()
+}
+
+/// Mutual information of each feature with the class, in nats. Values are
+/// split into `MI_BINS` equal-width bins over the feature's range, so
+/// binary and small categorical features keep one bin per value.
+pub fn mutual_information(dataset: &Dataset) -> Vec<f64> {
+    let num_labels: usize = dataset.num_labels();
+
+    (0..dataset.num_features()).map(|feature| {
+        let values: Vec<(usize, f64)> = column(dataset, feature);
+        let low: f64 = values.iter().map(|&(_, value)| value).fold(f64::INFINITY, f64::min);
+        let high: f64 = values.iter().map(|&(_, value)| value).fold(f64::NEG_INFINITY, f64::max);
+        if values.is_empty() || low == high {
+            return 0.0;
+        }
+
+        let mut joint: Vec<Vec<usize>> = vec![vec![0; num_labels]; MI_BINS];
+        for &(label, value) in values.iter() {
+            let bin: usize = (((value - low) / (high - low) * MI_BINS as f64) as usize).min(MI_BINS - 1);
+            joint[bin][label] += 1;
+        }
+
+        let total: f64 = values.len() as f64;
+        let class_counts: Vec<usize> = (0..num_labels)
+            .map(|label| joint.iter().map(|bin| bin[label]).sum::<usize>())
+            .collect::<Vec<usize>>();
+
+        joint.iter().map(|bin| {
+            let bin_count: usize = bin.iter().sum();
+            bin.iter().zip(class_counts.iter())
+                .filter(|(&count, _)| count > 0)
+                .map(|(&count, &class_count)| {
+                    let p: f64 = count as f64 / total;
+                    p * (p * total * total / (bin_count as f64 * class_count as f64)).ln()
+                })
+                .sum::<f64>()
+        }).sum::<f64>()
+    }).collect::<Vec<f64>>()
+}
+
+/// The chi-squared statistic of each feature's per-class sums against the
+/// sums expected if the feature's total were shared out by class size.
+/// Features must be non-negative.
+pub fn chi_squared(dataset: &Dataset) -> Result<Vec<f64>, DatasetError> {
+    let num_labels: usize = dataset.num_labels();
+    let mut class_sizes: Vec<usize> = vec![0; num_labels];
+    for &label in dataset.get_labels().iter() {
+        class_sizes[label] += 1;
+    }
+
+    (0..dataset.num_features()).map(|feature| {
+        let mut observed: Vec<f64> = vec![0.0; num_labels];
+        for (label, value) in column(dataset, feature) {
+            if value < 0.0 {
+                return Err(DatasetError::InvalidValue);
+            }
+            observed[label] += value;
+        }
+
+        let total: f64 = observed.iter().sum();
+        Ok(observed.iter().zip(class_sizes.iter())
+            .map(|(&observed, &size)| (observed, total * size as f64 / dataset.len() as f64))
+            .filter(|&(_, expected)| expected > 0.0)
+            .map(|(observed, expected)| (observed - expected).powi(2) / expected)
+            .sum::<f64>())
+    }).collect::<Result<Vec<f64>, DatasetError>>()
+}
+
+/// The one-way ANOVA F statistic of each feature's class means.
+pub fn anova_f(dataset: &Dataset) -> Vec<f64> {
+    let num_labels: usize = dataset.num_labels();
+
+    (0..dataset.num_features()).map(|feature| {
+        let values: Vec<(usize, f64)> = column(dataset, feature);
+        let mut class_sizes: Vec<usize> = vec![0; num_labels];
+        let mut means: Vec<f64> = vec![0.0; num_labels];
+        for &(label, value) in values.iter() {
+            class_sizes[label] += 1;
+            means[label] += value;
+        }
+        for (mean, &size) in means.iter_mut().zip(class_sizes.iter()) {
+            if size > 0 {
+                *mean /= size as f64;
+            }
+        }
+
+        let mut square_mean_diffs: Vec<f64> = vec![0.0; num_labels];
+        for &(label, value) in values.iter() {
+            square_mean_diffs[label] += (value - means[label]).powi(2);
+        }
+
+        inspection::f_statistic(&class_sizes, &means, &square_mean_diffs)
+    }).collect::<Vec<f64>>()
+}
+
+/// The indices of the features the rule keeps, in ascending order.
+pub fn select(scores: &[f64], rule: SelectionRule) -> Vec<usize> {
+    let mut features: Vec<usize> = match rule {
+        SelectionRule::Top(k) => {
+            let mut ranked: Vec<usize> = (0..scores.len()).collect::<Vec<usize>>();
+            // A stable sort keeps earlier features first on ties
+            ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+            ranked.truncate(k);
+            ranked
+        },
+        SelectionRule::Threshold(threshold) => (0..scores.len())
+            .filter(|&f| scores[f] >= threshold)
+            .collect::<Vec<usize>>()
+    };
+    features.sort_unstable();
+
+    features
+}
+
+#[cfg(test)]
+mod selection_tests {
+    use crate::ml::selection::*;
+
+    fn dataset() -> Dataset {
+        let names: Vec<String> = vec![String::from("a"), String::from("b")];
+        // Feature 0 separates the classes, feature 1 is noise and feature 2
+        // is constant
+        let samples: Vec<Vec<f64>> = vec![
+            vec![0.0, 1.0, 2.0], vec![1.0, 3.0, 2.0], vec![0.0, 2.0, 2.0],
+            vec![5.0, 2.0, 2.0], vec![6.0, 1.0, 2.0], vec![5.0, 3.0, 2.0]
+        ];
+        Dataset::with_label_names(names, vec![0, 0, 0, 1, 1, 1], samples)
+    }
+
+    #[test]
+    fn test_scores() {
+        let dataset: Dataset = dataset();
+
+        let information: Vec<f64> = mutual_information(&dataset);
+        assert_abs_diff_eq!(information[0], 2f64.ln(), epsilon=1e-12);
+        assert_abs_diff_eq!(information[1], 0.0, epsilon=1e-12);
+        assert_eq!(information[2], 0.0);
+
+        // Feature 0 sums to 1 and 16 against 8.5 each
+        let chi: Vec<f64> = chi_squared(&dataset).unwrap();
+        assert_abs_diff_eq!(chi[0], 2.0 * 7.5f64.powi(2) / 8.5, epsilon=1e-12);
+        assert_abs_diff_eq!(chi[1], 0.0, epsilon=1e-12);
+        assert_abs_diff_eq!(chi[2], 0.0, epsilon=1e-12);
+
+        let f: Vec<f64> = anova_f(&dataset);
+        assert!(f[0] > 10.0);
+        assert_eq!(f[1], 0.0);
+        assert_eq!(f[2], 0.0);
+
+        let negative: Dataset = Dataset::new(vec![0, 1], vec![vec![-1.0], vec![1.0]]);
+        assert!(matches!(chi_squared(&negative), Err(DatasetError::InvalidValue)));
+
+        let mut nominal: Dataset = dataset.clone();
+        nominal.set_feature_types(vec![FeatureType::Numeric, FeatureType::Numeric,
+            FeatureType::Nominal(vec![String::from("x"), String::from("y"), String::from("z")])]);
+        assert_eq!(scores(&nominal, SelectionMethod::AnovaF), Err(DatasetError::NominalFeature));
+        assert_eq!(scores(&nominal, SelectionMethod::ChiSquared), Err(DatasetError::NominalFeature));
+        assert_eq!(scores(&nominal, SelectionMethod::MutualInformation).unwrap(), information);
+    }
+
+    #[test]
+    fn test_select() {
+        let scores: [f64; 4] = [0.5, 2.0, 0.5, 1.0];
+        assert_eq!(select(&scores, SelectionRule::Top(2)), vec![1, 3]);
+        assert_eq!(select(&scores, SelectionRule::Top(3)), vec![0, 1, 3]);
+        assert_eq!(select(&scores, SelectionRule::Top(9)), vec![0, 1, 2, 3]);
+        assert_eq!(select(&scores, SelectionRule::Threshold(1.0)), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_projection() {
+        let selection: FeatureSelection =
+            FeatureSelection::fit(&dataset(), SelectionMethod::AnovaF, SelectionRule::Top(1)).unwrap();
+        assert_eq!(selection.features, vec![0]);
+
+        let selection: FeatureSelection = FeatureSelection {
+            method: SelectionMethod::AnovaF,
+            features: vec![1, 3],
+            num_features: 4
+        };
+        assert_eq!(selection.project(&[1.0, 2.0, 3.0, 4.0]).unwrap(), vec![2.0, 4.0]);
+        assert!(selection.project(&[1.0, 2.0]).is_err());
+        assert_eq!(selection.project_row(&SparseRow::new(vec![(0, 5.0), (3, 7.0)])),
+            SparseRow::new(vec![(1, 7.0)]));
+        assert_eq!(selection.expand(vec![vec![0.5, 1.5]]), vec![vec![0.0, 0.5, 0.0, 1.5]]);
+    }
+
+    #[test]
+    fn test_selected_features() {
+        use crate::ml::prediction::Prediction;
+        use crate::model::gaussian_nb::GaussianNaiveBayes;
+
+        // Only feature 1 tells the classes apart
+        let dataset: Dataset = Dataset::with_label_names(vec![String::from("a"), String::from("b")],
+            vec![0, 0, 0, 1, 1, 1], vec![
+                vec![3.0, 0.0, 1.0], vec![4.0, 1.0, 2.0], vec![5.0, 0.5, 1.0],
+                vec![4.0, 9.0, 2.0], vec![5.0, 8.0, 1.0], vec![3.0, 9.5, 2.0]
+            ]);
+
+        let mut model: SelectedModel<GaussianNaiveBayes> = SelectedModel::from_label_names(&[]);
+        model.set_selection(FeatureSelection::fit(&dataset, SelectionMethod::AnovaF, SelectionRule::Top(1)).unwrap());
+        model.fit(&dataset).unwrap();
+        assert_eq!(model.get_model().summary().unwrap().num_features, 1);
+
+        // Samples keep every feature
+        assert_eq!(model.classify(&[100.0, 8.5, -7.0]).unwrap().get_name(), "b");
+        assert!(model.classify(&[8.5]).is_err());
+        let predictions: Vec<Prediction> = model.predict(&dataset, false).unwrap();
+        assert!(predictions.iter().all(|p| p.is_correct()));
+
+        let (_, terms): (Vec<f64>, Vec<Vec<f64>>) = model.log_likelihood_terms(&[4.0, 0.5, 1.0]).unwrap();
+        assert_eq!(terms[0].len(), 3);
+        assert!(terms.iter().all(|class_terms| class_terms[0] == 0.0 && class_terms[2] == 0.0));
+
+        let summary: ModelSummary = model.summary().unwrap();
+        assert_eq!((summary.num_features, summary.selected_features), (3, Some(1)));
+        assert_eq!(summary.features[0].feature, 1);
+
+        // The selection is saved with the model
+        let json: String = serde_json::to_string(&model).unwrap();
+        let loaded: SelectedModel<GaussianNaiveBayes> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get_selection(), model.get_selection());
+        assert!(model.to_pmml().is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::ml::{model::Model, error::{DatasetError, ModelError}, dataset::Dataset, label};
+use crate::ml::prediction::Prediction;
+use std::{error::Error, fs, string::String, vec::Vec};
+
+/// A sample stored as its non-zero entries. Every feature not listed is 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparseRow {
+    indices: Vec<usize>,
+    values: Vec<f64>
+}
+
+impl SparseRow {
+    /// Builds a row from `(feature index, value)` pairs in any order.
+    pub fn new(mut entries: Vec<(usize, f64)>) -> SparseRow {
+        entries.sort_by_key(|&(idx, _)| idx);
+
+        SparseRow {
+            indices: entries.iter().map(|&(idx, _)| idx).collect::<Vec<usize>>(),
+            values: entries.iter().map(|&(_, value)| value).collect::<Vec<f64>>()
+        }
+    }
+
+    pub fn from_dense(sample: &[f64]) -> SparseRow {
+        SparseRow::new(sample.iter().enumerate()
+            .filter(|&(_, &value)| value != 0.0)
+            .map(|(idx, &value)| (idx, value))
+            .collect::<Vec<(usize, f64)>>())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.indices.iter().copied().zip(self.values.iter().copied())
+    }
+
+    /// One more than the largest feature index, or 0 for an empty row.
+    pub fn width(&self) -> usize {
+        self.indices.last().map_or(0, |&idx| idx + 1)
+    }
+
+    pub fn to_dense(&self, num_features: usize) -> Vec<f64> {
+        let mut sample: Vec<f64> = vec![0.0; num_features];
+        for (idx, value) in self.iter() {
+            sample[idx] = value;
+        }
+        sample
+    }
+}
+
+/// Labelled sparse rows, with the same class naming as `Dataset`.
+#[derive(Debug, Clone, Default)]
+pub struct SparseDataset {
+    label_names: Vec<String>,
+    labels: Vec<usize>,
+    rows: Vec<SparseRow>,
+    num_features: usize
+}
+
+impl SparseDataset {
+    /// Reads LIBSVM / svmlight lines of the form `label index:value ...`.
+    /// Indices are 1-based unless `zero_based` is set. `qid` tokens and `#`
+    /// comments are ignored, and a line whose first token is an entry has
+    /// no label.
+    pub fn from_libsvm(file_path: &str, zero_based: bool) -> Result<SparseDataset, Box<dyn Error>> {
+        let contents: String = fs::read_to_string(file_path)?;
+
+        let mut names: Vec<String> = Vec::new();
+        let mut labels: Vec<usize> = Vec::new();
+        let mut rows: Vec<Vec<(usize, f64)>> = Vec::new();
+
+        for line in contents.lines() {
+            let line: &str = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace().peekable();
+            if let Some(first) = tokens.peek() {
+                if !first.contains(':') {
+                    let name: &str = tokens.next().unwrap();
+                    let label: usize = match names.iter().position(|n| n == name) {
+                        Some(label) => label,
+                        None => {
+                            names.push(name.to_string());
+                            names.len() - 1
+                        }
+                    };
+                    labels.push(label);
+                }
+            }
+
+            let mut entries: Vec<(usize, f64)> = Vec::new();
+            for token in tokens {
+                let (key, value) = token.split_once(':').ok_or(DatasetError::InvalidValue)?;
+                if key == "qid" {
+                    continue;
+                }
+
+                let idx: usize = key.parse::<usize>().map_err(|_| DatasetError::InvalidValue)?;
+                let value: f64 = value.parse::<f64>().map_err(|_| DatasetError::InvalidValue)?;
+                let idx: usize = if zero_based { idx } else { idx.checked_sub(1).ok_or(DatasetError::InvalidIndex)? };
+                entries.push((idx, value));
+            }
+            rows.push(entries);
+        }
+
+        if !labels.is_empty() && labels.len() != rows.len() {
+            return Err(Box::new(DatasetError::MissingLabel));
+        }
+
+        let rows: Vec<SparseRow> = rows.into_iter()
+            .map(SparseRow::new)
+            .collect::<Vec<SparseRow>>();
+
+        let mut dataset: SparseDataset = SparseDataset {
+            num_features: rows.iter().map(|row| row.width()).max().unwrap_or(0),
+            label_names: names,
+            labels,
+            rows
+        };
+        dataset.sort_label_names();
+
+        Ok(dataset)
+    }
+
+    /// Counts `num_features` features in every row, so rows expand to that
+    /// width rather than to the largest index in the data. Fails if a row
+    /// has an index beyond it.
+    pub fn widen(&mut self, num_features: usize) -> Result<(), DatasetError> {
+        if self.num_features > num_features {
+            return Err(DatasetError::InvalidIndex);
+        }
+
+        self.num_features = num_features;
+        Ok(())
+    }
+
+    /// Keeps only the non-zero values of each sample.
+    pub fn from_dense(dataset: &Dataset) -> SparseDataset {
+        SparseDataset {
+            label_names: dataset.get_label_names().to_vec(),
+            labels: dataset.get_labels().to_vec(),
+            rows: dataset.iter()
+                .map(|(_, sample)| SparseRow::from_dense(sample))
+                .collect::<Vec<SparseRow>>(),
+            num_features: dataset.num_features()
+        }
+    }
+
+    fn sort_label_names(&mut self) {
+        let mut sorted: Vec<String> = self.label_names.clone();
+        label::sort_names(&mut sorted);
+
+        let mapping: Vec<usize> = label::mapping(&self.label_names, &sorted)
+            .expect("sorted names hold the same classes");
+        for label in self.labels.iter_mut() {
+            *label = mapping[*label];
+        }
+        self.label_names = sorted;
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+
+    pub fn get_label_names(&self) -> &[String] {
+        &self.label_names
+    }
+
+    pub fn get_labels(&self) -> &[usize] {
+        &self.labels
+    }
+
+    pub fn get_rows(&self) -> &[SparseRow] {
+        &self.rows
+    }
+
+    pub fn is_labelled(&self) -> bool {
+        !self.rows.is_empty() && self.labels.len() == self.rows.len()
+    }
+
+    pub fn label_mapping(&self, names: &[String]) -> Result<Vec<usize>, DatasetError> {
+        label::mapping(&self.label_names, names)
+    }
+
+    /// Expands every row, for code that only works on dense samples.
+    pub fn to_dense(&self) -> Result<Dataset, DatasetError> {
+        let samples: Vec<Vec<f64>> = self.rows.iter()
+            .map(|row| row.to_dense(self.num_features))
+            .collect::<Vec<Vec<f64>>>();
+
+        if !self.is_labelled() {
+            return Err(DatasetError::MissingLabel);
+        }
+
+        Ok(Dataset::with_label_names(self.label_names.clone(), self.labels.clone(), samples))
+    }
+}
+
+/// Models that can train on and score sparse rows without expanding them.
+pub trait SparseModel: Model {
+    fn fit_sparse(&mut self, dataset: &SparseDataset) -> Result<(), Box<dyn Error>>;
+
+    /// Posteriors of each row, in class index order.
+    fn sparse_posteriors(&self, rows: &[SparseRow]) -> Result<Vec<Vec<f64>>, ModelError>;
+
+    fn predict_sparse(&self, dataset: &SparseDataset) -> Result<Vec<Prediction>, ModelError> {
+        let mapping: Vec<usize> = dataset.label_mapping(&self.label_names())
+            .map_err(|_| ModelError::LabelNotFound)?;
+        let posteriors: Vec<Vec<f64>> = self.sparse_posteriors(dataset.get_rows())?;
+
+        Ok(dataset.get_labels().iter().zip(posteriors)
+            .map(|(&label, posteriors)| Prediction::new(mapping[label], posteriors))
+            .collect::<Vec<Prediction>>())
+    }
+}
+
+/// For each class, indexed by `mapping`, the number of its rows that store
+/// an entry for each feature. The remaining rows hold an implicit 0.
+pub(crate) fn nonzero_counts(dataset: &SparseDataset, mapping: &[usize], num_labels: usize)
+        -> Vec<Vec<usize>> {
+    let mut counts: Vec<Vec<usize>> = vec![vec![0; dataset.num_features()]; num_labels];
+
+    for (&label, row) in dataset.get_labels().iter().zip(dataset.get_rows()) {
+        for (idx, _) in row.iter() {
+            counts[mapping[label]][idx] += 1;
+        }
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod sparse_tests {
+    use crate::ml::sparse::*;
+    use crate::ml::temp_path;
+
+    #[test]
+    fn test_from_libsvm() {
+        let path = temp_path("sparse_test.svm");
+        fs::write(&path, "spam 1:2 3:0.5 # comment\nham qid:4 2:1\n\nspam 3:1\n").unwrap();
+
+        let dataset: SparseDataset = SparseDataset::from_libsvm(path.to_str().unwrap(), false).unwrap();
+        assert_eq!(dataset.get_label_names(), &["ham", "spam"]);
+        assert_eq!(dataset.get_labels(), &[1, 0, 1]);
+        assert_eq!(dataset.num_features(), 3);
+        assert_eq!(dataset.get_rows()[0], SparseRow::new(vec![(2, 0.5), (0, 2.0)]));
+
+        let dense: Dataset = dataset.to_dense().unwrap();
+        assert_eq!(dense.get_sample(1), &[0.0, 1.0, 0.0]);
+
+        // A model wider than the file's largest index still gets full rows
+        let mut wide: SparseDataset = dataset.clone();
+        wide.widen(5).unwrap();
+        assert_eq!(wide.to_dense().unwrap().get_sample(1), &[0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(wide.clone().widen(2), Err(DatasetError::InvalidIndex));
+
+        fs::write(&path, "0:1 4:2\n").unwrap();
+        let unlabelled: SparseDataset = SparseDataset::from_libsvm(path.to_str().unwrap(), true).unwrap();
+        assert!(!unlabelled.is_labelled());
+        assert_eq!(unlabelled.num_features(), 5);
+
+        // Index 0 has no place in 1-based data
+        assert!(SparseDataset::from_libsvm(path.to_str().unwrap(), false).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+extern crate rand;
+
+use crate::ml::{model::Model, dataset::Dataset};
+use crate::ml::hyperparameters::{Hyperparameters, PriorMode};
+use crate::ml::validation::{self, CrossValidationReport, Fold};
+
+use self::rand::{rngs::StdRng, seq::SliceRandom};
+use std::{error::Error, vec::Vec};
+use std::cmp::Ordering;
+
+/// Candidate values for each hyperparameter.
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub min_std: Vec<f64>,
+    pub var_smoothing: Vec<f64>,
+    pub alpha: Vec<f64>,
+    pub smoothing: Vec<f64>,
+    pub binarize: Vec<Option<f64>>,
+    pub prior: Vec<PriorMode>
+}
+
+impl Default for SearchSpace {
+    /// A space containing only the default configuration.
+    fn default() -> SearchSpace {
+        let defaults: Hyperparameters = Hyperparameters::default();
+
+        SearchSpace {
+            min_std: vec![defaults.min_std],
+            var_smoothing: vec![defaults.var_smoothing],
+            alpha: vec![defaults.alpha],
+            smoothing: vec![defaults.smoothing],
+            binarize: vec![defaults.binarize],
+            prior: vec![defaults.prior]
+        }
+    }
+}
+
+impl SearchSpace {
+    /// Every combination of the candidate values.
+    pub fn grid(&self) -> Vec<Hyperparameters> {
+        let mut configurations: Vec<Hyperparameters> = Vec::new();
+
+        for &min_std in self.min_std.iter() {
+            for &var_smoothing in self.var_smoothing.iter() {
+                for &alpha in self.alpha.iter() {
+                    for &smoothing in self.smoothing.iter() {
+                        for &binarize in self.binarize.iter() {
+                            for &prior in self.prior.iter() {
+                                configurations.push(Hyperparameters {
+                                    min_std,
+                                    var_smoothing,
+                                    alpha,
+                                    smoothing,
+                                    binarize,
+                                    prior
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        configurations
+    }
+
+    /// Up to `count` distinct combinations drawn uniformly from the grid.
+    pub fn sample(&self, count: usize, seed: Option<u64>) -> Vec<Hyperparameters> {
+        let mut rng: StdRng = validation::make_rng(seed);
+
+        let mut configurations: Vec<Hyperparameters> = self.grid();
+        configurations.shuffle(&mut rng);
+        configurations.truncate(count);
+        configurations
+    }
+}
+
+/// Cross-validated accuracy of one configuration.
+#[derive(Debug, Clone)]
+pub struct TrialResult {
+    pub hyperparameters: Hyperparameters,
+    pub mean_accuracy: f64,
+    pub std_accuracy: f64
+}
+
+/// Cross-validates every candidate on the same folds, returning the
+/// results ordered from best to worst mean accuracy.
+pub fn search<M: Model, F: Fn(&Hyperparameters, &Dataset) -> Result<M, Box<dyn Error>>>
+        (dataset: &Dataset, folds: &[Fold], candidates: &[Hyperparameters], make_model: F)
+        -> Result<Vec<TrialResult>, Box<dyn Error>> {
+    let mut results: Vec<TrialResult> = Vec::new();
+
+    for (idx, hyperparameters) in candidates.iter().enumerate() {
+        eprintln!("Trial {} of {}: {:?}", idx + 1, candidates.len(), hyperparameters);
+
+        let report: CrossValidationReport =
+            validation::cross_validate(dataset, folds, |train| make_model(hyperparameters, train))?;
+        let (mean_accuracy, std_accuracy): (f64, f64) = report.accuracy();
+
+        results.push(TrialResult {
+            hyperparameters: *hyperparameters,
+            mean_accuracy,
+            std_accuracy
+        });
+    }
+
+    // Prefer the more stable configuration when accuracies tie
+    results.sort_by(|a, b| b.mean_accuracy.partial_cmp(&a.mean_accuracy)
+        .unwrap_or(Ordering::Equal)
+        .then(a.std_accuracy.partial_cmp(&b.std_accuracy).unwrap_or(Ordering::Equal)));
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tuning_tests {
+    use crate::ml::tuning::*;
+
+    #[test]
+    fn test_grid_and_sample_sizes() {
+        let space: SearchSpace = SearchSpace {
+            min_std: vec![1e-10, 1e-3],
+            var_smoothing: vec![1e-9],
+            alpha: vec![0.0, 1.0],
+            smoothing: vec![1.0],
+            binarize: vec![None, Some(127.0)],
+            prior: vec![PriorMode::Empirical, PriorMode::Uniform]
+        };
+
+        assert_eq!(space.grid().len(), 16);
+        assert_eq!(space.sample(5, Some(2)).len(), 5);
+        assert_eq!(space.sample(5, Some(2)), space.sample(5, Some(2)));
+        assert_eq!(space.sample(100, None).len(), 16);
+    }
+}
@@ -0,0 +1,217 @@
+extern crate ndarray;
+extern crate rand;
+
+use crate::ml::{model::Model, dataset::Dataset};
+use crate::ml::metrics::{self, ClassMetrics};
+use crate::ml::prediction::{self, Prediction};
+
+use self::ndarray::{prelude::*, Array};
+use self::rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::{error::Error, vec::Vec};
+
+/// Row indices used to train and evaluate a single fold.
+#[derive(Debug, Clone)]
+pub struct Fold {
+    pub train: Vec<usize>,
+    pub test: Vec<usize>
+}
+
+/// A generator from the seed, or from entropy when none is given.
+pub(crate) fn make_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy()
+    }
+}
+
+/// Turns per-fold test index lists into train/test folds.
+fn folds_from_assignment(assignment: Vec<Vec<usize>>) -> Vec<Fold> {
+    (0..assignment.len()).map(|fold| {
+        let mut train: Vec<usize> = assignment.iter().enumerate()
+            .filter(|(idx, _)| *idx != fold)
+            .flat_map(|(_, indices)| indices.iter().cloned())
+            .collect::<Vec<usize>>();
+        train.sort_unstable();
+
+        let mut test: Vec<usize> = assignment[fold].clone();
+        test.sort_unstable();
+
+        Fold { train, test }
+    }).collect::<Vec<Fold>>()
+}
+
+/// Splits `num_samples` shuffled rows into `k` folds of near-equal size.
+pub fn k_fold(num_samples: usize, k: usize, seed: Option<u64>) -> Vec<Fold> {
+    let mut indices: Vec<usize> = (0..num_samples).collect::<Vec<usize>>();
+    indices.shuffle(&mut make_rng(seed));
+
+    let mut assignment: Vec<Vec<usize>> = vec![Vec::new(); k];
+    for (position, idx) in indices.into_iter().enumerate() {
+        assignment[position % k].push(idx);
+    }
+
+    folds_from_assignment(assignment)
+}
+
+/// Like `k_fold`, but deals the rows of each class round-robin across the
+/// folds so every fold keeps roughly the overall class proportions.
+pub fn stratified_k_fold(labels: &[usize], k: usize, seed: Option<u64>) -> Vec<Fold> {
+    let mut rng: StdRng = make_rng(seed);
+    let num_classes: usize = labels.iter().max().map_or(0, |&max| max + 1);
+
+    let mut by_class: Vec<Vec<usize>> = vec![Vec::new(); num_classes];
+    for (idx, &label) in labels.iter().enumerate() {
+        by_class[label].push(idx);
+    }
+
+    // Continue dealing where the previous class stopped so small classes
+    // do not all land in the first fold
+    let mut assignment: Vec<Vec<usize>> = vec![Vec::new(); k];
+    let mut next_fold: usize = 0;
+
+    for mut indices in by_class.into_iter() {
+        indices.shuffle(&mut rng);
+
+        for idx in indices.into_iter() {
+            assignment[next_fold].push(idx);
+            next_fold = (next_fold + 1) % k;
+        }
+    }
+
+    folds_from_assignment(assignment)
+}
+
+/// Results of evaluating the model trained on one fold.
+#[derive(Debug, Clone)]
+pub struct FoldResult {
+    pub accuracy: f64,
+    pub class_metrics: Vec<ClassMetrics>,
+    pub confusion_matrix: Array<usize, Ix2>
+}
+
+#[derive(Debug, Clone)]
+pub struct CrossValidationReport {
+    pub folds: Vec<FoldResult>,
+    /// Sum of the per-fold confusion matrices.
+    pub confusion_matrix: Array<usize, Ix2>
+}
+
+impl CrossValidationReport {
+    /// Mean and standard deviation of accuracy across folds.
+    pub fn accuracy(&self) -> (f64, f64) {
+        let accuracies: Vec<f64> = self.folds.iter()
+            .map(|f| f.accuracy)
+            .collect::<Vec<f64>>();
+
+        metrics::mean_std(&accuracies)
+    }
+
+    /// Mean and standard deviation of each class metric across folds,
+    /// returned as (precision, recall, f1) pairs per class.
+    pub fn class_summary(&self) -> Vec<[(f64, f64); 3]> {
+        let num_classes: usize = self.confusion_matrix.nrows();
+
+        (0..num_classes).map(|class| {
+            let collect = |select: fn(&ClassMetrics) -> f64| -> (f64, f64) {
+                let values: Vec<f64> = self.folds.iter()
+                    .map(|f| select(&f.class_metrics[class]))
+                    .collect::<Vec<f64>>();
+                metrics::mean_std(&values)
+            };
+
+            [collect(|m| m.precision), collect(|m| m.recall), collect(|m| m.f1)]
+        }).collect::<Vec<[(f64, f64); 3]>>()
+    }
+}
+
+/// Trains a fresh model from `make_model` on each fold's training rows and
+/// evaluates it on the held-out rows. `make_model` is given the training
+/// rows, so anything it fits to the data, such as a feature selection,
+/// never sees the held-out rows.
+pub fn cross_validate<M: Model, F: Fn(&Dataset) -> Result<M, Box<dyn Error>>>
+        (dataset: &Dataset, folds: &[Fold], make_model: F)
+        -> Result<CrossValidationReport, Box<dyn Error>> {
+    let mut results: Vec<FoldResult> = Vec::new();
+    let mut total: Option<Array<usize, Ix2>> = None;
+
+    for (fold_idx, fold) in folds.iter().enumerate() {
+        eprintln!("Fold {} of {}", fold_idx + 1, folds.len());
+
+        let train: Dataset = dataset.subset(&fold.train);
+        let mut model: M = make_model(&train)?;
+        model.fit(&train)?;
+
+        let predictions: Vec<Prediction> =
+            model.predict(&dataset.subset(&fold.test), true)?;
+        let confusion_matrix: Array<usize, Ix2> =
+            prediction::confusion_matrix(&predictions, model.num_labels());
+
+        total = Some(match total {
+            Some(sum) => sum + &confusion_matrix,
+            None => confusion_matrix.clone()
+        });
+
+        results.push(FoldResult {
+            accuracy: M::calculate_accuracy(&confusion_matrix),
+            class_metrics: metrics::class_metrics(&confusion_matrix),
+            confusion_matrix
+        });
+    }
+
+    Ok(CrossValidationReport {
+        folds: results,
+        confusion_matrix: total.unwrap_or_else(|| Array::<usize, Ix2>::zeros((0, 0)))
+    })
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use crate::ml::validation::*;
+
+    #[test]
+    fn test_k_fold_partitions_rows() {
+        let folds: Vec<Fold> = k_fold(10, 3, Some(7));
+        assert_eq!(folds.len(), 3);
+
+        let mut seen: Vec<usize> = folds.iter()
+            .flat_map(|f| f.test.iter().cloned())
+            .collect::<Vec<usize>>();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..10).collect::<Vec<usize>>());
+
+        for fold in folds.iter() {
+            assert_eq!(fold.train.len() + fold.test.len(), 10);
+            assert!(fold.test.iter().all(|idx| !fold.train.contains(idx)));
+        }
+
+        assert_eq!(k_fold(10, 3, Some(7))[0].test, folds[0].test);
+    }
+
+    #[test]
+    fn test_models_see_only_training_rows() {
+        use crate::model::gaussian_nb::GaussianNaiveBayes;
+
+        let dataset: Dataset = Dataset::with_label_names(vec![String::from("a"), String::from("b")],
+            vec![0, 0, 0, 1, 1, 1], vec![vec![0.0], vec![1.0], vec![0.5], vec![9.0], vec![8.0], vec![9.5]]);
+        let folds: Vec<Fold> = stratified_k_fold(dataset.get_labels(), 3, Some(3));
+
+        let report: CrossValidationReport = cross_validate(&dataset, &folds, |train| {
+            assert_eq!(train.len(), 4);
+            Ok(GaussianNaiveBayes::from_label_names(dataset.get_label_names()))
+        }).unwrap();
+        assert_eq!(report.accuracy(), (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_stratified_k_fold_keeps_proportions() {
+        let labels: Vec<usize> = vec![0, 0, 0, 0, 0, 0, 1, 1, 1, 2, 2, 2];
+        let folds: Vec<Fold> = stratified_k_fold(&labels, 3, Some(1));
+
+        for fold in folds.iter() {
+            let count = |class: usize| fold.test.iter().filter(|&&i| labels[i] == class).count();
+            assert_eq!(count(0), 2);
+            assert_eq!(count(1), 1);
+            assert_eq!(count(2), 1);
+        }
+    }
+}
@@ -1,317 +1,633 @@
-extern crate num_traits;
-extern crate ndarray;
 extern crate serde;
-extern crate csv;
 
-use std::sync::{Arc, mpsc, mpsc::{Sender, Receiver}};
+use std::sync::{mpsc, mpsc::Receiver};
 use std::{error::Error, vec::Vec, string::String};
-use std::{io::{BufReader, BufRead}, fs, thread};
+use std::thread;
 
-use crate::ml::{model::Model, feature::Feature, label::Label, error::ModelError};
+use crate::ml::{model::{self, Model}, feature::Feature, error::{DatasetError, ModelError, ModelFileError}};
+use crate::ml::sparse::{self, SparseDataset, SparseModel, SparseRow};
+use crate::ml::label::Label;
+use crate::ml::{dataset::Dataset, hyperparameters::Hyperparameters};
+use crate::ml::prediction::{self, Prediction};
 use crate::naivebayes::gaussian_feature::GaussianFeature;
+use crate::naivebayes::gaussian_classification::{self, GaussianClassification};
+use crate::ml::{binary::{self, BinaryReader, BinaryWriter, Compression}, envelope::ModelMetadata};
+use crate::ml::{mapped, onnx};
+use crate::ml::{error::PmmlError, pmml::{self, BayesInput, PmmlModel}};
+use crate::ml::inspection::{ModelSummary, ScoreMeasure};
+use crate::model::ModelKind;
 use crate::naivebayes::class_label::ClassLabel;
 
 use self::serde::{Serialize, Deserialize};
-use self::ndarray::{prelude::*, Array};
-use self::num_traits::ToPrimitive;
-use core::str::FromStr;
-
-type Matrix<T> = Array<T, Ix2>;
 
 static PRINT_INTERVAL: usize = 5000;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The trained parameters of a Gaussian model, for exporters that score
+/// samples without the model types.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaussianParameters {
+    pub labels: Vec<String>,
+    /// Log10 prior of each class.
+    pub log_priors: Vec<f64>,
+    /// Per-class feature means, indexed `[class][feature]`.
+    pub means: Vec<Vec<f64>>,
+    /// Per-class feature standard deviations, indexed `[class][feature]`.
+    pub stds: Vec<Vec<f64>>,
+    /// Threshold that features are binarized around before scoring.
+    pub binarize: Option<f64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GaussianNaiveBayes {
     labels: Vec<ClassLabel>,
-    features: Vec<GaussianFeature>
+    features: Vec<GaussianFeature>,
+    #[serde(default)]
+    class_sizes: Vec<usize>,
+    #[serde(default)]
+    hyperparameters: Hyperparameters
 }
 
 impl GaussianNaiveBayes {
-    fn parse_csv_record(record: Result<csv::StringRecord, csv::Error>) 
-            -> Result<(usize, Vec<String>), Box<dyn Error>> {
-        // iterator yields Result<StringRecord, Error>, so check error here.
-        let result = record?;
-
-        // Parse the 1st entry to get the image label
-        let label_index: usize = result.get(0)
-            .expect("Could not parse record.").parse::<usize>()
-            .expect("Could not convert String to usize.");
-
-        // Skip the 1st element since we already parsed it above
-        let features: Vec<String> = 
-            result.iter().skip(1).map(|s| s.to_string()).collect::<Vec<String>>();
-
-        Ok((label_index, features))
-    }
-
-    fn add_values_from_file<Num: ToPrimitive + Copy + FromStr>
-            (&mut self, file_path: &String, train_iteration: usize)
-            -> Result<(), Box<dyn Error>> {
-        // Build the CSV reader and iterate over each record.
-        let mut rdr = csv::Reader::from_path(file_path)?;
-
-        // Get the 1st record wrapped in an Option<Result<>>, panic otherwise
-        let record = rdr.records().nth(0).expect("Could not parse CSV record.")?;
-
-        // subtract 1 to skip over the row index label 
-        if train_iteration == 0 {
-            let num_features = record.len() - 1; 
-            self.features = (0..num_features)
-                .map(|_| GaussianFeature::new(self.labels.len()))
-                .collect::<Vec<GaussianFeature>>();
-        }
-        
-        for (sample_idx, result) in rdr.records().enumerate() {
+    /// Maps each of the dataset's class indices to this model's index for
+    /// the class with the same name.
+    fn label_mapping(&self, dataset: &Dataset) -> Result<Vec<usize>, ModelError> {
+        dataset.label_mapping(&self.label_names())
+            .map_err(|_| ModelError::LabelNotFound)
+    }
+
+    fn add_values(&mut self, dataset: &Dataset, mapping: &[usize], train_iteration: usize)
+            -> Result<(), ModelError> {
+        for (sample_idx, (label_index, sample)) in dataset.iter().enumerate() {
             // Print the training status on the specified interval
             if sample_idx % PRINT_INTERVAL == 0 {
-                println!("Iteration {}", sample_idx);
+                eprintln!("Iteration {}", sample_idx);
             }
 
-            let (label_index, sample): (usize, Vec<String>) = 
-                GaussianNaiveBayes::parse_csv_record(result)?;
-            let label: &ClassLabel = &self.labels[label_index];
+            let label: &ClassLabel = 
+                self.labels.get(mapping[label_index]).ok_or(ModelError::LabelNotFound)?;
 
-            for (value, feature) in sample.iter().zip(self.features.iter_mut()) {
-                feature.train_iter::<Num>(label, value, train_iteration);
+            for (&value, feature) in sample.iter().zip(self.features.iter_mut()) {
+                let transformed: f64 = self.hyperparameters.transform(value);
+                feature.train_iter(label, transformed, train_iteration);
             }    
         }
 
         Ok(())
     }
 
-    fn test_linear<Num: ToPrimitive + Copy + FromStr>
-            (&self, file_path: &String) -> Result<f64, Box<dyn Error>> {
-        println!("Testing model.");
-        let mut rdr = csv::Reader::from_path(file_path)?;
-
-        let shape: usize = self.labels.len();
-        // Row: actual, Column: predicted
-        let mut confusion_matrix: Matrix<usize> = 
-            Array::<usize, Ix2>::zeros((shape, shape).f());
+    fn predict_linear(&self, dataset: &Dataset, mapping: &[usize])
+            -> Result<Vec<Prediction>, ModelError> {
+        eprintln!("Testing model.");
 
-        for (idx, result) in rdr.records().enumerate() {
+        dataset.iter().enumerate().map(|(idx, (actual_index, sample))| {
             if idx % PRINT_INTERVAL == 0 {
-                println!("Iteration {}", idx);
+                eprintln!("Iteration {}", idx);
             }
 
-            let (actual_index, features): (usize, Vec<String>) = 
-                GaussianNaiveBayes::parse_csv_record(result)?;
+            // Keep the full posterior so callers can score confidence
+            Ok(Prediction::new(mapping[actual_index], self.posteriors(sample)?))
+        }).collect::<Result<Vec<Prediction>, ModelError>>()
+    }
 
-            // Classify the features in the record
-            let predicted_label: Box<dyn Label> = self.classify::<Num>(&features)?;
-            let predicted_index: usize = predicted_label.get_index();
+    fn predict_multithreaded(&self, dataset: &Dataset, mapping: &[usize])
+            -> Result<Vec<Prediction>, ModelError> {
+        eprintln!("Testing model.");
 
-            // Indexed by row = actual label, column = predicted label
-            confusion_matrix[[actual_index, predicted_index]] += 1;
-        }
+        let num_threads: usize = 10;
+        let chunk_size: usize = dataset.len().div_ceil(num_threads);
+
+        // Each thread handles a contiguous block of rows so the combined 
+        // predictions stay in the same order as the dataset
+        thread::scope(|scope| {
+            let receivers: Vec<Receiver<Result<Vec<Prediction>, ModelError>>> = 
+                (0..num_threads).map(|thread_idx| {
+                    let (tx, rx) = mpsc::channel();
+                    let start: usize = (thread_idx * chunk_size).min(dataset.len());
+                    let end: usize = (start + chunk_size).min(dataset.len());
+
+                    scope.spawn(move || {
+                        tx.send(self.predict_range(dataset, mapping, start, end, thread_idx)).unwrap();
+                        eprintln!("Thread {}\tFINISHED", thread_idx);
+                    });
+
+                    rx // keep the receiver to collect the results
+                }).collect();
+
+            // Combine received predictions from each thread into one list
+            receivers.iter().try_fold(Vec::new(), |mut acc, rx| {
+                acc.extend(rx.recv().unwrap()?);
+                Ok(acc)
+            })
+        })
+    }
 
-        println!("Confusion Matrix:");
-        println!("{:?}\n", confusion_matrix);
+    fn predict_range(&self, dataset: &Dataset, mapping: &[usize], start: usize, end: usize,
+            thread_index: usize) -> Result<Vec<Prediction>, ModelError> {
+        (start..end).map(|idx| {
+            if (idx - start).is_multiple_of(2500) {
+                eprintln!("Thread {}\tIteration {}", thread_index, idx - start);
+            }
 
-        Ok(GaussianNaiveBayes::calculate_accuracy(&confusion_matrix))
+            let posteriors: Vec<f64> = self.posteriors(dataset.get_sample(idx))?;
+            Ok(Prediction::new(mapping[dataset.get_label(idx)], posteriors))
+        }).collect::<Result<Vec<Prediction>, ModelError>>()
     }
 
-    fn test_multithreaded<Num: ToPrimitive + Copy + FromStr>
-            (&self, file_path: &String) -> Result<f64, Box<dyn Error>> {
-        
-        println!("Testing model.");
+    /// Sets every standard deviation once the training passes are done,
+    /// smoothing each variance by a fraction of the widest feature's spread.
+    fn configure_std(&mut self) {
+        let max_variance: f64 = self.features.iter()
+            .map(|f| f.total_variance())
+            .fold(0.0, f64::max);
+        let epsilon: f64 = self.hyperparameters.var_smoothing * max_variance;
 
-        let num_threads: usize = 10;
-        let matrix_shape: usize = self.labels.len();
-
-        // Wrap copies of the features and labels of the model in Arc pointers 
-        // so we can share the model data between threads
-        let model_data: Arc<Vec<GaussianFeature>> = Arc::new(self.features.to_owned());
-        let model_labels: Arc<Vec<ClassLabel>> = Arc::new(self.labels.to_owned());
-        
-        // TODO move path to arc to avoid repeated cloning
-        // let csv_path: Arc<String> = 
-
-        let receivers: Vec<Receiver<Matrix<usize>>> = 
-            (0..num_threads).map(|thread_idx| {
-                let (tx, rx) = mpsc::channel();
-
-                // Clone the arc reference so we can pass the same data 
-                let model_clone: Arc<Vec<GaussianFeature>> = Arc::clone(&model_data);
-                let labels_clone: Arc<Vec<ClassLabel>> = Arc::clone(&model_labels);
-                let path: String = file_path.clone();
-                
-                // Spawn a thread and send the transmitter to pass the results
-                thread::spawn(move || {
-                    if let Err(e) = 
-                        GaussianNaiveBayes::test_helper::<Num>(
-                            model_clone, labels_clone, path, tx, 
-                            matrix_shape, thread_idx, num_threads) {
-                        panic!("Error in Thread {}: {:?}", thread_idx, e);
-                    }
-                });
+        for feature in self.features.iter_mut() {
+            feature.configure_std(self.hyperparameters.min_std, epsilon);
+        }
+    }
 
-                rx // keep the receiver to collect the results
-            }).collect::<Vec<Receiver<Matrix<usize>>>>();
+    /// Training rows in each class, in label order. Models saved before
+    /// class sizes were stored count them from the first feature.
+    fn class_sizes(&self) -> Result<Vec<usize>, ModelError> {
+        let first: &GaussianFeature = 
+            self.features.first().ok_or(ModelError::UntrainedError)?;
 
-        let zeros_matrix: Matrix<usize> = 
-            Array::<usize, _>::zeros((matrix_shape, matrix_shape).f());
-            
-        // Combine received confusion matrices from each thread into one matrix
-        let confusion_matrix: Array<usize, Ix2> = 
-            receivers.iter().fold(zeros_matrix, |acc, rx| {
-                let matrix: Array<usize, Ix2> = rx.recv().unwrap();
-                acc + matrix
-            });
+        if !self.class_sizes.is_empty() {
+            return Ok(self.class_sizes.clone());
+        }
 
-        Ok(GaussianNaiveBayes::calculate_accuracy(&confusion_matrix))
+        Ok(self.labels.iter()
+            .map(|label| first.get_class_sample_size(label))
+            .collect::<Vec<usize>>())
     }
 
-    fn test_helper<Num: ToPrimitive + Copy + FromStr>
-            (model: Arc<Vec<GaussianFeature>>, labels: Arc<Vec<ClassLabel>>, 
-                csv_file_path: String, transmitter: Sender<Matrix<usize>>, 
-                matrix_shape: usize, thread_index: usize, num_threads: usize) 
-            -> Result<(), Box<dyn Error>> {
-        let mut rdr = csv::Reader::from_path(csv_file_path)?;
+    /// Log10 prior of each class, in label order.
+    fn log_priors(&self) -> Result<Vec<f64>, ModelError> {
+        Ok(self.hyperparameters.log_priors(&self.class_sizes()?))
+    }
 
-        // Iterate through the records, starting on offset given by thread index
-        // Step by num threads since parallel threads will handle skipped records
-        let csv_iter = rdr.records().skip(thread_index).step_by(num_threads);
+    /// The trained parameters, class by class.
+    pub fn parameters(&self) -> Result<GaussianParameters, ModelError> {
+        if self.features.iter().any(|feature| !feature.is_trained()) {
+            return Err(ModelError::UntrainedError);
+        }
 
-        let mut confusion_matrix: Matrix<usize> = 
-            Array::<usize, _>::zeros((matrix_shape, matrix_shape).f());
+        let per_class = |value: fn(&GaussianClassification) -> f64| (0..self.labels.len())
+            .map(|class| self.features.iter()
+                .map(|feature| value(&feature.get_classifications()[class]))
+                .collect::<Vec<f64>>())
+            .collect::<Vec<Vec<f64>>>();
+
+        Ok(GaussianParameters {
+            labels: self.label_names(),
+            log_priors: self.log_priors()?,
+            means: per_class(GaussianClassification::get_mean),
+            stds: per_class(GaussianClassification::get_std),
+            binarize: self.hyperparameters.binarize
+        })
+    }
 
-        for (idx, result) in csv_iter.enumerate() {
-            if idx % 2500 == 0 {
-                println!("Thread {}\tIteration {}", thread_index, idx);
-            }
+    /// Joint log10 likelihood of the sample under each class, in label order.
+    /// Missing (NaN) features are marginalized out by omitting their terms.
+    fn log_likelihoods(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        let log_priors: Vec<f64> = self.log_priors()?;
 
-            let (actual_index, features): (usize, Vec<String>) = 
-                GaussianNaiveBayes::parse_csv_record(result)?;
+        if sample_features.len() != self.features.len() {
+            return Err(ModelError::FeatureCountMismatch);
+        }
 
-            let predicted_label: Box<dyn Label> = 
-                GaussianNaiveBayes::classify_helper::<Num>(&model, &labels, &features)?;
+        // Combine the likelihoods of the occurrences of each feature
+        self.labels.iter().zip(log_priors.iter()).map(|(current_class, &log_prior)| {
+            let feature_likelihoods: f64 = self.features.iter().zip(sample_features.iter())
+                .filter(|(_, value)| !value.is_nan())
+                .try_fold(0.0, |total, (feat, &value)| {
+                    // Use log rules and addition to avoid float underflow
+                    let transformed: f64 = self.hyperparameters.transform(value);
+                    feat.likelihood_given_class(transformed, current_class)
+                        .map(|prob| total + prob.log10())
+                })?;
 
-            let predicted_index: usize = predicted_label.get_index();
+            Ok(feature_likelihoods + log_prior)
+        }).collect::<Result<Vec<f64>, ModelError>>()
+    }
+}
 
-            confusion_matrix[[actual_index, predicted_index]] += 1;
-        }
+impl Model for GaussianNaiveBayes {
+    fn from_json(file_path: &str) -> GaussianNaiveBayes {
+        model::read_json(file_path, ModelKind::Gaussian)
+    }
 
-        // Send intermediate confusion matrix from this thread to main thread
-        transmitter.send(confusion_matrix).unwrap();
-        println!("Thread {}\tFINISHED", thread_index);
+    fn to_json(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        model::write_json(self, &ModelMetadata::new(ModelKind::Gaussian, self.hyperparameters), file_path)
+    }
 
-        Ok(())
+    fn kind() -> ModelKind {
+        ModelKind::Gaussian
     }
 
-    fn classify_helper<Num: ToPrimitive + Copy + FromStr>
-            (model: &Vec<GaussianFeature>, 
-                labels: &Vec<ClassLabel>,
-                sample_features: &Vec<String>) 
-            -> Result<Box<dyn Label>, ModelError> {
-        let mut max_likelihood: f64 = 0.0;
-        let mut best_label: Option<&ClassLabel> = None;
+    fn from_binary(file_path: &str) -> GaussianNaiveBayes {
+        binary::read_binary(file_path, ModelKind::Gaussian)
+    }
 
-        // Combine the likelihoods of the occurrences of each feature
-        for current_class in labels.iter() {        
-            let feature_likelihoods: f64 = model.iter().enumerate()
-                                            .fold(0.0, |total, (idx, feat)| {
-                match feat.likelihood_given_class::<Num>(
-                        &sample_features[idx], current_class) {
-                    // Use log rules and addition to avoid float underflow
-                    Ok(prob) => total + prob.log10(),
-                    Err(error) => panic!("{:?}", error)
-                }
-            });
+    fn to_binary(&self, file_path: &str, compression: Compression) -> Result<(), Box<dyn Error>> {
+        let metadata: ModelMetadata = ModelMetadata::new(ModelKind::Gaussian, self.hyperparameters);
+        binary::write_binary(self, &metadata, file_path, compression)
+    }
 
-            let class_prob: f64 = 
-                model[0].class_likelihood(current_class)?;
-            let likelihood: f64 = feature_likelihoods + class_prob.log10();
+    /// Every class's statistics are written as one block per statistic,
+    /// with a value for each feature.
+    fn encode_binary(&self, writer: &mut BinaryWriter) {
+        writer.write_labels(&self.labels);
+        writer.write_hyperparameters(&self.hyperparameters);
+        writer.write_usize(self.class_sizes.len());
+        writer.write_sizes(&self.class_sizes);
+
+        let num_classes: usize = self.features.first()
+            .map_or(0, |feature| feature.get_classifications().len());
+        writer.write_usize(self.features.len());
+        writer.write_usize(num_classes);
+        for feature in self.features.iter() {
+            writer.write_bool(feature.is_trained());
+            writer.write_usize(feature.get_sample_size());
+        }
 
-            if best_label.is_none() || likelihood > max_likelihood {
-                max_likelihood = likelihood;
-                best_label = Some(current_class);
+        for class in 0..num_classes {
+            gaussian_classification::encode_blocks(&self.features.iter()
+                .map(|feature| &feature.get_classifications()[class])
+                .collect::<Vec<&GaussianClassification>>(), writer);
+        }
+    }
+
+    fn decode_binary(reader: &mut BinaryReader) -> Result<GaussianNaiveBayes, ModelFileError> {
+        let labels: Vec<ClassLabel> = reader.read_labels()?;
+        let hyperparameters: Hyperparameters = reader.read_hyperparameters()?;
+        let num_sizes: usize = reader.read_count()?;
+        let class_sizes: Vec<usize> = reader.read_sizes(num_sizes)?;
+
+        let (num_features, num_classes): (usize, usize) = (reader.read_count()?, reader.read_count()?);
+        let trained: Vec<(bool, usize)> = (0..num_features)
+            .map(|_| Ok((reader.read_bool()?, reader.read_usize()?)))
+            .collect::<Result<Vec<(bool, usize)>, ModelFileError>>()?;
+
+        let mut classifications: Vec<Vec<GaussianClassification>> = vec![Vec::new(); num_features];
+        for _ in 0..num_classes {
+            let class: Vec<GaussianClassification> =
+                gaussian_classification::decode_blocks(num_features, reader)?;
+            for (feature, classification) in classifications.iter_mut().zip(class) {
+                feature.push(classification);
             }
         }
 
-        Ok(Box::new(*best_label.expect("Could not classify record.")))
+        Ok(GaussianNaiveBayes {
+            labels,
+            features: trained.into_iter().zip(classifications)
+                .map(|((is_trained, sample_size), classifications)|
+                    GaussianFeature::from_classifications(is_trained, sample_size, classifications))
+                .collect::<Vec<GaussianFeature>>(),
+            class_sizes,
+            hyperparameters
+        })
     }
-}
 
-impl Model for GaussianNaiveBayes {
-    fn from_json(file_path: &String) -> GaussianNaiveBayes {
-        print!("Loading model from {}...", file_path);
-        
-        let data = fs::read_to_string(file_path).expect("Unable to read JSON file.");
-        let deserialized: GaussianNaiveBayes = 
-            serde_json::from_str(&data).expect("Could not deserialize JSON.");
-        
-        println!("done.");
+    fn to_mapped(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        let p: GaussianParameters = self.parameters()?;
+        mapped::write_mapped(file_path, &p.labels, &p.log_priors, &p.means.concat(), &p.stds.concat(),
+            p.binarize)
+    }
 
-        deserialized
+    fn to_onnx(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        let p: GaussianParameters = self.parameters()?;
+        onnx::write_gaussian(file_path, &p.labels, &p.log_priors, &p.means.concat(), &p.stds.concat(),
+            p.binarize)
     }
 
-    fn to_json(&self, file_path: &String) {
-        print!("Saving model to {}...", file_path);
+    /// Binarized inputs are not Gaussian, so only models without a binarize
+    /// threshold can be exported.
+    fn to_pmml(&self) -> Result<PmmlModel, Box<dyn Error>> {
+        if self.hyperparameters.binarize.is_some() {
+            return Err(Box::new(PmmlError::UnsupportedModel));
+        }
+        if self.features.iter().any(|feature| !feature.is_trained()) {
+            return Err(Box::new(ModelError::UntrainedError));
+        }
 
-        let data = serde_json::to_string(&self).expect("Could not serialize JSON.");
-        let message: String = format!("Unable to write to file at {}", file_path);
-        fs::write(file_path, data).expect(&message);
-        
-        println!("done.")
+        Ok(PmmlModel {
+            labels: self.label_names(),
+            inputs: self.features.iter()
+                .map(|feature| BayesInput::Gaussian { means: feature.means(), variances: feature.variances() })
+                .collect::<Vec<BayesInput>>(),
+            class_counts: pmml::prior_counts(&self.class_sizes()?, &self.hyperparameters),
+            hyperparameters: self.hyperparameters
+        })
     }
 
-    fn from_labels(file_path: &String) -> GaussianNaiveBayes {
-        let msg: String = format!("Unable to read labels file at {}", file_path);
-        let file = fs::File::open(file_path).expect(&msg);
+    fn from_pmml(pmml: &PmmlModel) -> Result<GaussianNaiveBayes, Box<dyn Error>> {
+        let class_sizes: Vec<usize> = pmml.class_sizes();
+        let features: Vec<GaussianFeature> = pmml.inputs.iter().map(|input| match input {
+            BayesInput::Gaussian { means, variances } =>
+                Ok(GaussianFeature::from_distributions(means, variances, &class_sizes)),
+            BayesInput::Categorical { .. } => Err(PmmlError::UnsupportedModel)
+        }).collect::<Result<Vec<GaussianFeature>, PmmlError>>()?;
+
+        Ok(GaussianNaiveBayes {
+            labels: ClassLabel::from_names(&pmml.labels),
+            features,
+            class_sizes,
+            hyperparameters: pmml.hyperparameters
+        })
+    }
 
-        print!("Reading labels...");
+    fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
 
-        let reader: BufReader<fs::File> = BufReader::new(file);
-        let labels: Vec<ClassLabel> = reader.lines().map(|line| {
-            // split each line by space to get index and ascii values
-            let result = line.expect("Could not parse label.");
-            let components: Vec<&str> = result.split(" ").collect();
+    fn summary(&self) -> Result<ModelSummary, ModelError> {
+        if self.features.iter().any(|feature| !feature.is_trained()) {
+            return Err(ModelError::UntrainedError);
+        }
 
-            // Save parsed values in a ClassLabel struct
-            ClassLabel::new(
-                components[0].parse::<usize>().unwrap(),
-                components[1].parse::<u8>().unwrap()
-            )
-        }).collect::<Vec<ClassLabel>>();
+        let log_priors: Vec<f64> = self.log_priors()?;
+        let zero_variance: Vec<usize> = (0..self.labels.len())
+            .map(|class| self.features.iter()
+                .filter(|feature| feature.get_classifications()[class].has_zero_variance())
+                .count())
+            .collect::<Vec<usize>>();
+        let scores: Vec<(ScoreMeasure, f64)> = self.features.iter()
+            .map(|feature| (ScoreMeasure::FStatistic, feature.f_statistic()))
+            .collect::<Vec<(ScoreMeasure, f64)>>();
+
+        Ok(ModelSummary::new(ModelKind::Gaussian, self.label_names(), &self.class_sizes()?,
+            &log_priors, Some(zero_variance), &scores))
+    }
+
+    fn get_hyperparameters(&self) -> &Hyperparameters {
+        &self.hyperparameters
+    }
 
-        println!("done.");
+    fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters) {
+        self.hyperparameters = hyperparameters;
+    }
 
+    fn from_label_names(label_names: &[String]) -> GaussianNaiveBayes {
         GaussianNaiveBayes {
-            labels: labels,
-            features: Vec::new()
+            labels: ClassLabel::from_names(label_names),
+            features: Vec::new(),
+            class_sizes: Vec::new(),
+            hyperparameters: Hyperparameters::default()
         }
     }
 
-    fn train<Num: ToPrimitive + Copy + FromStr>(
-            &mut self, file_path: &String) -> Result<(), Box<dyn Error>> {
-        println!("Adding distribution means.");
-        self.add_values_from_file::<Num>(&file_path, 0)?;
+    fn label_names(&self) -> Vec<String> {
+        self.labels.iter()
+            .map(|label| label.get_name().to_string())
+            .collect::<Vec<String>>()
+    }
 
-        println!("Adding distribution standard deviations.");
-        self.add_values_from_file::<Num>(&file_path, 1)?;
+    fn from_labels(file_path: &str) -> GaussianNaiveBayes {
+        GaussianNaiveBayes {
+            labels: ClassLabel::from_file(file_path),
+            features: Vec::new(),
+            class_sizes: Vec::new(),
+            hyperparameters: Hyperparameters::default()
+        }
+    }
 
-        for feature in self.features.iter_mut() {
-            feature.prepare();
+    fn fit(&mut self, dataset: &Dataset) -> Result<(), Box<dyn Error>> {
+        if self.labels.is_empty() {
+            self.labels = ClassLabel::from_names(dataset.get_label_names());
         }
+        let mapping: Vec<usize> = self.label_mapping(dataset)?;
+
+        self.features = (0..dataset.num_features())
+            .map(|_| GaussianFeature::new(self.labels.len()))
+            .collect::<Vec<GaussianFeature>>();
+
+        self.class_sizes = vec![0; self.labels.len()];
+        for &label_index in dataset.get_labels().iter() {
+            self.class_sizes[mapping[label_index]] += 1;
+        }
+
+        eprintln!("Adding distribution means.");
+        self.add_values(dataset, &mapping, 0)?;
 
+        eprintln!("Adding distribution standard deviations.");
+        self.add_values(dataset, &mapping, 1)?;
+
+        self.configure_std();
         Ok(())
     }
 
-    fn test<Num: ToPrimitive + Copy + FromStr>
-            (&self, file_path: &String, multithreaded: bool) 
-            -> Result<f64, Box<dyn Error>> {
+    fn predict(&self, dataset: &Dataset, multithreaded: bool) 
+            -> Result<Vec<Prediction>, ModelError> {
+        let mapping: Vec<usize> = self.label_mapping(dataset)?;
+
         if multithreaded {
-            self.test_multithreaded::<Num>(&file_path)
+            self.predict_multithreaded(dataset, &mapping)
         } else {
-            self.test_linear::<Num>(&file_path)
+            self.predict_linear(dataset, &mapping)
+        }
+    }
+
+    fn classify(&self, sample_features: &[f64]) -> Result<Box<dyn Label>, ModelError> {
+        let log_likelihoods: Vec<f64> = self.log_likelihoods(sample_features)?;
+        self.label(prediction::argmax(&log_likelihoods))
+    }
+
+    fn posteriors(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        Ok(prediction::normalize_log10(&self.log_likelihoods(sample_features)?))
+    }
+
+    /// Terms are computed in log space, so unlike the likelihoods that
+    /// `classify` multiplies they stay finite far from every class mean.
+    fn log_likelihood_terms(&self, sample_features: &[f64])
+            -> Result<(Vec<f64>, Vec<Vec<f64>>), ModelError> {
+        let log_priors: Vec<f64> = self.log_priors()?;
+        if self.features.iter().any(|feature| !feature.is_trained()) {
+            return Err(ModelError::UntrainedError);
+        }
+        if sample_features.len() != self.features.len() {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        let terms: Vec<Vec<f64>> = (0..self.labels.len())
+            .map(|class| self.features.iter().zip(sample_features.iter())
+                .map(|(feature, &value)| if value.is_nan() {
+                    0.0
+                } else {
+                    feature.get_classifications()[class].log10_pdf(self.hyperparameters.transform(value))
+                })
+                .collect::<Vec<f64>>())
+            .collect::<Vec<Vec<f64>>>();
+
+        Ok((log_priors, terms))
+    }
+
+    fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError> {
+        match self.labels.get(index) {
+            Some(label) => Ok(Box::new(label.clone())),
+            None => Err(ModelError::LabelNotFound)
+        }
+    }
+}
+
+impl SparseModel for GaussianNaiveBayes {
+    fn fit_sparse(&mut self, dataset: &SparseDataset) -> Result<(), Box<dyn Error>> {
+        if !dataset.is_labelled() {
+            return Err(Box::new(DatasetError::MissingLabel));
+        }
+        if self.labels.is_empty() {
+            self.labels = ClassLabel::from_names(dataset.get_label_names());
+        }
+        let mapping: Vec<usize> = dataset.label_mapping(&self.label_names())
+            .map_err(|_| ModelError::LabelNotFound)?;
+
+        self.features = (0..dataset.num_features())
+            .map(|_| GaussianFeature::new(self.labels.len()))
+            .collect::<Vec<GaussianFeature>>();
+
+        let nonzero: Vec<Vec<usize>> = sparse::nonzero_counts(dataset, &mapping, self.labels.len());
+        let mut class_sizes: Vec<usize> = vec![0; self.labels.len()];
+        for &label_index in dataset.get_labels().iter() {
+            class_sizes[mapping[label_index]] += 1;
+        }
+        let zero: f64 = self.hyperparameters.transform(0.0);
+
+        for train_iteration in 0..2 {
+            eprintln!("Training pass {} over sparse rows.", train_iteration + 1);
+
+            for (&label_index, row) in dataset.get_labels().iter().zip(dataset.get_rows()) {
+                let label: &ClassLabel = &self.labels[mapping[label_index]];
+
+                for (idx, value) in row.iter() {
+                    let transformed: f64 = self.hyperparameters.transform(value);
+                    self.features[idx].train_iter(label, transformed, train_iteration);
+                }
+            }
+
+            // Every entry a row leaves out is a zero
+            for (class, label) in self.labels.iter().enumerate() {
+                for (feature, &count) in self.features.iter_mut().zip(nonzero[class].iter()) {
+                    feature.train_repeated(label, zero, class_sizes[class] - count, train_iteration);
+                }
+            }
+        }
+
+        self.class_sizes = class_sizes;
+        self.configure_std();
+        Ok(())
+    }
+
+    fn sparse_posteriors(&self, rows: &[SparseRow]) -> Result<Vec<Vec<f64>>, ModelError> {
+        let log_priors: Vec<f64> = self.log_priors()?;
+        let zero: f64 = self.hyperparameters.transform(0.0);
+
+        // Score an all-zero row once, then correct it for each stored entry
+        let zero_terms: Vec<Vec<f64>> = self.labels.iter().map(|label| {
+            self.features.iter()
+                .map(|feat| feat.likelihood_given_class(zero, label).map(|prob| prob.log10()))
+                .collect::<Result<Vec<f64>, ModelError>>()
+        }).collect::<Result<Vec<Vec<f64>>, ModelError>>()?;
+        let baselines: Vec<f64> = zero_terms.iter().zip(log_priors.iter())
+            .map(|(terms, &log_prior)| terms.iter().sum::<f64>() + log_prior)
+            .collect::<Vec<f64>>();
+
+        rows.iter().map(|row| {
+            if row.width() > self.features.len() {
+                return Err(ModelError::FeatureCountMismatch);
+            }
+
+            let log_likelihoods: Vec<f64> = self.labels.iter().enumerate()
+                .map(|(class, label)| row.iter().try_fold(baselines[class], |total, (idx, value)| {
+                    // A stored NaN is missing, so only its zero term is removed
+                    if value.is_nan() {
+                        return Ok(total - zero_terms[class][idx]);
+                    }
+
+                    let transformed: f64 = self.hyperparameters.transform(value);
+                    self.features[idx].likelihood_given_class(transformed, label)
+                        .map(|prob| total + prob.log10() - zero_terms[class][idx])
+                }))
+                .collect::<Result<Vec<f64>, ModelError>>()?;
+
+            Ok(prediction::normalize_log10(&log_likelihoods))
+        }).collect::<Result<Vec<Vec<f64>>, ModelError>>()
+    }
+}
+
+#[cfg(test)]
+mod gaussian_nb_tests {
+    use crate::ml::fitted_gaussian;
+    use crate::model::gaussian_nb::*;
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let names: Vec<String> = vec![String::from("a"), String::from("b")];
+        let samples: Vec<Vec<f64>> = vec![
+            vec![1.0, 0.0, 2.0], vec![3.0, 0.5, 0.0], vec![0.0, 4.0, 1.0], 
+            vec![0.0, 2.0, 0.0], vec![2.0, 0.0, 0.0], vec![0.0, 3.0, 3.0]
+        ];
+        let dataset: Dataset = Dataset::with_label_names(names, vec![0, 0, 1, 1, 0, 1], samples);
+
+        let mut dense_model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        let mut sparse_model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        dense_model.fit(&dataset).unwrap();
+        sparse_model.fit_sparse(&SparseDataset::from_dense(&dataset)).unwrap();
+
+        let sample: [f64; 3] = [1.5, 0.0, 1.0];
+        let expected: Vec<f64> = dense_model.posteriors(&sample).unwrap();
+        let actual: Vec<f64> = sparse_model
+            .sparse_posteriors(&[SparseRow::from_dense(&sample)]).unwrap().remove(0);
+
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            assert_relative_eq!(e, a, max_relative=1e-6);
+        }
+    }
+
+    #[test]
+    fn test_missing_values_are_marginalized() {
+        let names: Vec<String> = vec![String::from("a"), String::from("b")];
+        let samples: Vec<Vec<f64>> = vec![
+            vec![1.0, f64::NAN], vec![f64::NAN, 2.0], vec![2.0, 3.0],
+            vec![8.0, 9.0], vec![9.0, 8.0]
+        ];
+        let dataset: Dataset = Dataset::with_label_names(names, vec![0, 0, 0, 1, 1], samples);
+
+        let mut model: GaussianNaiveBayes = GaussianNaiveBayes::from_label_names(&[]);
+        model.fit(&dataset).unwrap();
+
+        // Priors count every row even though no feature saw all of class a
+        let priors: Vec<f64> = model.posteriors(&[f64::NAN, f64::NAN]).unwrap();
+        assert_relative_eq!(priors[0], 0.6, max_relative=1e-9);
+
+        let without_second: Vec<f64> = model.posteriors(&[1.5, f64::NAN]).unwrap();
+        let sparse: Vec<f64> = model.sparse_posteriors(
+            &[SparseRow::new(vec![(0, 1.5), (1, f64::NAN)])]).unwrap().remove(0);
+        assert!(without_second[0] > 0.99);
+        for (e, a) in without_second.iter().zip(sparse.iter()) {
+            assert_relative_eq!(e, a, max_relative=1e-9);
         }
     }
 
-    fn classify<Num: ToPrimitive + Copy + FromStr>
-            (&self, sample_features: &Vec<String>) 
-            -> Result<Box<dyn Label>, ModelError> {
-        GaussianNaiveBayes::classify_helper::<Num>(
-            &self.features, &self.labels, &sample_features)
+    #[test]
+    fn test_summary() {
+        assert!(GaussianNaiveBayes::from_label_names(&[]).summary().is_err());
+
+        let (_, model): (Dataset, GaussianNaiveBayes) = fitted_gaussian(vec![0, 0, 0, 1, 1], vec![
+            vec![1.0, 0.0, 5.0], vec![2.0, 0.0, 6.0], vec![3.0, 0.0, 5.5],
+            vec![1.5, 9.0, 7.0], vec![2.5, 9.0, 8.0]
+        ]);
+
+        let summary: ModelSummary = model.summary().unwrap();
+        assert_eq!(summary.num_features, 3);
+        assert_eq!(summary.classes[0].sample_size, 3);
+        assert_relative_eq!(summary.classes[1].prior, 0.4, max_relative=1e-9);
+        assert_eq!(summary.classes[0].zero_variance_features, Some(1));
+        assert_eq!(summary.classes[1].zero_variance_features, Some(1));
+
+        // The feature constant within each class separates them best, and
+        // the one with equal class means not at all
+        assert_eq!(summary.features.iter().map(|f| f.feature).collect::<Vec<usize>>(), vec![1, 2, 0]);
+        assert!(summary.features[0].score.is_infinite());
+        assert_relative_eq!(summary.features[2].score, 0.0);
     }
 }
@@ -0,0 +1,483 @@
+extern crate serde;
+
+use std::{error::Error, vec::Vec, string::String};
+
+use crate::ml::{model::{self, Model}, feature::Feature, label::{self, Label}};
+use crate::ml::{error::{DatasetError, ModelError, ModelFileError}, hyperparameters::Hyperparameters};
+use crate::ml::dataset::{Dataset, FeatureType};
+use crate::ml::prediction::{self, Prediction};
+use crate::ml::sparse::{SparseDataset, SparseModel, SparseRow};
+use crate::ml::{binary::{self, BinaryReader, BinaryWriter, Compression}, envelope::ModelMetadata};
+use crate::ml::pmml::{self, BayesInput, PmmlModel};
+use crate::ml::inspection::{self, ModelSummary, ScoreMeasure};
+use crate::model::ModelKind;
+use crate::naivebayes::class_label::ClassLabel;
+use crate::naivebayes::gaussian_feature::GaussianFeature;
+use crate::naivebayes::discrete_feature::DiscreteFeature;
+
+use self::serde::{Serialize, Deserialize};
+
+/// A feature modelled according to its declared type.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+enum MixedFeature {
+    Gaussian(GaussianFeature),
+    Categorical(DiscreteFeature)
+}
+
+impl MixedFeature {
+    fn as_feature(&self) -> &dyn Feature {
+        match self {
+            MixedFeature::Gaussian(feature) => feature,
+            MixedFeature::Categorical(feature) => feature
+        }
+    }
+
+    fn as_feature_mut(&mut self) -> &mut dyn Feature {
+        match self {
+            MixedFeature::Gaussian(feature) => feature,
+            MixedFeature::Categorical(feature) => feature
+        }
+    }
+
+    fn encode_binary(&self, writer: &mut BinaryWriter) {
+        match self {
+            MixedFeature::Gaussian(feature) => {
+                writer.write_u8(0);
+                feature.encode_binary(writer);
+            },
+            MixedFeature::Categorical(feature) => {
+                writer.write_u8(1);
+                feature.encode_binary(writer);
+            }
+        }
+    }
+
+    fn decode_binary(reader: &mut BinaryReader) -> Result<MixedFeature, ModelFileError> {
+        match reader.read_u8()? {
+            0 => Ok(MixedFeature::Gaussian(GaussianFeature::decode_binary(reader)?)),
+            1 => Ok(MixedFeature::Categorical(DiscreteFeature::decode_binary(reader)?)),
+            _ => Err(ModelFileError::InvalidEncoding)
+        }
+    }
+}
+
+fn encode_feature_type(feature_type: &FeatureType, writer: &mut BinaryWriter) {
+    match feature_type {
+        FeatureType::Numeric => writer.write_u8(0),
+        FeatureType::Nominal(categories) => {
+            writer.write_u8(1);
+            writer.write_usize(categories.len());
+            for category in categories.iter() {
+                writer.write_string(category);
+            }
+        }
+    }
+}
+
+fn decode_feature_type(reader: &mut BinaryReader) -> Result<FeatureType, ModelFileError> {
+    match reader.read_u8()? {
+        0 => Ok(FeatureType::Numeric),
+        1 => Ok(FeatureType::Nominal((0..reader.read_count()?)
+            .map(|_| reader.read_string())
+            .collect::<Result<Vec<String>, ModelFileError>>()?)),
+        _ => Err(ModelFileError::InvalidEncoding)
+    }
+}
+
+/// Naive Bayes over a mix of numeric and nominal features, as declared by
+/// the dataset's feature types. Numeric features are Gaussian and nominal
+/// ones categorical. Missing (NaN) values are left out of training and
+/// their likelihood terms are skipped at classification.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MixedNaiveBayes {
+    labels: Vec<ClassLabel>,
+    feature_types: Vec<FeatureType>,
+    features: Vec<MixedFeature>,
+    class_sizes: Vec<usize>,
+    #[serde(default)]
+    hyperparameters: Hyperparameters
+}
+
+impl MixedNaiveBayes {
+    pub fn get_feature_types(&self) -> &[FeatureType] {
+        &self.feature_types
+    }
+
+    fn label_mapping(&self, label_names: &[String]) -> Result<Vec<usize>, ModelError> {
+        label::mapping(label_names, &self.label_names())
+            .map_err(|_| ModelError::LabelNotFound)
+    }
+
+    fn add_values(&mut self, dataset: &Dataset, mapping: &[usize], train_iteration: usize) {
+        for (label_index, sample) in dataset.iter() {
+            let label: &ClassLabel = &self.labels[mapping[label_index]];
+
+            for (&value, feature) in sample.iter().zip(self.features.iter_mut()) {
+                if !value.is_nan() {
+                    feature.as_feature_mut().train_iter(label, value, train_iteration);
+                }
+            }
+        }
+    }
+
+    fn log_likelihoods(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        if self.features.is_empty() {
+            return Err(ModelError::UntrainedError);
+        }
+        if sample_features.len() != self.features.len() {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        let log_priors: Vec<f64> = self.hyperparameters.log_priors(&self.class_sizes);
+
+        self.labels.iter().zip(log_priors.iter()).map(|(class, &log_prior)| {
+            self.features.iter().zip(sample_features.iter())
+                .filter(|(_, value)| !value.is_nan())
+                .try_fold(log_prior, |total, (feature, &value)| feature.as_feature()
+                    .likelihood_given_class(value, class)
+                    .map(|prob| total + prob.log10()))
+        }).collect::<Result<Vec<f64>, ModelError>>()
+    }
+}
+
+impl Model for MixedNaiveBayes {
+    fn from_labels(file_path: &str) -> MixedNaiveBayes {
+        MixedNaiveBayes {
+            labels: ClassLabel::from_file(file_path),
+            feature_types: Vec::new(),
+            features: Vec::new(),
+            class_sizes: Vec::new(),
+            hyperparameters: Hyperparameters::default()
+        }
+    }
+
+    fn from_label_names(label_names: &[String]) -> MixedNaiveBayes {
+        MixedNaiveBayes {
+            labels: ClassLabel::from_names(label_names),
+            feature_types: Vec::new(),
+            features: Vec::new(),
+            class_sizes: Vec::new(),
+            hyperparameters: Hyperparameters::default()
+        }
+    }
+
+    fn from_json(file_path: &str) -> MixedNaiveBayes {
+        model::read_json(file_path, ModelKind::Mixed)
+    }
+
+    fn to_json(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        model::write_json(self, &ModelMetadata::new(ModelKind::Mixed, self.hyperparameters), file_path)
+    }
+
+    fn kind() -> ModelKind {
+        ModelKind::Mixed
+    }
+
+    fn from_binary(file_path: &str) -> MixedNaiveBayes {
+        binary::read_binary(file_path, ModelKind::Mixed)
+    }
+
+    fn to_binary(&self, file_path: &str, compression: Compression) -> Result<(), Box<dyn Error>> {
+        let metadata: ModelMetadata = ModelMetadata::new(ModelKind::Mixed, self.hyperparameters);
+        binary::write_binary(self, &metadata, file_path, compression)
+    }
+
+    fn encode_binary(&self, writer: &mut BinaryWriter) {
+        writer.write_labels(&self.labels);
+        writer.write_hyperparameters(&self.hyperparameters);
+        writer.write_usize(self.class_sizes.len());
+        writer.write_sizes(&self.class_sizes);
+
+        writer.write_usize(self.feature_types.len());
+        for feature_type in self.feature_types.iter() {
+            encode_feature_type(feature_type, writer);
+        }
+        writer.write_usize(self.features.len());
+        for feature in self.features.iter() {
+            feature.encode_binary(writer);
+        }
+    }
+
+    fn decode_binary(reader: &mut BinaryReader) -> Result<MixedNaiveBayes, ModelFileError> {
+        let labels: Vec<ClassLabel> = reader.read_labels()?;
+        let hyperparameters: Hyperparameters = reader.read_hyperparameters()?;
+        let num_sizes: usize = reader.read_count()?;
+        let class_sizes: Vec<usize> = reader.read_sizes(num_sizes)?;
+
+        Ok(MixedNaiveBayes {
+            labels,
+            feature_types: (0..reader.read_count()?)
+                .map(|_| decode_feature_type(reader))
+                .collect::<Result<Vec<FeatureType>, ModelFileError>>()?,
+            features: (0..reader.read_count()?)
+                .map(|_| MixedFeature::decode_binary(reader))
+                .collect::<Result<Vec<MixedFeature>, ModelFileError>>()?,
+            class_sizes,
+            hyperparameters
+        })
+    }
+
+    fn to_pmml(&self) -> Result<PmmlModel, Box<dyn Error>> {
+        if self.features.is_empty() || self.features.iter().any(|f| !f.as_feature().is_trained()) {
+            return Err(Box::new(ModelError::UntrainedError));
+        }
+
+        // Every categorical feature is smoothed alike when trained
+        let smoothing: f64 = self.features.iter()
+            .find_map(|feature| match feature {
+                MixedFeature::Categorical(feature) => Some(feature.get_smoothing()),
+                MixedFeature::Gaussian(_) => None
+            })
+            .unwrap_or(self.hyperparameters.smoothing);
+
+        let inputs: Vec<BayesInput> = self.features.iter().zip(self.feature_types.iter())
+            .map(|(feature, kind)| match (feature, kind) {
+                (MixedFeature::Gaussian(feature), _) =>
+                    BayesInput::Gaussian { means: feature.means(), variances: feature.variances() },
+                (MixedFeature::Categorical(feature), kind) => {
+                    let counts: Vec<Vec<f64>> = feature.smoothed_counts();
+                    let mut values: Vec<String> = match kind {
+                        FeatureType::Nominal(values) => values.clone(),
+                        FeatureType::Numeric => Vec::new()
+                    };
+                    // Categories seen in training but never declared keep their index
+                    let num_categories: usize = counts.first().map_or(0, |c| c.len());
+                    values.extend((values.len()..num_categories).map(|idx| idx.to_string()));
+                    values.truncate(num_categories);
+
+                    BayesInput::Categorical { values, counts }
+                }
+            }).collect::<Vec<BayesInput>>();
+
+        Ok(PmmlModel {
+            labels: self.label_names(),
+            inputs,
+            class_counts: pmml::prior_counts(&self.class_sizes, &self.hyperparameters),
+            hyperparameters: Hyperparameters { smoothing, ..self.hyperparameters }
+        })
+    }
+
+    fn from_pmml(pmml: &PmmlModel) -> Result<MixedNaiveBayes, Box<dyn Error>> {
+        let class_sizes: Vec<usize> = pmml.class_sizes();
+        let smoothing: f64 = pmml.hyperparameters.smoothing;
+
+        let (feature_types, features): (Vec<FeatureType>, Vec<MixedFeature>) = pmml.inputs.iter()
+            .map(|input| match input {
+                BayesInput::Gaussian { means, variances } => (FeatureType::Numeric,
+                    MixedFeature::Gaussian(GaussianFeature::from_distributions(means, variances, &class_sizes))),
+                BayesInput::Categorical { values, counts } => (FeatureType::Nominal(values.clone()),
+                    MixedFeature::Categorical(DiscreteFeature::from_smoothed_counts(counts, smoothing)))
+            }).unzip();
+
+        Ok(MixedNaiveBayes {
+            labels: ClassLabel::from_names(&pmml.labels),
+            feature_types,
+            features,
+            class_sizes,
+            hyperparameters: pmml.hyperparameters
+        })
+    }
+
+    fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn label_names(&self) -> Vec<String> {
+        self.labels.iter()
+            .map(|label| label.get_name().to_string())
+            .collect::<Vec<String>>()
+    }
+
+    /// Only the Gaussian features count towards each class's zero-variance
+    /// features. Gaussian and categorical features are scored by different
+    /// measures, and ranked separately.
+    fn summary(&self) -> Result<ModelSummary, ModelError> {
+        if self.features.is_empty() || self.features.iter().any(|f| !f.as_feature().is_trained()) {
+            return Err(ModelError::UntrainedError);
+        }
+
+        let log_priors: Vec<f64> = self.hyperparameters.log_priors(&self.class_sizes);
+        let priors: Vec<f64> = inspection::priors(&log_priors);
+        let zero_variance: Vec<usize> = (0..self.labels.len())
+            .map(|class| self.features.iter()
+                .filter(|feature| match feature {
+                    MixedFeature::Gaussian(feature) =>
+                        feature.get_classifications()[class].has_zero_variance(),
+                    MixedFeature::Categorical(_) => false
+                })
+                .count())
+            .collect::<Vec<usize>>();
+        let scores: Vec<(ScoreMeasure, f64)> = self.features.iter()
+            .map(|feature| match feature {
+                MixedFeature::Gaussian(feature) => (ScoreMeasure::FStatistic, feature.f_statistic()),
+                MixedFeature::Categorical(feature) => (ScoreMeasure::Divergence, feature.divergence(&priors))
+            })
+            .collect::<Vec<(ScoreMeasure, f64)>>();
+        let has_gaussian: bool = self.features.iter()
+            .any(|feature| matches!(feature, MixedFeature::Gaussian(_)));
+
+        Ok(ModelSummary::new(ModelKind::Mixed, self.label_names(), &self.class_sizes, &log_priors,
+            if has_gaussian { Some(zero_variance) } else { None }, &scores))
+    }
+
+    fn get_hyperparameters(&self) -> &Hyperparameters {
+        &self.hyperparameters
+    }
+
+    fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters) {
+        self.hyperparameters = hyperparameters;
+    }
+
+    /// Trains on the dataset's declared feature types, treating every
+    /// feature as numeric when none are declared.
+    fn fit(&mut self, dataset: &Dataset) -> Result<(), Box<dyn Error>> {
+        if self.labels.is_empty() {
+            self.labels = ClassLabel::from_names(dataset.get_label_names());
+        }
+        let mapping: Vec<usize> = self.label_mapping(dataset.get_label_names())?;
+
+        self.feature_types = match dataset.get_feature_types() {
+            [] => vec![FeatureType::Numeric; dataset.num_features()],
+            types if types.len() == dataset.num_features() => types.to_vec(),
+            _ => return Err(Box::new(DatasetError::InconsistentRow))
+        };
+
+        let num_labels: usize = self.labels.len();
+        self.features = self.feature_types.iter().map(|kind| match kind {
+            FeatureType::Numeric => MixedFeature::Gaussian(GaussianFeature::new(num_labels)),
+            FeatureType::Nominal(values) =>
+                MixedFeature::Categorical(DiscreteFeature::new(num_labels, values.len()))
+        }).collect::<Vec<MixedFeature>>();
+
+        self.class_sizes = vec![0; num_labels];
+        for &label_index in dataset.get_labels().iter() {
+            self.class_sizes[mapping[label_index]] += 1;
+        }
+
+        eprintln!("Adding feature counts and distribution means.");
+        self.add_values(dataset, &mapping, 0);
+
+        eprintln!("Adding distribution standard deviations.");
+        self.add_values(dataset, &mapping, 1);
+
+        // Smooth the Gaussian variances as the Gaussian model does
+        let max_variance: f64 = self.features.iter()
+            .filter_map(|f| match f {
+                MixedFeature::Gaussian(feature) => Some(feature.total_variance()),
+                MixedFeature::Categorical(_) => None
+            })
+            .fold(0.0, f64::max);
+        let epsilon: f64 = self.hyperparameters.var_smoothing * max_variance;
+
+        for feature in self.features.iter_mut() {
+            match feature {
+                MixedFeature::Gaussian(feature) =>
+                    feature.configure_std(self.hyperparameters.min_std, epsilon),
+                MixedFeature::Categorical(feature) =>
+                    feature.configure(self.hyperparameters.smoothing)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scores every row on the calling thread; `multithreaded` is ignored.
+    fn predict(&self, dataset: &Dataset, _multithreaded: bool)
+            -> Result<Vec<Prediction>, ModelError> {
+        let mapping: Vec<usize> = self.label_mapping(dataset.get_label_names())?;
+
+        dataset.iter()
+            .map(|(label, sample)| Ok(Prediction::new(mapping[label], self.posteriors(sample)?)))
+            .collect::<Result<Vec<Prediction>, ModelError>>()
+    }
+
+    fn classify(&self, sample_features: &[f64]) -> Result<Box<dyn Label>, ModelError> {
+        self.label(prediction::argmax(&self.log_likelihoods(sample_features)?))
+    }
+
+    fn posteriors(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        Ok(prediction::normalize_log10(&self.log_likelihoods(sample_features)?))
+    }
+
+    /// Gaussian terms are computed in log space, so they stay finite far
+    /// from every class mean.
+    fn log_likelihood_terms(&self, sample_features: &[f64])
+            -> Result<(Vec<f64>, Vec<Vec<f64>>), ModelError> {
+        if self.features.is_empty() {
+            return Err(ModelError::UntrainedError);
+        }
+        if sample_features.len() != self.features.len() {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        let terms: Vec<Vec<f64>> = self.labels.iter().enumerate()
+            .map(|(class, label)| self.features.iter().zip(sample_features.iter())
+                .map(|(feature, &value)| match feature {
+                    _ if value.is_nan() => Ok(0.0),
+                    MixedFeature::Gaussian(feature) =>
+                        Ok(feature.get_classifications()[class].log10_pdf(value)),
+                    MixedFeature::Categorical(feature) =>
+                        feature.likelihood_given_class(value, label).map(f64::log10)
+                })
+                .collect::<Result<Vec<f64>, ModelError>>())
+            .collect::<Result<Vec<Vec<f64>>, ModelError>>()?;
+
+        Ok((self.hyperparameters.log_priors(&self.class_sizes), terms))
+    }
+
+    fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError> {
+        match self.labels.get(index) {
+            Some(label) => Ok(Box::new(label.clone())),
+            None => Err(ModelError::LabelNotFound)
+        }
+    }
+}
+
+/// Categorical features have no implicit value, so sparse rows are
+/// expanded one at a time rather than scored in place.
+impl SparseModel for MixedNaiveBayes {
+    fn fit_sparse(&mut self, dataset: &SparseDataset) -> Result<(), Box<dyn Error>> {
+        self.fit(&dataset.to_dense()?)
+    }
+
+    fn sparse_posteriors(&self, rows: &[SparseRow]) -> Result<Vec<Vec<f64>>, ModelError> {
+        rows.iter().map(|row| {
+            if row.width() > self.features.len() {
+                return Err(ModelError::FeatureCountMismatch);
+            }
+            self.posteriors(&row.to_dense(self.features.len()))
+        }).collect::<Result<Vec<Vec<f64>>, ModelError>>()
+    }
+}
+
+#[cfg(test)]
+mod mixed_nb_tests {
+    use crate::model::mixed_nb::*;
+    use crate::ml::arff::ArffFile;
+
+    #[test]
+    fn test_mixed_features_and_missing_values() {
+        let arff: ArffFile = ArffFile::parse("@relation r\n\
+            @attribute colour {red, green}\n\
+            @attribute size numeric\n\
+            @attribute class {apple, lime}\n\
+            @data\n\
+            red,7,apple\nred,8,apple\ngreen,7.5,apple\n\
+            green,3,lime\ngreen,?,lime\ngreen,4,lime\n").unwrap();
+        let dataset: Dataset = arff.to_dataset(None).unwrap();
+
+        let mut model: MixedNaiveBayes = MixedNaiveBayes::from_label_names(&[]);
+        model.fit(&dataset).unwrap();
+        assert_eq!(model.get_feature_types()[1], FeatureType::Numeric);
+
+        assert_eq!(model.classify(&[0.0, 3.5]).unwrap().get_name(), "lime");
+        assert_eq!(model.classify(&[0.0, f64::NAN]).unwrap().get_name(), "apple");
+
+        // With both values missing only the priors remain
+        let posteriors: Vec<f64> = model.posteriors(&[f64::NAN, f64::NAN]).unwrap();
+        assert_relative_eq!(posteriors[0], 0.5);
+
+        assert!(model.posteriors(&[2.0, 1.0]).is_err());
+    }
+}
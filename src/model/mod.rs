@@ -1 +1,67 @@
-pub mod gaussian_nb;
\ No newline at end of file
+extern crate serde;
+
+use self::serde::{Serialize, Deserialize};
+use std::{fmt, str::FromStr, string::String};
+
+pub mod gaussian_nb;
+pub mod multinomial_nb;
+pub mod bernoulli_nb;
+pub mod mixed_nb;
+
+/// The naive Bayes variants, named as on the command line.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    Gaussian,
+    Multinomial,
+    Bernoulli,
+    /// Gaussian and categorical features, as declared by the data.
+    Mixed
+}
+
+impl ModelKind {
+    /// The byte identifying the kind in binary model files.
+    pub fn code(&self) -> u8 {
+        match *self {
+            ModelKind::Gaussian => 0,
+            ModelKind::Multinomial => 1,
+            ModelKind::Bernoulli => 2,
+            ModelKind::Mixed => 3
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<ModelKind> {
+        match code {
+            0 => Some(ModelKind::Gaussian),
+            1 => Some(ModelKind::Multinomial),
+            2 => Some(ModelKind::Bernoulli),
+            3 => Some(ModelKind::Mixed),
+            _ => None
+        }
+    }
+}
+
+impl FromStr for ModelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ModelKind, String> {
+        match s {
+            "gaussian" => Ok(ModelKind::Gaussian),
+            "multinomial" => Ok(ModelKind::Multinomial),
+            "bernoulli" => Ok(ModelKind::Bernoulli),
+            "mixed" => Ok(ModelKind::Mixed),
+            _ => Err(format!("Unknown model kind '{}'.", s))
+        }
+    }
+}
+
+impl fmt::Display for ModelKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModelKind::Gaussian => write!(f, "gaussian"),
+            ModelKind::Multinomial => write!(f, "multinomial"),
+            ModelKind::Bernoulli => write!(f, "bernoulli"),
+            ModelKind::Mixed => write!(f, "mixed")
+        }
+    }
+}
@@ -0,0 +1,252 @@
+extern crate serde;
+
+use std::{error::Error, vec::Vec, string::String};
+
+use crate::ml::{model::{self, Model}, label::{self, Label}, error::{DatasetError, ModelError, ModelFileError}};
+use crate::ml::{dataset::Dataset, hyperparameters::Hyperparameters};
+use crate::ml::prediction::{self, Prediction};
+use crate::ml::inspection::{self, ModelSummary, ScoreMeasure};
+use crate::ml::sparse::{SparseDataset, SparseModel, SparseRow};
+use crate::ml::{binary::{self, BinaryReader, BinaryWriter, Compression}, envelope::ModelMetadata};
+use crate::model::ModelKind;
+use crate::naivebayes::class_label::ClassLabel;
+use crate::naivebayes::count_table::{self, CountTable};
+
+use self::serde::{Serialize, Deserialize};
+
+/// Naive Bayes over non-negative counts, such as word or click counts.
+/// Each class is a distribution over features, estimated from the class's
+/// feature totals plus the `smoothing` pseudo-count.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultinomialNaiveBayes {
+    labels: Vec<ClassLabel>,
+    counts: CountTable,
+    #[serde(default)]
+    hyperparameters: Hyperparameters
+}
+
+impl MultinomialNaiveBayes {
+    fn label_mapping(&self, label_names: &[String]) -> Result<Vec<usize>, ModelError> {
+        label::mapping(label_names, &self.label_names())
+            .map_err(|_| ModelError::LabelNotFound)
+    }
+
+    /// Log10 probability of each feature within each class.
+    fn log_probabilities(&self) -> Result<Vec<Vec<f64>>, ModelError> {
+        if !self.counts.is_trained() {
+            return Err(ModelError::UntrainedError);
+        }
+
+        let smoothing: f64 = self.hyperparameters.smoothing;
+        let num_features: f64 = self.counts.num_features() as f64;
+
+        Ok((0..self.labels.len()).map(|class| {
+            let totals: &[f64] = self.counts.get_totals(class);
+            let denominator: f64 = totals.iter().sum::<f64>() + smoothing * num_features;
+
+            totals.iter()
+                .map(|&total| ((total + smoothing) / denominator).log10())
+                .collect::<Vec<f64>>()
+        }).collect::<Vec<Vec<f64>>>())
+    }
+
+    fn log_priors(&self) -> Vec<f64> {
+        self.hyperparameters.log_priors(self.counts.get_class_sizes())
+    }
+}
+
+impl Model for MultinomialNaiveBayes {
+    fn from_labels(file_path: &str) -> MultinomialNaiveBayes {
+        MultinomialNaiveBayes {
+            labels: ClassLabel::from_file(file_path),
+            counts: CountTable::default(),
+            hyperparameters: Hyperparameters::default()
+        }
+    }
+
+    fn from_label_names(label_names: &[String]) -> MultinomialNaiveBayes {
+        MultinomialNaiveBayes {
+            labels: ClassLabel::from_names(label_names),
+            counts: CountTable::default(),
+            hyperparameters: Hyperparameters::default()
+        }
+    }
+
+    fn from_json(file_path: &str) -> MultinomialNaiveBayes {
+        model::read_json(file_path, ModelKind::Multinomial)
+    }
+
+    fn to_json(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        model::write_json(self, &ModelMetadata::new(ModelKind::Multinomial, self.hyperparameters), file_path)
+    }
+
+    fn kind() -> ModelKind {
+        ModelKind::Multinomial
+    }
+
+    fn from_binary(file_path: &str) -> MultinomialNaiveBayes {
+        binary::read_binary(file_path, ModelKind::Multinomial)
+    }
+
+    fn to_binary(&self, file_path: &str, compression: Compression) -> Result<(), Box<dyn Error>> {
+        let metadata: ModelMetadata = ModelMetadata::new(ModelKind::Multinomial, self.hyperparameters);
+        binary::write_binary(self, &metadata, file_path, compression)
+    }
+
+    fn encode_binary(&self, writer: &mut BinaryWriter) {
+        writer.write_labels(&self.labels);
+        writer.write_hyperparameters(&self.hyperparameters);
+        self.counts.encode_binary(writer);
+    }
+
+    fn decode_binary(reader: &mut BinaryReader) -> Result<MultinomialNaiveBayes, ModelFileError> {
+        Ok(MultinomialNaiveBayes {
+            labels: reader.read_labels()?,
+            hyperparameters: reader.read_hyperparameters()?,
+            counts: CountTable::decode_binary(reader)?
+        })
+    }
+
+    fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn label_names(&self) -> Vec<String> {
+        self.labels.iter()
+            .map(|label| label.get_name().to_string())
+            .collect::<Vec<String>>()
+    }
+
+    /// Each feature's divergence is its share of the divergence between
+    /// the classes' distributions over all the features.
+    fn summary(&self) -> Result<ModelSummary, ModelError> {
+        let probabilities: Vec<Vec<f64>> = self.log_probabilities()?;
+        let log_priors: Vec<f64> = self.log_priors();
+        let priors: Vec<f64> = inspection::priors(&log_priors);
+
+        let scores: Vec<(ScoreMeasure, f64)> = (0..self.counts.num_features())
+            .map(|idx| (ScoreMeasure::Divergence, inspection::pairwise_divergence(&priors, |a, b|
+                inspection::discrete_divergence(&[10f64.powf(probabilities[a][idx])],
+                    &[10f64.powf(probabilities[b][idx])]))))
+            .collect::<Vec<(ScoreMeasure, f64)>>();
+
+        Ok(ModelSummary::new(ModelKind::Multinomial, self.label_names(), self.counts.get_class_sizes(),
+            &log_priors, None, &scores))
+    }
+
+    fn get_hyperparameters(&self) -> &Hyperparameters {
+        &self.hyperparameters
+    }
+
+    fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters) {
+        self.hyperparameters = hyperparameters;
+    }
+
+    fn fit(&mut self, dataset: &Dataset) -> Result<(), Box<dyn Error>> {
+        if self.labels.is_empty() {
+            self.labels = ClassLabel::from_names(dataset.get_label_names());
+        }
+        let mapping: Vec<usize> = self.label_mapping(dataset.get_label_names())?;
+
+        let hyperparameters: Hyperparameters = self.hyperparameters;
+        self.counts = CountTable::from_dense(dataset, &mapping, self.labels.len(),
+            |value| hyperparameters.transform(value))?;
+
+        Ok(())
+    }
+
+    /// Scores every row on the calling thread; `multithreaded` is ignored.
+    fn predict(&self, dataset: &Dataset, _multithreaded: bool)
+            -> Result<Vec<Prediction>, ModelError> {
+        let mapping: Vec<usize> = self.label_mapping(dataset.get_label_names())?;
+
+        dataset.iter()
+            .map(|(label, sample)| Ok(Prediction::new(mapping[label], self.posteriors(sample)?)))
+            .collect::<Result<Vec<Prediction>, ModelError>>()
+    }
+
+    fn classify(&self, sample_features: &[f64]) -> Result<Box<dyn Label>, ModelError> {
+        self.label(prediction::argmax(&self.posteriors(sample_features)?))
+    }
+
+    fn posteriors(&self, sample_features: &[f64]) -> Result<Vec<f64>, ModelError> {
+        let log_probabilities: Vec<Vec<f64>> = self.log_probabilities()?;
+
+        count_table::dense_posteriors(sample_features, self.counts.num_features(),
+            &self.log_priors(), |class, idx, value|
+                self.hyperparameters.transform(value) * log_probabilities[class][idx])
+    }
+
+    fn log_likelihood_terms(&self, sample_features: &[f64])
+            -> Result<(Vec<f64>, Vec<Vec<f64>>), ModelError> {
+        let log_probabilities: Vec<Vec<f64>> = self.log_probabilities()?;
+
+        let terms: Vec<Vec<f64>> = count_table::dense_terms(sample_features, self.counts.num_features(),
+            self.labels.len(), |class, idx, value|
+                self.hyperparameters.transform(value) * log_probabilities[class][idx])?;
+        Ok((self.log_priors(), terms))
+    }
+
+    fn label(&self, index: usize) -> Result<Box<dyn Label>, ModelError> {
+        match self.labels.get(index) {
+            Some(label) => Ok(Box::new(label.clone())),
+            None => Err(ModelError::LabelNotFound)
+        }
+    }
+}
+
+impl SparseModel for MultinomialNaiveBayes {
+    fn fit_sparse(&mut self, dataset: &SparseDataset) -> Result<(), Box<dyn Error>> {
+        if !dataset.is_labelled() {
+            return Err(Box::new(DatasetError::MissingLabel));
+        }
+        if self.labels.is_empty() {
+            self.labels = ClassLabel::from_names(dataset.get_label_names());
+        }
+        let mapping: Vec<usize> = self.label_mapping(dataset.get_label_names())?;
+
+        let hyperparameters: Hyperparameters = self.hyperparameters;
+        self.counts = CountTable::from_sparse(dataset, &mapping, self.labels.len(),
+            |value| hyperparameters.transform(value))?;
+
+        Ok(())
+    }
+
+    fn sparse_posteriors(&self, rows: &[SparseRow]) -> Result<Vec<Vec<f64>>, ModelError> {
+        let log_probabilities: Vec<Vec<f64>> = self.log_probabilities()?;
+
+        count_table::sparse_posteriors(rows, self.counts.num_features(),
+            &self.log_priors(), |class, idx, value|
+                self.hyperparameters.transform(value) * log_probabilities[class][idx])
+    }
+}
+
+#[cfg(test)]
+mod multinomial_nb_tests {
+    use crate::model::multinomial_nb::*;
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let names: Vec<String> = vec![String::from("ham"), String::from("spam")];
+        let samples: Vec<Vec<f64>> = vec![
+            vec![3.0, 0.0, 1.0], vec![2.0, 1.0, 0.0], vec![0.0, 4.0, 0.0], vec![0.0, 2.0, 2.0]
+        ];
+        let dataset: Dataset = Dataset::with_label_names(names, vec![0, 0, 1, 1], samples);
+        let sparse: SparseDataset = SparseDataset::from_dense(&dataset);
+
+        let mut dense_model: MultinomialNaiveBayes = MultinomialNaiveBayes::from_label_names(&[]);
+        let mut sparse_model: MultinomialNaiveBayes = MultinomialNaiveBayes::from_label_names(&[]);
+        dense_model.fit(&dataset).unwrap();
+        sparse_model.fit_sparse(&sparse).unwrap();
+
+        let row: SparseRow = SparseRow::from_dense(&[1.0, 0.0, 0.0]);
+        let expected: Vec<f64> = dense_model.posteriors(&[1.0, 0.0, 0.0]).unwrap();
+        let actual: Vec<f64> = sparse_model.sparse_posteriors(&[row]).unwrap().remove(0);
+
+        // Most of the first feature's counts come from ham rows
+        assert!(expected[0] > expected[1]);
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            assert_relative_eq!(e, a, max_relative=1e-9);
+        }
+    }
+}
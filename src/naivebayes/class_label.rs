@@ -1,25 +1,64 @@
 extern crate serde;
 
 use self::serde::{Serialize, Deserialize};
-use crate::ml::label::Label;
+use crate::ml::label::{self, Label};
+use std::{fs, string::String};
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredClassLabel")]
 pub struct ClassLabel {
     index: usize,
-    ascii: u8
+    name: String
+}
+
+/// Accepts both the current named labels and the older ASCII-coded ones,
+/// whose name is the character for the stored code.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredClassLabel {
+    Named { index: usize, name: String },
+    Ascii { index: usize, ascii: u8 }
+}
+
+impl From<StoredClassLabel> for ClassLabel {
+    fn from(stored: StoredClassLabel) -> ClassLabel {
+        match stored {
+            StoredClassLabel::Named { index, name } => ClassLabel::new(index, name),
+            StoredClassLabel::Ascii { index, ascii } =>
+                ClassLabel::new(index, (ascii as char).to_string())
+        }
+    }
 }
 
 impl ClassLabel {
-    pub(crate) fn new(index: usize, ascii: u8) -> ClassLabel {
+    pub fn new(index: usize, name: String) -> ClassLabel {
         ClassLabel {
             index,
-            ascii
+            name
         }
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn get_ascii(&self) -> u8 {
-        self.ascii
+    /// Reads the labels listed in a labels file, numbered in index order.
+    pub fn from_file(file_path: &str) -> Vec<ClassLabel> {
+        let msg: String = format!("Unable to read labels file at {}", file_path);
+        let contents: String = fs::read_to_string(file_path).expect(&msg);
+
+        eprint!("Reading labels...");
+
+        let names: Vec<String> = label::parse_labels_file(&contents)
+            .unwrap_or_else(|e| panic!("Unable to parse labels file at {}: {}", file_path, e));
+        let labels: Vec<ClassLabel> = ClassLabel::from_names(&names);
+
+        eprintln!("done.");
+
+        labels
+    }
+
+    /// Builds labels whose indices follow the order of the names.
+    pub fn from_names(names: &[String]) -> Vec<ClassLabel> {
+        names.iter().enumerate()
+            .map(|(index, name)| ClassLabel::new(index, name.clone()))
+            .collect::<Vec<ClassLabel>>()
     }
 }
 
@@ -27,4 +66,8 @@ impl Label for ClassLabel {
     fn get_index(&self) -> usize {
         self.index
     }
-}
\ No newline at end of file
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+}
@@ -0,0 +1,217 @@
+extern crate serde;
+
+use crate::ml::{error::{DatasetError, ModelError, ModelFileError}, dataset::Dataset, prediction};
+use crate::ml::binary::{BinaryReader, BinaryWriter};
+use crate::ml::sparse::{self, SparseDataset, SparseRow};
+
+use self::serde::{Serialize, Deserialize};
+use std::vec::Vec;
+
+/// Per-class totals of each feature, the sufficient statistics of the
+/// multinomial and Bernoulli models. Missing (NaN) values add nothing to
+/// the totals and are counted separately.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct CountTable {
+    class_sizes: Vec<usize>,
+    totals: Vec<Vec<f64>>,
+    #[serde(default)]
+    missing: Vec<Vec<usize>>
+}
+
+impl CountTable {
+    pub(crate) fn new(num_labels: usize, num_features: usize) -> CountTable {
+        CountTable {
+            class_sizes: vec![0; num_labels],
+            totals: vec![vec![0.0; num_features]; num_labels],
+            missing: vec![vec![0; num_features]; num_labels]
+        }
+    }
+
+    /// Counts every dense row, after `value` maps each raw value to the
+    /// amount it adds to its feature's total.
+    pub(crate) fn from_dense<V: Fn(f64) -> f64>(dataset: &Dataset, mapping: &[usize],
+            num_labels: usize, value: V) -> Result<CountTable, DatasetError> {
+        let mut table: CountTable = CountTable::new(num_labels, dataset.num_features());
+
+        for (label, sample) in dataset.iter() {
+            let class: usize = mapping[label];
+            table.class_sizes[class] += 1;
+
+            for (idx, &raw) in sample.iter().enumerate() {
+                table.record(class, idx, raw, &value)?;
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Counts every sparse row, adding the value of an implicit zero once
+    /// for each row that leaves a feature out.
+    pub(crate) fn from_sparse<V: Fn(f64) -> f64>(dataset: &SparseDataset, mapping: &[usize],
+            num_labels: usize, value: V) -> Result<CountTable, DatasetError> {
+        let mut table: CountTable = CountTable::new(num_labels, dataset.num_features());
+
+        for (&label, row) in dataset.get_labels().iter().zip(dataset.get_rows()) {
+            let class: usize = mapping[label];
+            table.class_sizes[class] += 1;
+
+            for (idx, raw) in row.iter() {
+                table.record(class, idx, raw, &value)?;
+            }
+        }
+
+        let zero: f64 = value(0.0);
+        let nonzero: Vec<Vec<usize>> = sparse::nonzero_counts(dataset, mapping, num_labels);
+        for (class, counts) in nonzero.iter().enumerate() {
+            for (idx, &count) in counts.iter().enumerate() {
+                table.add(class, idx, zero, table.class_sizes[class] - count)?;
+            }
+        }
+
+        Ok(table)
+    }
+
+    fn record<V: Fn(f64) -> f64>(&mut self, class: usize, feature: usize, raw: f64, value: &V)
+            -> Result<(), DatasetError> {
+        if raw.is_nan() {
+            self.missing[class][feature] += 1;
+            return Ok(());
+        }
+
+        self.add(class, feature, value(raw), 1)
+    }
+
+    fn add(&mut self, class: usize, feature: usize, value: f64, count: usize)
+            -> Result<(), DatasetError> {
+        // Counts can only grow, so a negative value means the data is unsuitable
+        if value < 0.0 {
+            return Err(DatasetError::InvalidValue);
+        }
+
+        self.totals[class][feature] += count as f64 * value;
+        Ok(())
+    }
+
+    pub(crate) fn is_trained(&self) -> bool {
+        !self.class_sizes.is_empty()
+    }
+
+    pub(crate) fn num_features(&self) -> usize {
+        self.totals.first().map_or(0, |totals| totals.len())
+    }
+
+    pub(crate) fn get_class_sizes(&self) -> &[usize] {
+        &self.class_sizes
+    }
+
+    pub(crate) fn get_totals(&self, class: usize) -> &[f64] {
+        &self.totals[class]
+    }
+
+    /// The number of the class's rows in which the feature was observed.
+    pub(crate) fn observed(&self, class: usize, feature: usize) -> usize {
+        let missing: usize = self.missing.get(class)
+            .and_then(|counts| counts.get(feature))
+            .map_or(0, |&count| count);
+
+        self.class_sizes[class] - missing
+    }
+
+    /// Writes each class's totals and missing counts as one block each.
+    /// Tables saved before missing values were counted write no misses.
+    pub(crate) fn encode_binary(&self, writer: &mut BinaryWriter) {
+        let num_features: usize = self.num_features();
+        writer.write_usize(self.class_sizes.len());
+        writer.write_usize(num_features);
+        writer.write_sizes(&self.class_sizes);
+
+        for class in 0..self.class_sizes.len() {
+            writer.write_floats(&self.totals[class]);
+            writer.write_sizes(&(0..num_features)
+                .map(|feature| self.class_sizes[class] - self.observed(class, feature))
+                .collect::<Vec<usize>>());
+        }
+    }
+
+    pub(crate) fn decode_binary(reader: &mut BinaryReader) -> Result<CountTable, ModelFileError> {
+        let num_labels: usize = reader.read_count()?;
+        let num_features: usize = reader.read_usize()?;
+        let class_sizes: Vec<usize> = reader.read_sizes(num_labels)?;
+
+        let mut totals: Vec<Vec<f64>> = Vec::with_capacity(num_labels);
+        let mut missing: Vec<Vec<usize>> = Vec::with_capacity(num_labels);
+        for &class_size in class_sizes.iter() {
+            totals.push(reader.read_floats(num_features)?);
+            let class_missing: Vec<usize> = reader.read_sizes(num_features)?;
+            if class_missing.iter().any(|&count| count > class_size) {
+                return Err(ModelFileError::InvalidEncoding);
+            }
+            missing.push(class_missing);
+        }
+
+        Ok(CountTable {
+            class_sizes,
+            totals,
+            missing
+        })
+    }
+}
+
+/// Posteriors of dense samples when feature `f` with value `x` adds
+/// `term(class, f, x)` to the class's log10 likelihood. Missing (NaN)
+/// values add no term, marginalizing the feature out.
+pub(crate) fn dense_posteriors<T: Fn(usize, usize, f64) -> f64>(sample: &[f64],
+        num_features: usize, log_priors: &[f64], term: T) -> Result<Vec<f64>, ModelError> {
+    if sample.len() != num_features {
+        return Err(ModelError::FeatureCountMismatch);
+    }
+
+    let log_likelihoods: Vec<f64> = log_priors.iter().enumerate()
+        .map(|(class, &log_prior)| sample.iter().enumerate()
+            .filter(|(_, value)| !value.is_nan())
+            .fold(log_prior, |total, (idx, &value)| total + term(class, idx, value)))
+        .collect::<Vec<f64>>();
+
+    Ok(prediction::normalize_log10(&log_likelihoods))
+}
+
+/// The `term` of every feature of a dense sample under every class,
+/// indexed `[class][feature]`. Missing (NaN) values add no term.
+pub(crate) fn dense_terms<T: Fn(usize, usize, f64) -> f64>(sample: &[f64], num_features: usize,
+        num_labels: usize, term: T) -> Result<Vec<Vec<f64>>, ModelError> {
+    if sample.len() != num_features {
+        return Err(ModelError::FeatureCountMismatch);
+    }
+
+    Ok((0..num_labels)
+        .map(|class| sample.iter().enumerate()
+            .map(|(idx, &value)| if value.is_nan() { 0.0 } else { term(class, idx, value) })
+            .collect::<Vec<f64>>())
+        .collect::<Vec<Vec<f64>>>())
+}
+
+/// Posteriors of sparse rows for the same `term`, scoring an all-zero row
+/// once and correcting it for each stored entry. A stored NaN removes the
+/// feature's term.
+pub(crate) fn sparse_posteriors<T: Fn(usize, usize, f64) -> f64>(rows: &[SparseRow],
+        num_features: usize, log_priors: &[f64], term: T) -> Result<Vec<Vec<f64>>, ModelError> {
+    let baselines: Vec<f64> = log_priors.iter().enumerate()
+        .map(|(class, &log_prior)| (0..num_features)
+            .fold(log_prior, |total, idx| total + term(class, idx, 0.0)))
+        .collect::<Vec<f64>>();
+
+    rows.iter().map(|row| {
+        if row.width() > num_features {
+            return Err(ModelError::FeatureCountMismatch);
+        }
+
+        let log_likelihoods: Vec<f64> = baselines.iter().enumerate()
+            .map(|(class, &baseline)| row.iter().fold(baseline, |total, (idx, value)| {
+                let stored: f64 = if value.is_nan() { 0.0 } else { term(class, idx, value) };
+                total + stored - term(class, idx, 0.0)
+            }))
+            .collect::<Vec<f64>>();
+
+        Ok(prediction::normalize_log10(&log_likelihoods))
+    }).collect::<Result<Vec<Vec<f64>>, ModelError>>()
+}
@@ -1,44 +1,73 @@
 extern crate serde;
 
-use crate::ml::error::ModelError;
 use self::serde::{Serialize, Deserialize};
-use std::{string::String, vec::Vec};
+use std::vec::Vec;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// Occurrences of each category of a feature within one class.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct DiscreteClassification {
     sample_size: usize,
-    occurrences: Vec<usize>,
-    classes: Vec<String>
+    occurrences: Vec<usize>
 }
 
 impl DiscreteClassification {
-    pub(crate) fn add_occurrence(&mut self, sample: &String) {
-        let iter = self.classes.iter().zip(self.occurrences.iter_mut());
-        self.sample_size += 1;
+    pub(crate) fn new(num_categories: usize) -> DiscreteClassification {
+        DiscreteClassification {
+            sample_size: 0,
+            occurrences: vec![0; num_categories]
+        }
+    }
+
+    pub(crate) fn from_occurrences(occurrences: Vec<usize>) -> DiscreteClassification {
+        DiscreteClassification {
+            sample_size: occurrences.iter().sum(),
+            occurrences
+        }
+    }
 
-        for (class, occurrence) in iter {
-            if class == sample {
-                *occurrence += 1;
-                break;
-            }
+    pub(crate) fn add_occurrence(&mut self, category: usize) {
+        if category >= self.occurrences.len() {
+            self.occurrences.resize(category + 1, 0);
         }
 
-        self.classes.push(sample.to_string());
-        self.occurrences.push(1);
+        self.occurrences[category] += 1;
+        self.sample_size += 1;
     }
 
-    pub(crate) fn get_num_classes(&self) -> usize {
-        self.classes.len()
+    pub(crate) fn get_sample_size(&self) -> usize {
+        self.sample_size
     }
 
-    pub(crate) fn get_class_occurrences(&self, sample: &String) 
-            -> Result<usize, ModelError> {
-        for (idx, class) in self.classes.iter().enumerate() {
-            if class == sample {
-                return Ok(self.occurrences[idx]);
-            }
+    /// Occurrences of every category, in category order.
+    pub(crate) fn get_occurrence_counts(&self) -> &[usize] {
+        &self.occurrences
+    }
+
+    pub(crate) fn get_occurrences(&self, category: usize) -> usize {
+        self.occurrences.get(category).copied().unwrap_or(0)
+    }
+
+    /// Probability of the category, Laplace smoothed by `smoothing` over
+    /// `num_categories` categories.
+    pub(crate) fn probability(&self, category: usize, num_categories: usize, smoothing: f64) -> f64 {
+        (self.get_occurrences(category) as f64 + smoothing) 
+            / (self.sample_size as f64 + smoothing * num_categories as f64)
+    }
+}
+
+#[cfg(test)]
+mod discrete_classification_tests {
+    use crate::naivebayes::discrete_classification::*;
+
+    #[test]
+    fn test_smoothed_probability() {
+        let mut dc: DiscreteClassification = DiscreteClassification::new(3);
+        for category in [0, 0, 2].iter() {
+            dc.add_occurrence(*category);
         }
 
-        Err(ModelError::FeatureNotFound)
+        assert_eq!(dc.get_occurrences(0), 2);
+        assert_relative_eq!(dc.probability(0, 3, 0.0), 2.0 / 3.0);
+        assert_relative_eq!(dc.probability(1, 3, 1.0), 1.0 / 6.0);
     }
 }
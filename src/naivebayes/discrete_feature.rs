@@ -1,21 +1,116 @@
-extern crate num_traits;
 extern crate serde;
 
 use crate::naivebayes::discrete_classification::DiscreteClassification;
-use crate::ml::{feature::Feature, label::Label, error::ModelError};
+use crate::ml::{feature::Feature, label::Label, error::{ModelError, ModelFileError}, inspection};
+use crate::ml::binary::{BinaryReader, BinaryWriter};
 
-use std::{string::String, vec::Vec, str::FromStr};
+use std::vec::Vec;
 use self::serde::{Serialize, Deserialize};
-use num_traits::ToPrimitive;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A categorical feature whose values are category indices.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DiscreteFeature {
     is_trained: bool,
     sample_size: usize,
+    num_categories: usize,
+    #[serde(default)]
+    smoothing: f64,
     classifications: Vec<DiscreteClassification>
 }
 
 impl DiscreteFeature {
+    pub(crate) fn new(count: usize, num_categories: usize) -> DiscreteFeature {
+        DiscreteFeature {
+            is_trained: false,
+            sample_size: 0,
+            num_categories,
+            smoothing: 0.0,
+            classifications: (0..count)
+                .map(|_| DiscreteClassification::new(num_categories))
+                .collect::<Vec<DiscreteClassification>>()
+        }
+    }
+
+    /// A trained feature from counts already smoothed by `smoothing`,
+    /// indexed `[class][category]`.
+    pub(crate) fn from_smoothed_counts(counts: &[Vec<f64>], smoothing: f64) -> DiscreteFeature {
+        let classifications: Vec<DiscreteClassification> = counts.iter()
+            .map(|class_counts| DiscreteClassification::from_occurrences(class_counts.iter()
+                .map(|&count| (count - smoothing).round().max(0.0) as usize)
+                .collect::<Vec<usize>>()))
+            .collect::<Vec<DiscreteClassification>>();
+
+        DiscreteFeature {
+            is_trained: true,
+            sample_size: classifications.iter().map(|c| c.get_sample_size()).sum(),
+            num_categories: counts.first().map_or(0, |class_counts| class_counts.len()),
+            smoothing,
+            classifications
+        }
+    }
+
+    /// Occurrences of every category in every class plus the smoothing,
+    /// indexed `[class][category]`.
+    pub(crate) fn smoothed_counts(&self) -> Vec<Vec<f64>> {
+        self.classifications.iter()
+            .map(|class| (0..self.num_categories)
+                .map(|category| class.get_occurrences(category) as f64 + self.smoothing)
+                .collect::<Vec<f64>>())
+            .collect::<Vec<Vec<f64>>>()
+    }
+
+    /// Symmetric KL divergence between the classes' distributions over the
+    /// categories, combined by `inspection::pairwise_divergence`.
+    pub(crate) fn divergence(&self, priors: &[f64]) -> f64 {
+        let distributions: Vec<Vec<f64>> = self.smoothed_counts().iter()
+            .map(|counts| {
+                let total: f64 = counts.iter().sum();
+                counts.iter().map(|&count| count / total).collect::<Vec<f64>>()
+            })
+            .collect::<Vec<Vec<f64>>>();
+
+        inspection::pairwise_divergence(priors, |a, b|
+            inspection::discrete_divergence(&distributions[a], &distributions[b]))
+    }
+
+    pub(crate) fn get_smoothing(&self) -> f64 {
+        self.smoothing
+    }
+
+    /// Writes the feature with each class's occurrences as one block.
+    pub(crate) fn encode_binary(&self, writer: &mut BinaryWriter) {
+        writer.write_bool(self.is_trained);
+        writer.write_usize(self.sample_size);
+        writer.write_usize(self.num_categories);
+        writer.write_f64(self.smoothing);
+        writer.write_usize(self.classifications.len());
+        for class in self.classifications.iter() {
+            writer.write_usize(class.get_occurrence_counts().len());
+            writer.write_sizes(class.get_occurrence_counts());
+        }
+    }
+
+    pub(crate) fn decode_binary(reader: &mut BinaryReader) -> Result<DiscreteFeature, ModelFileError> {
+        let is_trained: bool = reader.read_bool()?;
+        let sample_size: usize = reader.read_usize()?;
+        let num_categories: usize = reader.read_usize()?;
+        let smoothing: f64 = reader.read_f64()?;
+        let classifications: Vec<DiscreteClassification> = (0..reader.read_count()?)
+            .map(|_| {
+                let len: usize = reader.read_count()?;
+                Ok(DiscreteClassification::from_occurrences(reader.read_sizes(len)?))
+            })
+            .collect::<Result<Vec<DiscreteClassification>, ModelFileError>>()?;
+
+        Ok(DiscreteFeature {
+            is_trained,
+            sample_size,
+            num_categories,
+            smoothing,
+            classifications
+        })
+    }
+
     fn get_class(&self, label: &dyn Label) -> &DiscreteClassification {
         &self.classifications[label.get_index()]
     }
@@ -23,45 +118,51 @@ impl DiscreteFeature {
     fn get_class_mut(&mut self, label: &dyn Label) -> &mut DiscreteClassification {
         &mut self.classifications[label.get_index()]
     }
+
+    /// Finishes training, Laplace smoothing every category count by
+    /// `smoothing`.
+    pub(crate) fn configure(&mut self, smoothing: f64) {
+        self.smoothing = smoothing;
+        self.is_trained = true;
+    }
 }
 
 impl Feature for DiscreteFeature {
-    fn train_iter<Num: ToPrimitive + Copy + FromStr>
-            (&mut self, label: &dyn Label, value: &String, iter: usize) {
-        match iter {
-            0 => {
-                // self.get_class_mut(label).add_occurrence(value);
-                // let class: &mut GaussianClassification = 
-                //     &mut self.classifications[label.get_index()];
-                
-                // class.add_value_for_mean(value);
-                // self.sample_size += 1;
-            },
-            1 => {
-                // let class: &mut GaussianClassification = 
-                //     &mut self.classifications[label.get_index()];
-                // class.add_value_for_std(value);
-
-            },
-            _ => {}
+    fn train_iter(&mut self, label: &dyn Label, value: f64, iter: usize) {
+        // Every observed occurrence is counted on the first pass
+        if iter == 0 && !value.is_nan() {
+            self.get_class_mut(label).add_occurrence(value as usize);
+            self.sample_size += 1;
+            self.num_categories = self.num_categories.max(value as usize + 1);
         }
     }
 
     fn prepare(&mut self) {
-        self.is_trained = true
+        self.configure(1.0);
     }
 
     fn is_trained(&self) -> bool {
         self.is_trained
     }
 
-    fn likelihood_given_class<Num: ToPrimitive + Copy + FromStr>
-        (&self, feature: &String, class: &dyn Label) 
+    fn likelihood_given_class(&self, feature: f64, class: &dyn Label) 
             -> Result<f64, ModelError> {
-        Ok(0.0)
+        if !self.is_trained() {
+            return Err(ModelError::UntrainedError);
+        }
+        if feature < 0.0 || feature as usize >= self.num_categories {
+            return Err(ModelError::FeatureNotFound);
+        }
+
+        Ok(self.get_class(class).probability(feature as usize, self.num_categories, self.smoothing))
     }
 
     fn class_likelihood(&self, class: &dyn Label) -> Result<f64, ModelError> {
-        Ok(0.0)
+        if !self.is_trained() {
+            return Err(ModelError::UntrainedError);
+        }
+
+        let class_size: f64 = self.get_class(class).get_sample_size() as f64;
+        Ok(class_size / self.sample_size as f64)
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,10 @@
 extern crate num_traits;
 extern crate serde;
 
+use crate::ml::{binary::{BinaryReader, BinaryWriter}, error::ModelFileError};
 use self::serde::{Serialize, Deserialize};
 use self::num_traits::ToPrimitive;
-use std::f64::consts::PI;
-use std::vec::Vec;
-
-static MIN_STD: f64 = 1e-10;
+use std::f64::consts::{LN_10, PI};
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct GaussianClassification {
@@ -16,56 +14,39 @@ pub struct GaussianClassification {
     square_mean_diffs: f64
 }
 
-#[allow(dead_code)]
-fn mean<Num: ToPrimitive + Copy>(features: &Vec<Num>) -> f64 {
-    features.iter().enumerate().fold(0.0, |avg, (i, &x)|
-        {
-            let value: f64 = match x.to_f64() {
-                None => avg,
-                Some(n) => n
-            };
-
-            avg + ((value - avg) / (i + 1) as f64)
-        })
+/// Density of the normal distribution with the given mean and standard
+/// deviation at `x`.
+pub(crate) fn normal_pdf(x: f64, mean: f64, std: f64) -> f64 {
+    let multiplier: f64 = 1.0 / (std * (2.0 * PI).sqrt());
+    let exponent: f64 = -0.5 * ((x - mean) / std).powf(2.0);
+
+    multiplier * exponent.exp()
 }
 
-#[allow(dead_code)]
-fn std<Num: ToPrimitive + Copy>(features: &Vec<Num>) -> f64 {
-    let mut sample_size: usize = features.len();
-    let mean: f64 = mean(features);
-
-    let sum: f64 = features
-        .iter()
-        .map(|x: &Num| {
-            match x.to_f64() {
-                None => {
-                    sample_size -= 1;
-                    0.0
-                }
-                Some(n) => (n - mean).powf(2.0)
-            }
-        })
-        .sum::<f64>();
-
-    (sum / sample_size as f64).sqrt()
+/// Writes the distributions as four blocks of one value each: their means,
+/// standard deviations, squared differences and sample sizes.
+pub(crate) fn encode_blocks(classifications: &[&GaussianClassification], writer: &mut BinaryWriter) {
+    writer.write_floats(&classifications.iter().map(|c| c.mean).collect::<Vec<f64>>());
+    writer.write_floats(&classifications.iter().map(|c| c.std).collect::<Vec<f64>>());
+    writer.write_floats(&classifications.iter().map(|c| c.square_mean_diffs).collect::<Vec<f64>>());
+    writer.write_sizes(&classifications.iter().map(|c| c.sample_size).collect::<Vec<usize>>());
 }
 
-impl GaussianClassification {
-    pub(crate) fn merge(a: &GaussianClassification, b: &GaussianClassification)
-            -> GaussianClassification {
-        
-        let a_sum: f64 = a.sample_size as f64 * a.mean;
-        let b_sum: f64 = b.sample_size as f64 * b.mean;
-        let new_mean: f64 = (a_sum + b_sum) / (a.sample_size + b.sample_size) as f64;
-                                
-        GaussianClassification {
-            mean: new_mean,
-            std: 0.0,
-            sample_size: a.sample_size + b.sample_size,
-            square_mean_diffs: a.square_mean_diffs + b.square_mean_diffs
-        }
-    }
+/// Reads `len` distributions written by `encode_blocks`.
+pub(crate) fn decode_blocks(len: usize, reader: &mut BinaryReader)
+        -> Result<Vec<GaussianClassification>, ModelFileError> {
+    let means: Vec<f64> = reader.read_floats(len)?;
+    let stds: Vec<f64> = reader.read_floats(len)?;
+    let square_mean_diffs: Vec<f64> = reader.read_floats(len)?;
+    let sample_sizes: Vec<usize> = reader.read_sizes(len)?;
+
+    Ok((0..len)
+        .map(|idx| GaussianClassification::from_statistics(means[idx], stds[idx], sample_sizes[idx],
+            square_mean_diffs[idx]))
+        .collect::<Vec<GaussianClassification>>())
+}
 
+impl GaussianClassification {
     pub(crate) fn new() -> GaussianClassification {
         GaussianClassification {
             mean: 0.0,
@@ -75,8 +56,8 @@ impl GaussianClassification {
         }
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn create<Num: ToPrimitive + Copy>(mean: f64, std: f64) -> GaussianClassification {
+    #[cfg(test)]
+    pub(crate) fn create(mean: f64, std: f64) -> GaussianClassification {
         GaussianClassification {
             mean,
             std,
@@ -89,57 +70,119 @@ impl GaussianClassification {
         self.sample_size
     }
 
+    /// Adds a value to the running mean. Missing values, those that are
+    /// NaN or cannot be widened to `f64`, are left out of the statistics.
     pub(crate) fn add_value_for_mean<Num: ToPrimitive + Copy>(&mut self, value: Num) {
         match value.to_f64() {
-            None => {
-                println!("Could not parse feature");
-            },
-            Some(n) => {
+            Some(n) if !n.is_nan() => {
                 self.sample_size += 1;
                 self.mean = self.mean + ((n - self.mean) / (self.sample_size) as f64);
-            }
+            },
+            _ => {}
         };
     }
 
     pub(crate) fn add_value_for_std<Num: ToPrimitive + Copy>(&mut self, value: Num) {
         match value.to_f64() {
-            None => {
-                println!("Could not parse feature");
-            },
-            Some(n) => {
+            Some(n) if !n.is_nan() => {
                 self.square_mean_diffs += (n - self.mean).powf(2.0);
-            }
+            },
+            _ => {}
+        }
+    }
+
+    /// Adds `count` copies of `value` to the mean, as one update.
+    pub(crate) fn add_repeated_for_mean(&mut self, value: f64, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        self.sample_size += count;
+        self.mean += count as f64 * (value - self.mean) / self.sample_size as f64;
+    }
+
+    /// Adds `count` copies of `value` to the squared differences.
+    pub(crate) fn add_repeated_for_std(&mut self, value: f64, count: usize) {
+        self.square_mean_diffs += count as f64 * (value - self.mean).powf(2.0);
+    }
+
+    /// A trained distribution with the given parameters, as imported from
+    /// another format.
+    pub(crate) fn from_parameters(mean: f64, std: f64, sample_size: usize) -> GaussianClassification {
+        GaussianClassification {
+            mean,
+            std,
+            sample_size,
+            square_mean_diffs: std * std * sample_size as f64
+        }
+    }
+
+    /// A distribution with every statistic given, as read back from a
+    /// binary model.
+    pub(crate) fn from_statistics(mean: f64, std: f64, sample_size: usize, square_mean_diffs: f64)
+            -> GaussianClassification {
+        GaussianClassification {
+            mean,
+            std,
+            sample_size,
+            square_mean_diffs
         }
     }
 
-    pub(crate) fn configure_std(&mut self) {
-        let std_dev: f64 = 
-            (self.square_mean_diffs / (self.sample_size - 1) as f64).sqrt();
+    pub(crate) fn get_mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub(crate) fn get_std(&self) -> f64 {
+        self.std
+    }
+
+    pub(crate) fn get_square_mean_diffs(&self) -> f64 {
+        self.square_mean_diffs
+    }
+
+    /// Whether every training value was the same, leaving the standard
+    /// deviation to smoothing and the `min_std` floor.
+    pub(crate) fn has_zero_variance(&self) -> bool {
+        self.sample_size < 2 || self.square_mean_diffs == 0.0
+    }
+
+    /// Sets the standard deviation from the accumulated squared differences,
+    /// adding `epsilon` to the variance before applying the `min_std` floor.
+    pub(crate) fn configure_std(&mut self, min_std: f64, epsilon: f64) {
+        // A class seen fewer than twice has no spread to estimate
+        let variance: f64 = if self.sample_size < 2 {
+            0.0
+        } else {
+            self.square_mean_diffs / (self.sample_size - 1) as f64
+        };
+
+        let std_dev: f64 = (variance + epsilon).sqrt();
         
-        self.std = if std_dev < MIN_STD { MIN_STD } else { std_dev };
+        self.std = if std_dev < min_std { min_std } else { std_dev };
+    }
+
+    /// Log10 of the density at `x`, computed in log space so it stays finite
+    /// far from the mean, where the density itself underflows to 0.
+    pub(crate) fn log10_pdf(&self, x: f64) -> f64 {
+        -(self.std * (2.0 * PI).sqrt()).log10() - 0.5 * ((x - self.mean) / self.std).powi(2) / LN_10
     }
 
     pub(crate) fn pdf<Num: ToPrimitive>(&self, x: Num) -> f64 {
         match x.to_f64() {
             None => 0.0,
-            Some(n) => {
-                let multiplier: f64 = 1.0 / (self.std * (2.0 * PI).sqrt());
-                let exponent: f64 = -0.5 * ((n - self.mean) / self.std).powf(2.0);
-
-                multiplier * exponent.exp()
-            }
+            Some(n) => normal_pdf(n, self.mean, self.std)
         }
     }
 }
 
 #[cfg(test)]
-#[allow(dead_code)]
 mod gaussian_classification_tests {
     use crate::naivebayes::gaussian_classification::GaussianClassification;
 
     #[test]
     fn test_standard_normal_distribution() {
-        let gc: GaussianClassification = GaussianClassification::create::<f64>(0.0, 1.0);
+        let gc: GaussianClassification = GaussianClassification::create(0.0, 1.0);
         
         assert_relative_eq!(gc.pdf(0.0), 0.39894228, max_relative=1.0);
 
@@ -158,9 +201,52 @@ mod gaussian_classification_tests {
 
     #[test]
     fn test_arbitrary_normal_distribution() {
-        let gc: GaussianClassification = GaussianClassification::create::<f64>(0.0, 1.0);
+        let gc: GaussianClassification = GaussianClassification::create(0.0, 1.0);
 
         assert_relative_eq!(gc.pdf(2.5), 0.0175283, max_relative=1.0);
         assert_relative_eq!(gc.pdf(-2.5), 0.0175283, max_relative=1.0);
     }
+
+    #[test]
+    fn test_configure_std_smoothing() {
+        let mut gc: GaussianClassification = GaussianClassification::new();
+        for value in [2.0, 2.0, 2.0].iter() {
+            gc.add_value_for_mean(*value);
+        }
+        for value in [2.0, 2.0, 2.0].iter() {
+            gc.add_value_for_std(*value);
+        }
+
+        gc.configure_std(1e-10, 0.0);
+        assert_relative_eq!(gc.std, 1e-10);
+
+        gc.configure_std(1e-10, 0.25);
+        assert_relative_eq!(gc.std, 0.5);
+        assert_relative_eq!(gc.pdf(2.0), 0.79788456, max_relative=1e-6);
+    }
+
+    #[test]
+    fn test_repeated_values_match_individual() {
+        let mut individual: GaussianClassification = GaussianClassification::new();
+        let mut repeated: GaussianClassification = GaussianClassification::new();
+        let values: [f64; 5] = [3.0, 1.0, 0.0, 0.0, 0.0];
+
+        for value in values.iter() {
+            individual.add_value_for_mean(*value);
+        }
+        for value in values.iter() {
+            individual.add_value_for_std(*value);
+        }
+
+        repeated.add_value_for_mean(3.0);
+        repeated.add_value_for_mean(1.0);
+        repeated.add_repeated_for_mean(0.0, 3);
+        repeated.add_value_for_std(3.0);
+        repeated.add_value_for_std(1.0);
+        repeated.add_repeated_for_std(0.0, 3);
+
+        assert_eq!(repeated.sample_size, 5);
+        assert_relative_eq!(repeated.mean, individual.mean, max_relative=1e-12);
+        assert_relative_eq!(repeated.square_mean_diffs, individual.square_mean_diffs, max_relative=1e-12);
+    }
 }
@@ -1,12 +1,12 @@
-extern crate num_traits;
 extern crate serde;
 
-use crate::naivebayes::gaussian_classification::GaussianClassification;
-use crate::ml::{feature::Feature, label::Label, error::ModelError};
+use crate::naivebayes::gaussian_classification::{self, GaussianClassification};
+use crate::ml::{feature::Feature, label::Label, error::{ModelError, ModelFileError}};
+use crate::ml::binary::{BinaryReader, BinaryWriter};
+use crate::ml::{hyperparameters::MIN_STD, inspection};
 
 use self::serde::{Serialize, Deserialize};
-use self::num_traits::ToPrimitive;
-use std::{str::FromStr, vec::Vec};
+use std::vec::Vec;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GaussianFeature {
@@ -16,19 +16,6 @@ pub struct GaussianFeature {
 }
 
 impl GaussianFeature {
-    pub(crate) fn merge(a: &GaussianFeature, b: &GaussianFeature) 
-            -> GaussianFeature {
-        let (av, bv) = (&a.classifications, &b.classifications);
-
-        GaussianFeature {
-            is_trained: false,
-            sample_size: a.sample_size + b.sample_size,
-            classifications: (0..a.classifications.len())
-                .map(|idx| GaussianClassification::merge(&av[idx], &bv[idx]))
-                .collect::<Vec<GaussianClassification>>()
-        } 
-    }
-
     pub(crate) fn new(count: usize) -> GaussianFeature {
         GaussianFeature {
             is_trained: false,
@@ -47,75 +34,159 @@ impl GaussianFeature {
         &mut self.classifications[label.get_index()]
     }
 
-    /* pub(crate) fn add_value_for_mean<Num: ToPrimitive + Copy>
-            (&mut self, label: &dyn Label, value: Num) {
-        let class: &mut GaussianClassification = 
-            &mut self.classifications[label.get_index()];
-        class.add_value_for_mean(value);
-        self.sample_size += 1;
+    /// A trained feature with the given per-class means and variances.
+    pub(crate) fn from_distributions(means: &[f64], variances: &[f64], class_sizes: &[usize])
+            -> GaussianFeature {
+        GaussianFeature {
+            is_trained: true,
+            sample_size: class_sizes.iter().sum(),
+            classifications: means.iter().zip(variances.iter()).zip(class_sizes.iter())
+                .map(|((&mean, &variance), &size)|
+                    GaussianClassification::from_parameters(mean, variance.sqrt(), size))
+                .collect::<Vec<GaussianClassification>>()
+        }
+    }
+
+    pub(crate) fn means(&self) -> Vec<f64> {
+        self.classifications.iter().map(|c| c.get_mean()).collect::<Vec<f64>>()
+    }
+
+    pub(crate) fn variances(&self) -> Vec<f64> {
+        self.classifications.iter().map(|c| c.get_std().powi(2)).collect::<Vec<f64>>()
+    }
+
+    /// The ANOVA F statistic of the class means, from the training
+    /// statistics rather than the smoothed deviations.
+    pub(crate) fn f_statistic(&self) -> f64 {
+        let sizes: Vec<usize> = self.classifications.iter().map(|c| c.get_sample_size()).collect::<Vec<usize>>();
+        let square_diffs: Vec<f64> = self.classifications.iter()
+            .map(|c| c.get_square_mean_diffs())
+            .collect::<Vec<f64>>();
+
+        inspection::f_statistic(&sizes, &self.means(), &square_diffs)
+    }
+
+    pub(crate) fn get_classifications(&self) -> &[GaussianClassification] {
+        &self.classifications
+    }
+
+    pub(crate) fn get_sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    /// A feature with the given per-class distributions, as read back from
+    /// a binary model.
+    pub(crate) fn from_classifications(is_trained: bool, sample_size: usize,
+            classifications: Vec<GaussianClassification>) -> GaussianFeature {
+        GaussianFeature {
+            is_trained,
+            sample_size,
+            classifications
+        }
+    }
+
+    pub(crate) fn encode_binary(&self, writer: &mut BinaryWriter) {
+        writer.write_bool(self.is_trained);
+        writer.write_usize(self.sample_size);
+        writer.write_usize(self.classifications.len());
+        let classifications: Vec<&GaussianClassification> = self.classifications.iter()
+            .collect::<Vec<&GaussianClassification>>();
+        gaussian_classification::encode_blocks(&classifications, writer);
     }
 
-    pub(crate) fn add_value_for_std<Num: ToPrimitive + Copy>
-            (&mut self, label: &dyn Label, value: Num) {
-        let class: &mut GaussianClassification = 
-            &mut self.classifications[label.get_index()];
-        class.add_value_for_std(value);
-    } 
+    pub(crate) fn decode_binary(reader: &mut BinaryReader) -> Result<GaussianFeature, ModelFileError> {
+        let is_trained: bool = reader.read_bool()?;
+        let sample_size: usize = reader.read_usize()?;
+        let num_classes: usize = reader.read_count()?;
 
-    pub(crate) fn configure_std(&mut self) {
+        Ok(GaussianFeature::from_classifications(is_trained, sample_size,
+            gaussian_classification::decode_blocks(num_classes, reader)?))
+    }
+
+    pub(crate) fn get_class_sample_size(&self, label: &dyn Label) -> usize {
+        self.get_class(label).get_sample_size()
+    }
+
+    /// Population variance of the feature across every class, pooled from
+    /// the per-class means and squared differences.
+    pub(crate) fn total_variance(&self) -> f64 {
+        let total: usize = self.classifications.iter().map(|c| c.get_sample_size()).sum();
+        if total == 0 {
+            return 0.0;
+        }
+
+        let mean: f64 = self.classifications.iter()
+            .map(|c| c.get_sample_size() as f64 * c.get_mean())
+            .sum::<f64>() / total as f64;
+
+        let square_diffs: f64 = self.classifications.iter()
+            .map(|c| c.get_square_mean_diffs() 
+                + c.get_sample_size() as f64 * (c.get_mean() - mean).powi(2))
+            .sum::<f64>();
+
+        square_diffs / total as f64
+    }
+
+    /// Trains on `count` occurrences of the same value, as sparse data does
+    /// for the zeros it leaves out.
+    pub(crate) fn train_repeated(&mut self, label: &dyn Label, value: f64, count: usize,
+            iter: usize) {
+        match iter {
+            0 => {
+                self.get_class_mut(label).add_repeated_for_mean(value, count);
+                self.sample_size += count;
+            },
+            1 => {
+                self.get_class_mut(label).add_repeated_for_std(value, count);
+            },
+            _ => {}
+        }
+    }
+
+    pub(crate) fn configure_std(&mut self, min_std: f64, epsilon: f64) {
         for class in self.classifications.iter_mut() {
-            class.configure_std();
+            class.configure_std(min_std, epsilon);
         }
 
         self.is_trained = true;
-    } */
+    }
 }
 
 impl Feature for GaussianFeature {
-    fn train_iter<Num: ToPrimitive + Copy + FromStr>
-            (&mut self, label: &dyn Label, value: &String, iter: usize) {
-        let converted: Num = match value.parse::<Num>() {
-            Ok(val) => val,
-            Err(_) => panic!("Could not parse record.")
-        };
-        
+    /// Missing (NaN) values are skipped, so each class's statistics only
+    /// cover the rows where the feature was observed.
+    fn train_iter(&mut self, label: &dyn Label, value: f64, iter: usize) {
+        if value.is_nan() {
+            return;
+        }
+
         match iter {
             0 => {
-                self.get_class_mut(label).add_value_for_mean(converted);
+                self.get_class_mut(label).add_value_for_mean(value);
                 self.sample_size += 1;
             },
             1 => {
-                self.get_class_mut(label).add_value_for_std(converted);
+                self.get_class_mut(label).add_value_for_std(value);
             },
             _ => {}
         }
     }
 
     fn prepare(&mut self) {
-        for class in self.classifications.iter_mut() {
-            class.configure_std();
-        }
-
-        self.is_trained = true;
+        self.configure_std(MIN_STD, 0.0);
     }
 
     fn is_trained(&self) -> bool {
         self.is_trained
     }
 
-    fn likelihood_given_class<Num: ToPrimitive + Copy + FromStr>
-            (&self, sample_feature: &String, label: &dyn Label) 
+    fn likelihood_given_class(&self, sample_feature: f64, label: &dyn Label) 
             -> Result<f64, ModelError> {
         if !self.is_trained() {
             return Err(ModelError::UntrainedError);
         }
-
-        let converted: Num = match sample_feature.parse::<Num>() {
-            Ok(val) => val,
-            Err(_) => panic!("Could not parse record.")
-        };
         
-        Ok(self.get_class(label).pdf(converted))
+        Ok(self.get_class(label).pdf(sample_feature))
     }
 
     fn class_likelihood(&self, label: &dyn Label) -> Result<f64, ModelError> {
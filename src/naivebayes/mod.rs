@@ -2,4 +2,5 @@ pub mod class_label;
 pub mod discrete_feature;
 pub mod gaussian_feature;
 pub mod discrete_classification;
-pub mod gaussian_classification;
\ No newline at end of file
+pub mod gaussian_classification;
+pub mod count_table;